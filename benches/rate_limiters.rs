@@ -0,0 +1,99 @@
+// Rate limiter cost per check for a single key sending continuously, so its
+// log or bucket is always full. Prints the table in the README's Performance
+// section:
+//   cargo bench --bench rate_limiters
+
+#[allow(dead_code, unused_imports)]
+#[path = "../src/middleware/rate_limiter/gcra.rs"]
+mod gcra;
+#[allow(dead_code, unused_imports)]
+#[path = "../src/middleware/rate_limiter/memory.rs"]
+mod memory;
+
+use chrono::{DateTime, Utc};
+use memory::MemoryRateLimiter;
+use std::collections::HashMap;
+use std::hint::black_box;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+// The sliding log GCRA replaced, kept as a baseline: one timestamp per
+// request in the last minute, all rescanned on every check. A std Mutex
+// stands in for the tokio RwLock it used, so only the algorithms differ.
+#[derive(Default)]
+struct SlidingLog {
+    requests: Mutex<HashMap<Uuid, Vec<DateTime<Utc>>>>,
+}
+
+impl SlidingLog {
+    fn check(&self, api_key_id: Uuid, limit: i32) -> bool {
+        let now = Utc::now();
+        let window_start = now - chrono::Duration::minutes(1);
+
+        let mut requests = self.requests.lock().unwrap();
+        let timestamps = requests.entry(api_key_id).or_default();
+
+        timestamps.retain(|&t| t > window_start);
+
+        if timestamps.len() >= limit as usize {
+            return false;
+        }
+
+        timestamps.push(now);
+        true
+    }
+}
+
+// Mean time per call over about a second of calls.
+fn time_per_check<T>(mut check: impl FnMut() -> T) -> Duration {
+    let started = Instant::now();
+    let mut calls = 0;
+
+    while started.elapsed() < Duration::from_secs(1) {
+        for _ in 0..100 {
+            black_box(check());
+        }
+        calls += 100;
+    }
+
+    started.elapsed() / calls
+}
+
+fn format_duration(d: Duration) -> String {
+    match d.as_nanos() {
+        n if n < 1_000 => format!("{} ns", n),
+        n if n < 1_000_000 => format!("{:.1} µs", n as f64 / 1e3),
+        n => format!("{:.1} ms", n as f64 / 1e6),
+    }
+}
+
+fn main() {
+    let key = Uuid::new_v4();
+
+    println!("| `rate_limit_per_minute` | Sliding log (previous) | GCRA |");
+    println!("| ----------------------- | ---------------------- | ---- |");
+
+    for limit in [60, 1_000, 10_000, 100_000] {
+        let log = SlidingLog::default();
+        log.requests
+            .lock()
+            .unwrap()
+            .insert(key, vec![Utc::now(); limit as usize]);
+        let sliding = time_per_check(|| log.check(key, limit));
+
+        let limiter = MemoryRateLimiter::new(1);
+        let gcra = gcra::Gcra::new(limit, limit);
+        for _ in 0..limit {
+            limiter.check(key, gcra);
+        }
+        let gcra = time_per_check(|| limiter.check(key, gcra));
+
+        println!(
+            "| {} | {} | {} |",
+            limit,
+            format_duration(sliding),
+            format_duration(gcra)
+        );
+    }
+}
@@ -0,0 +1,19 @@
+-- Add migration script here
+CREATE TYPE admin_role AS ENUM ('viewer', 'operator', 'owner');
+
+CREATE TABLE admin_tokens (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    token VARCHAR(255) UNIQUE NOT NULL,
+    name VARCHAR(255) NOT NULL,
+    role admin_role NOT NULL,
+    is_active BOOLEAN DEFAULT true NOT NULL,
+    last_used_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ DEFAULT NOW() NOT NULL,
+    updated_at TIMESTAMPTZ DEFAULT NOW() NOT NULL
+);
+
+CREATE INDEX idx_admin_tokens_token ON admin_tokens(token);
+
+CREATE TRIGGER update_admin_tokens_updated_at BEFORE UPDATE
+    ON admin_tokens FOR EACH ROW EXECUTE PROCEDURE
+    update_updated_at_column();
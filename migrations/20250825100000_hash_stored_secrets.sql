@@ -0,0 +1,46 @@
+-- Add migration script here
+CREATE EXTENSION IF NOT EXISTS pgcrypto;
+
+-- Secrets are stored as hex(sha256(salt || secret)) with a random hex salt,
+-- plus the first 11 characters in plaintext for lookup and identification.
+ALTER TABLE api_keys
+ADD COLUMN key_prefix VARCHAR(16),
+ADD COLUMN key_salt VARCHAR(64),
+ADD COLUMN key_hash VARCHAR(64);
+
+UPDATE api_keys SET key_salt = encode(gen_random_bytes(16), 'hex');
+
+UPDATE api_keys
+SET key_prefix = LEFT(key, 11),
+    key_hash = encode(digest(key_salt || key, 'sha256'), 'hex');
+
+ALTER TABLE api_keys
+ALTER COLUMN key_prefix SET NOT NULL,
+ALTER COLUMN key_salt SET NOT NULL,
+ALTER COLUMN key_hash SET NOT NULL;
+
+DROP INDEX idx_api_keys_key;
+ALTER TABLE api_keys DROP COLUMN key;
+
+CREATE INDEX idx_api_keys_key_prefix ON api_keys(key_prefix);
+
+ALTER TABLE admin_tokens
+ADD COLUMN token_prefix VARCHAR(16),
+ADD COLUMN token_salt VARCHAR(64),
+ADD COLUMN token_hash VARCHAR(64);
+
+UPDATE admin_tokens SET token_salt = encode(gen_random_bytes(16), 'hex');
+
+UPDATE admin_tokens
+SET token_prefix = LEFT(token, 11),
+    token_hash = encode(digest(token_salt || token, 'sha256'), 'hex');
+
+ALTER TABLE admin_tokens
+ALTER COLUMN token_prefix SET NOT NULL,
+ALTER COLUMN token_salt SET NOT NULL,
+ALTER COLUMN token_hash SET NOT NULL;
+
+DROP INDEX idx_admin_tokens_token;
+ALTER TABLE admin_tokens DROP COLUMN token;
+
+CREATE INDEX idx_admin_tokens_token_prefix ON admin_tokens(token_prefix);
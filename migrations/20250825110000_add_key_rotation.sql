@@ -0,0 +1,11 @@
+-- Add migration script here
+ALTER TABLE api_keys
+ADD COLUMN last_used_at TIMESTAMPTZ DEFAULT NULL,
+ADD COLUMN rotated_at TIMESTAMPTZ DEFAULT NULL,
+ADD COLUMN previous_key_prefix VARCHAR(16) DEFAULT NULL,
+ADD COLUMN previous_key_salt VARCHAR(64) DEFAULT NULL,
+ADD COLUMN previous_key_hash VARCHAR(64) DEFAULT NULL,
+ADD COLUMN previous_key_expires_at TIMESTAMPTZ DEFAULT NULL,
+ADD COLUMN previous_key_last_used_at TIMESTAMPTZ DEFAULT NULL;
+
+CREATE INDEX idx_api_keys_previous_key_prefix ON api_keys(previous_key_prefix);
@@ -0,0 +1,6 @@
+-- Add migration script here
+ALTER TABLE api_keys
+ADD COLUMN not_before TIMESTAMPTZ DEFAULT NULL,
+ADD COLUMN expires_at TIMESTAMPTZ DEFAULT NULL;
+
+CREATE INDEX idx_api_keys_expires_at ON api_keys(expires_at) WHERE expires_at IS NOT NULL;
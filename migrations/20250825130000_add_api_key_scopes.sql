@@ -0,0 +1,4 @@
+-- Add migration script here
+-- Existing keys keep full access; new keys choose their scopes at creation.
+ALTER TABLE api_keys
+ADD COLUMN scopes TEXT[] NOT NULL DEFAULT ARRAY['readings:write', 'readings:read', 'usage:read'];
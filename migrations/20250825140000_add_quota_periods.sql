@@ -0,0 +1,26 @@
+-- Add migration script here
+CREATE TYPE quota_period AS ENUM ('lifetime', 'daily', 'monthly');
+
+-- Quotas reset every period, counted from quota_anchor (e.g. a monthly key
+-- anchored on the 15th resets on the 15th). Existing keys keep the lifetime
+-- quota they were issued with; only new keys default to monthly.
+ALTER TABLE api_keys
+ADD COLUMN quota_period quota_period NOT NULL DEFAULT 'lifetime',
+ADD COLUMN quota_anchor TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+ADD COLUMN period_started_at TIMESTAMPTZ NOT NULL DEFAULT NOW();
+
+UPDATE api_keys SET quota_anchor = created_at, period_started_at = created_at;
+
+ALTER TABLE api_keys ALTER COLUMN quota_period SET DEFAULT 'monthly';
+
+CREATE TABLE quota_period_usage (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    api_key_id UUID NOT NULL REFERENCES api_keys(id) ON DELETE CASCADE,
+    period_start TIMESTAMPTZ NOT NULL,
+    period_end TIMESTAMPTZ NOT NULL,
+    usage_count INTEGER NOT NULL,
+    quota_limit INTEGER,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_quota_period_usage_key_start ON quota_period_usage(api_key_id, period_start);
@@ -0,0 +1,5 @@
+-- Add migration script here
+-- NULL keeps the previous behaviour of allowing a full minute's worth of
+-- requests at once.
+ALTER TABLE api_keys
+ADD COLUMN rate_limit_burst INTEGER DEFAULT NULL;
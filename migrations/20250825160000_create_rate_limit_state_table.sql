@@ -0,0 +1,6 @@
+-- Add migration script here
+-- Shared GCRA state for RATE_LIMIT_BACKEND=postgres
+CREATE TABLE rate_limit_state (
+    api_key_id UUID PRIMARY KEY REFERENCES api_keys(id) ON DELETE CASCADE,
+    tat TIMESTAMPTZ NOT NULL
+);
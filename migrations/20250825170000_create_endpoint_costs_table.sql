@@ -0,0 +1,27 @@
+-- Add migration script here
+CREATE TABLE endpoint_costs (
+    method VARCHAR(10) NOT NULL,
+    route VARCHAR(255) NOT NULL,
+    base_cost INTEGER NOT NULL DEFAULT 1 CHECK (base_cost >= 0),
+    per_item_cost INTEGER NOT NULL DEFAULT 0 CHECK (per_item_cost >= 0),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (method, route)
+);
+
+INSERT INTO endpoint_costs (method, route) VALUES
+    ('POST', '/readings'),
+    ('GET', '/readings'),
+    ('GET', '/usage');
+
+CREATE TRIGGER update_endpoint_costs_updated_at BEFORE UPDATE
+    ON endpoint_costs FOR EACH ROW EXECUTE PROCEDURE
+    update_updated_at_column();
+
+-- Units charged for each logged request. Everything logged so far was
+-- charged exactly one unit.
+ALTER TABLE requests
+ADD COLUMN cost INTEGER NOT NULL DEFAULT 1;
+
+ALTER TABLE requests
+ALTER COLUMN cost SET DEFAULT 0;
@@ -0,0 +1,29 @@
+-- Add migration script here
+-- Optional per-endpoint limits for a key, enforced on top of the key-wide
+-- rate_limit_per_minute and quota_limit.
+CREATE TABLE key_limits (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    api_key_id UUID NOT NULL REFERENCES api_keys(id) ON DELETE CASCADE,
+    method VARCHAR(10) NOT NULL,
+    route VARCHAR(255) NOT NULL,
+    rate_limit_per_minute INTEGER DEFAULT NULL,
+    rate_limit_burst INTEGER DEFAULT NULL,
+    quota_limit INTEGER DEFAULT NULL,
+    -- Units used in the key's quota period that started at period_started_at.
+    usage_count INTEGER NOT NULL DEFAULT 0,
+    period_started_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (api_key_id, method, route)
+);
+
+CREATE TRIGGER update_key_limits_updated_at BEFORE UPDATE
+    ON key_limits FOR EACH ROW EXECUTE PROCEDURE
+    update_updated_at_column();
+
+-- Endpoint limits get their own rate limit buckets, keyed by key_limits.id.
+ALTER TABLE rate_limit_state
+DROP CONSTRAINT rate_limit_state_api_key_id_fkey;
+
+ALTER TABLE rate_limit_state
+RENAME COLUMN api_key_id TO bucket_id;
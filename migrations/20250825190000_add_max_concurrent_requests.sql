@@ -0,0 +1,4 @@
+-- Add migration script here
+-- NULL uses the server's DEFAULT_MAX_CONCURRENT_REQUESTS.
+ALTER TABLE api_keys
+ADD COLUMN max_concurrent_requests INTEGER DEFAULT NULL;
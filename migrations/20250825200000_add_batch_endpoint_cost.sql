@@ -0,0 +1,5 @@
+-- Add migration script here
+-- A batch is charged per stored reading, so it costs the same as sending
+-- the readings one at a time.
+INSERT INTO endpoint_costs (method, route, base_cost, per_item_cost)
+VALUES ('POST', '/readings/batch', 0, 1);
@@ -0,0 +1,13 @@
+-- Add migration script here
+-- When the sample was taken, as reported by the device. created_at stays the
+-- ingestion time. Readings stored so far had no client time.
+ALTER TABLE readings
+ADD COLUMN recorded_at TIMESTAMPTZ;
+
+UPDATE readings SET recorded_at = created_at;
+
+ALTER TABLE readings
+ALTER COLUMN recorded_at SET NOT NULL,
+ALTER COLUMN recorded_at SET DEFAULT NOW();
+
+CREATE INDEX idx_readings_api_key_recorded_at ON readings(api_key_id, recorded_at DESC);
@@ -0,0 +1,7 @@
+-- Add migration script here
+-- GET /readings pages through a key's readings by (recorded_at, id), either
+-- across all sensors or for a single one.
+DROP INDEX IF EXISTS idx_readings_api_key_recorded_at;
+
+CREATE INDEX idx_readings_api_key_recorded_at_id ON readings(api_key_id, recorded_at, id);
+CREATE INDEX idx_readings_api_key_sensor_recorded_at_id ON readings(api_key_id, sensor_id, recorded_at, id);
@@ -0,0 +1,3 @@
+-- Add migration script here
+INSERT INTO endpoint_costs (method, route)
+VALUES ('GET', '/readings/aggregate');
@@ -0,0 +1,29 @@
+-- Add migration script here
+-- Sensors registered under an API key. sensor_id is the identifier devices
+-- send with their readings.
+CREATE TABLE sensors (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    api_key_id UUID NOT NULL REFERENCES api_keys(id) ON DELETE CASCADE,
+    sensor_id VARCHAR(100) NOT NULL,
+    name VARCHAR(255) NOT NULL,
+    location VARCHAR(255) DEFAULT NULL,
+    tags TEXT[] NOT NULL DEFAULT '{}',
+    unit VARCHAR(50) DEFAULT NULL,
+    min_value DOUBLE PRECISION DEFAULT NULL,
+    max_value DOUBLE PRECISION DEFAULT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (api_key_id, sensor_id),
+    CONSTRAINT sensors_value_range CHECK (min_value IS NULL OR max_value IS NULL OR min_value <= max_value)
+);
+
+CREATE TRIGGER update_sensors_updated_at BEFORE UPDATE
+    ON sensors FOR EACH ROW EXECUTE PROCEDURE
+    update_updated_at_column();
+
+INSERT INTO endpoint_costs (method, route) VALUES
+    ('GET', '/sensors'),
+    ('POST', '/sensors'),
+    ('GET', '/sensors/{sensor_id}'),
+    ('PATCH', '/sensors/{sensor_id}'),
+    ('DELETE', '/sensors/{sensor_id}');
@@ -0,0 +1,38 @@
+-- Add migration script here
+-- readings.unit now holds the canonical symbol for known units (°C for "C",
+-- "celsius", ...); raw_unit keeps the unit exactly as the device sent it.
+ALTER TABLE readings
+ADD COLUMN raw_unit VARCHAR(255);
+
+UPDATE readings SET raw_unit = unit;
+
+-- Existing readings are moved to their canonical symbol, matched the way
+-- units::Unit::lookup matches: case-insensitively, ignoring surrounding
+-- spaces. Keep this list in sync with the catalog in src/units.rs.
+UPDATE readings
+SET unit = c.symbol
+FROM (VALUES
+    ('°C', ARRAY['°c', 'c', 'degc', 'deg c', 'celsius']),
+    ('°F', ARRAY['°f', 'f', 'degf', 'deg f', 'fahrenheit']),
+    ('K', ARRAY['k', 'kelvin']),
+    ('Pa', ARRAY['pa', 'pascal', 'pascals']),
+    ('hPa', ARRAY['hpa', 'hectopascal', 'hectopascals', 'mbar', 'millibar', 'millibars']),
+    ('kPa', ARRAY['kpa', 'kilopascal', 'kilopascals']),
+    ('bar', ARRAY['bar', 'bars']),
+    ('psi', ARRAY['psi']),
+    ('atm', ARRAY['atm', 'atmosphere', 'atmospheres']),
+    ('mmHg', ARRAY['mmhg']),
+    ('mm', ARRAY['mm', 'millimeter', 'millimeters', 'millimetre', 'millimetres']),
+    ('cm', ARRAY['cm', 'centimeter', 'centimeters', 'centimetre', 'centimetres']),
+    ('m', ARRAY['m', 'meter', 'meters', 'metre', 'metres']),
+    ('km', ARRAY['km', 'kilometer', 'kilometers', 'kilometre', 'kilometres']),
+    ('in', ARRAY['in', 'inch', 'inches']),
+    ('ft', ARRAY['ft', 'foot', 'feet']),
+    ('%', ARRAY['%', 'percent', 'pct']),
+    ('ppm', ARRAY['ppm'])
+) AS c(symbol, names)
+WHERE lower(btrim(readings.unit)) = ANY(c.names)
+    AND readings.unit <> c.symbol;
+
+ALTER TABLE readings
+ALTER COLUMN raw_unit SET NOT NULL;
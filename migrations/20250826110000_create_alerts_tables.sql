@@ -0,0 +1,64 @@
+-- Add migration script here
+CREATE TYPE alert_comparison AS ENUM ('gt', 'gte', 'lt', 'lte');
+CREATE TYPE alert_status AS ENUM ('pending', 'firing', 'resolved');
+
+-- Threshold rules evaluated against incoming readings. A rule without
+-- sensor_id or sensor_tag applies to every sensor of the key. With a unit,
+-- readings are converted to it before comparing and skipped if they cannot
+-- be.
+CREATE TABLE alert_rules (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    api_key_id UUID NOT NULL REFERENCES api_keys(id) ON DELETE CASCADE,
+    name VARCHAR(255) NOT NULL,
+    sensor_id VARCHAR(100) DEFAULT NULL,
+    sensor_tag VARCHAR(50) DEFAULT NULL,
+    comparison alert_comparison NOT NULL,
+    threshold DOUBLE PRECISION NOT NULL,
+    unit VARCHAR(50) DEFAULT NULL,
+    duration_seconds INTEGER NOT NULL DEFAULT 0 CHECK (duration_seconds >= 0),
+    hysteresis DOUBLE PRECISION NOT NULL DEFAULT 0 CHECK (hysteresis >= 0),
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_alert_rules_api_key_id ON alert_rules(api_key_id);
+
+CREATE TRIGGER update_alert_rules_updated_at BEFORE UPDATE
+    ON alert_rules FOR EACH ROW EXECUTE PROCEDURE
+    update_updated_at_column();
+
+-- One row per breach of a rule by a sensor. A breach is pending until it has
+-- lasted duration_seconds, then firing until the value recovers past the
+-- threshold by the rule's hysteresis.
+CREATE TABLE alerts (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    rule_id UUID NOT NULL REFERENCES alert_rules(id) ON DELETE CASCADE,
+    api_key_id UUID NOT NULL REFERENCES api_keys(id) ON DELETE CASCADE,
+    sensor_id VARCHAR(100) NOT NULL,
+    status alert_status NOT NULL,
+    started_at TIMESTAMPTZ NOT NULL,
+    fired_at TIMESTAMPTZ DEFAULT NULL,
+    resolved_at TIMESTAMPTZ DEFAULT NULL,
+    trigger_value DOUBLE PRECISION NOT NULL,
+    last_value DOUBLE PRECISION NOT NULL,
+    last_reading_at TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- At most one open alert per rule and sensor.
+CREATE UNIQUE INDEX idx_alerts_open ON alerts(rule_id, sensor_id) WHERE status <> 'resolved';
+CREATE INDEX idx_alerts_api_key_started ON alerts(api_key_id, started_at DESC);
+
+CREATE TRIGGER update_alerts_updated_at BEFORE UPDATE
+    ON alerts FOR EACH ROW EXECUTE PROCEDURE
+    update_updated_at_column();
+
+INSERT INTO endpoint_costs (method, route) VALUES
+    ('GET', '/alerts'),
+    ('GET', '/alerts/rules'),
+    ('POST', '/alerts/rules'),
+    ('GET', '/alerts/rules/{rule_id}'),
+    ('PATCH', '/alerts/rules/{rule_id}'),
+    ('DELETE', '/alerts/rules/{rule_id}');
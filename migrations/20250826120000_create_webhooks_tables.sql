@@ -0,0 +1,55 @@
+-- Add migration script here
+-- Outbound webhook subscriptions. The secret signs each payload, so unlike
+-- key and token secrets it has to be kept in plaintext.
+CREATE TABLE webhooks (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    url TEXT NOT NULL,
+    secret VARCHAR(100) NOT NULL,
+    events TEXT[] NOT NULL,
+    api_key_id UUID DEFAULT NULL REFERENCES api_keys(id) ON DELETE CASCADE,
+    description VARCHAR(255) DEFAULT NULL,
+    is_active BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TRIGGER update_webhooks_updated_at BEFORE UPDATE
+    ON webhooks FOR EACH ROW EXECUTE PROCEDURE
+    update_updated_at_column();
+
+-- Events are only stored when some webhook subscribes to them. The payload is
+-- kept as sent, so replays deliver the same bytes. api_key_id has no foreign
+-- key so key.deleted events outlive their key. dedupe_key stops the same
+-- threshold crossing from being announced twice.
+CREATE TABLE webhook_events (
+    id UUID PRIMARY KEY,
+    event_type VARCHAR(50) NOT NULL,
+    api_key_id UUID DEFAULT NULL,
+    payload TEXT NOT NULL,
+    dedupe_key VARCHAR(255) DEFAULT NULL UNIQUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TYPE webhook_delivery_status AS ENUM ('pending', 'delivered', 'failed');
+
+CREATE TABLE webhook_deliveries (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    webhook_id UUID NOT NULL REFERENCES webhooks(id) ON DELETE CASCADE,
+    event_id UUID NOT NULL REFERENCES webhook_events(id) ON DELETE CASCADE,
+    status webhook_delivery_status NOT NULL DEFAULT 'pending',
+    attempts INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_attempt_at TIMESTAMPTZ DEFAULT NULL,
+    response_status SMALLINT DEFAULT NULL,
+    last_error TEXT DEFAULT NULL,
+    delivered_at TIMESTAMPTZ DEFAULT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_webhook_deliveries_due ON webhook_deliveries(next_attempt_at) WHERE status = 'pending';
+CREATE INDEX idx_webhook_deliveries_webhook ON webhook_deliveries(webhook_id, created_at DESC);
+
+CREATE TRIGGER update_webhook_deliveries_updated_at BEFORE UPDATE
+    ON webhook_deliveries FOR EACH ROW EXECUTE PROCEDURE
+    update_updated_at_column();
@@ -0,0 +1,5 @@
+-- Add migration script here
+-- A stream is charged once, when it is opened.
+INSERT INTO endpoint_costs (method, route) VALUES
+    ('GET', '/readings/stream'),
+    ('GET', '/readings/ws');
@@ -0,0 +1,435 @@
+use crate::db::DbPool;
+use crate::models::{Alert, AlertRule, AlertStatus, Reading};
+use crate::units::Unit;
+use chrono::{DateTime, Duration, Utc};
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+// An alert that started firing or resolved while evaluating readings.
+#[derive(Debug)]
+pub enum AlertEvent {
+    Fired(Alert),
+    Resolved(Alert),
+}
+
+// Evaluates the key's enabled alert rules against newly stored readings, in
+// recorded_at order, and persists the resulting alert state.
+pub async fn evaluate(
+    db: &DbPool,
+    api_key_id: Uuid,
+    readings: &[Reading],
+) -> Result<Vec<AlertEvent>, sqlx::Error> {
+    if readings.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut sensor_ids: Vec<&str> = readings.iter().map(|r| r.sensor_id.as_str()).collect();
+    sensor_ids.sort_unstable();
+    sensor_ids.dedup();
+
+    let mut tx = db.begin().await?;
+
+    // Locking the rules serialises concurrent evaluations that could
+    // otherwise open the same alert twice.
+    let rules = sqlx::query_as::<_, AlertRule>(
+        r#"
+        SELECT * FROM alert_rules
+        WHERE api_key_id = $1 AND enabled AND (sensor_id IS NULL OR sensor_id = ANY($2))
+        ORDER BY id
+        FOR UPDATE
+        "#,
+    )
+    .bind(api_key_id)
+    .bind(&sensor_ids)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    if rules.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let tags: HashMap<String, Vec<String>> = if rules.iter().any(|r| r.sensor_tag.is_some()) {
+        sqlx::query_as::<_, (String, Vec<String>)>(
+            "SELECT sensor_id, tags FROM sensors WHERE api_key_id = $1 AND sensor_id = ANY($2)",
+        )
+        .bind(api_key_id)
+        .bind(&sensor_ids)
+        .fetch_all(&mut *tx)
+        .await?
+        .into_iter()
+        .collect()
+    } else {
+        HashMap::new()
+    };
+
+    let rule_ids: Vec<Uuid> = rules.iter().map(|r| r.id).collect();
+    let mut open: HashMap<(Uuid, String), Alert> = sqlx::query_as::<_, Alert>(
+        r#"
+        SELECT * FROM alerts
+        WHERE rule_id = ANY($1) AND sensor_id = ANY($2) AND status <> 'resolved'
+        "#,
+    )
+    .bind(&rule_ids)
+    .bind(&sensor_ids)
+    .fetch_all(&mut *tx)
+    .await?
+    .into_iter()
+    .map(|a| ((a.rule_id, a.sensor_id.clone()), a))
+    .collect();
+
+    // Alerts follow each sensor's newest reading. A reading recorded before
+    // one already stored for its sensor, such as a device flushing its
+    // buffer, is kept but not evaluated: it could otherwise open an alert
+    // that started in the past and fires as soon as the next reading arrives.
+    let batch_ids: Vec<Uuid> = readings.iter().map(|r| r.id).collect();
+    let newest: HashMap<String, DateTime<Utc>> = sqlx::query_as::<_, (String, DateTime<Utc>)>(
+        r#"
+        SELECT s.sensor_id, r.recorded_at
+        FROM UNNEST($2::text[]) AS s(sensor_id)
+        CROSS JOIN LATERAL (
+            SELECT recorded_at FROM readings
+            WHERE api_key_id = $1 AND sensor_id = s.sensor_id AND id <> ALL($3)
+            ORDER BY recorded_at DESC
+            LIMIT 1
+        ) r
+        "#,
+    )
+    .bind(api_key_id)
+    .bind(&sensor_ids)
+    .bind(&batch_ids)
+    .fetch_all(&mut *tx)
+    .await?
+    .into_iter()
+    .collect();
+
+    let mut ordered: Vec<&Reading> = readings
+        .iter()
+        .filter(|r| newest.get(&r.sensor_id).is_none_or(|t| r.recorded_at >= *t))
+        .collect();
+    ordered.sort_by_key(|r| (r.recorded_at, r.id));
+
+    let mut touched: HashSet<Uuid> = HashSet::new();
+    let mut cancelled: Vec<Uuid> = Vec::new();
+    let mut resolved: Vec<Alert> = Vec::new();
+    let mut fired: HashSet<Uuid> = HashSet::new();
+
+    for reading in ordered {
+        for rule in rules.iter().filter(|rule| applies(rule, reading, &tags)) {
+            let Some(value) = rule_value(rule, reading) else {
+                continue;
+            };
+            let breached = rule.comparison.matches(value, rule.threshold);
+            let key = (rule.id, reading.sensor_id.clone());
+
+            match open.get_mut(&key) {
+                // Late readings cannot change what newer ones already decided.
+                Some(alert) if reading.recorded_at < alert.last_reading_at => {}
+                Some(alert) if alert.status == AlertStatus::Pending && !breached => {
+                    cancelled.push(alert.id);
+                    open.remove(&key);
+                }
+                Some(alert) if alert.status == AlertStatus::Pending => {
+                    alert.last_value = value;
+                    alert.last_reading_at = reading.recorded_at;
+                    if reading.recorded_at - alert.started_at
+                        >= Duration::seconds(i64::from(rule.duration_seconds))
+                    {
+                        alert.status = AlertStatus::Firing;
+                        alert.fired_at = Some(reading.recorded_at);
+                        fired.insert(alert.id);
+                    }
+                    touched.insert(alert.id);
+                }
+                Some(alert) => {
+                    alert.last_value = value;
+                    alert.last_reading_at = reading.recorded_at;
+                    touched.insert(alert.id);
+                    if rule
+                        .comparison
+                        .recovered(value, rule.threshold, rule.hysteresis)
+                    {
+                        alert.status = AlertStatus::Resolved;
+                        alert.resolved_at = Some(reading.recorded_at);
+                        resolved.extend(open.remove(&key));
+                    }
+                }
+                None if breached => {
+                    let firing = rule.duration_seconds == 0;
+                    let alert = Alert {
+                        id: Uuid::new_v4(),
+                        rule_id: rule.id,
+                        api_key_id,
+                        sensor_id: reading.sensor_id.clone(),
+                        status: if firing {
+                            AlertStatus::Firing
+                        } else {
+                            AlertStatus::Pending
+                        },
+                        started_at: reading.recorded_at,
+                        fired_at: firing.then_some(reading.recorded_at),
+                        resolved_at: None,
+                        trigger_value: value,
+                        last_value: value,
+                        last_reading_at: reading.recorded_at,
+                        created_at: Utc::now(),
+                        updated_at: Utc::now(),
+                    };
+                    if firing {
+                        fired.insert(alert.id);
+                    }
+                    touched.insert(alert.id);
+                    open.insert(key, alert);
+                }
+                None => {}
+            }
+        }
+    }
+
+    sqlx::query("DELETE FROM alerts WHERE id = ANY($1)")
+        .bind(&cancelled)
+        .execute(&mut *tx)
+        .await?;
+
+    // Resolved alerts are written first so a new alert for the same rule and
+    // sensor does not collide with them on idx_alerts_open.
+    let mut events = Vec::new();
+    for alert in resolved.iter().chain(open.values()) {
+        if !touched.contains(&alert.id) {
+            continue;
+        }
+
+        let saved = sqlx::query_as::<_, Alert>(
+            r#"
+            INSERT INTO alerts (id, rule_id, api_key_id, sensor_id, status, started_at,
+                                fired_at, resolved_at, trigger_value, last_value, last_reading_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+            ON CONFLICT (id) DO UPDATE
+            SET status = EXCLUDED.status,
+                fired_at = EXCLUDED.fired_at,
+                resolved_at = EXCLUDED.resolved_at,
+                last_value = EXCLUDED.last_value,
+                last_reading_at = EXCLUDED.last_reading_at
+            RETURNING *
+            "#,
+        )
+        .bind(alert.id)
+        .bind(alert.rule_id)
+        .bind(alert.api_key_id)
+        .bind(&alert.sensor_id)
+        .bind(alert.status)
+        .bind(alert.started_at)
+        .bind(alert.fired_at)
+        .bind(alert.resolved_at)
+        .bind(alert.trigger_value)
+        .bind(alert.last_value)
+        .bind(alert.last_reading_at)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        if fired.contains(&saved.id) {
+            events.push(AlertEvent::Fired(saved.clone()));
+        }
+        if saved.status == AlertStatus::Resolved {
+            events.push(AlertEvent::Resolved(saved));
+        }
+    }
+
+    tx.commit().await?;
+
+    Ok(events)
+}
+
+fn applies(rule: &AlertRule, reading: &Reading, tags: &HashMap<String, Vec<String>>) -> bool {
+    rule.sensor_id
+        .as_ref()
+        .is_none_or(|id| *id == reading.sensor_id)
+        && rule.sensor_tag.as_ref().is_none_or(|tag| {
+            tags.get(&reading.sensor_id)
+                .is_some_and(|tags| tags.contains(tag))
+        })
+}
+
+// The reading's value in the rule's unit, or None if it cannot be converted.
+// Both units are stored normalised, so equal units need no lookup.
+fn rule_value(rule: &AlertRule, reading: &Reading) -> Option<f64> {
+    match &rule.unit {
+        None => Some(reading.value),
+        Some(unit) if *unit == reading.unit => Some(reading.value),
+        Some(unit) => Unit::lookup(&reading.unit)?.convert(reading.value, Unit::lookup(unit)?),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::create_api_key;
+    use sqlx::PgPool;
+    use std::sync::Arc;
+
+    struct Fixture {
+        db: DbPool,
+        api_key_id: Uuid,
+        start: DateTime<Utc>,
+    }
+
+    impl Fixture {
+        // A key with one rule on sensor s1: value > 10.
+        async fn new(pool: PgPool, duration_seconds: i32, hysteresis: f64) -> Self {
+            let db = Arc::new(pool);
+            let (api_key_id, _) = create_api_key(&db).await;
+            sqlx::query(
+                r#"
+                INSERT INTO alert_rules (api_key_id, name, sensor_id, comparison, threshold,
+                                         duration_seconds, hysteresis)
+                VALUES ($1, 'too high', 's1', 'gt', 10, $2, $3)
+                "#,
+            )
+            .bind(api_key_id)
+            .bind(duration_seconds)
+            .bind(hysteresis)
+            .execute(&*db)
+            .await
+            .unwrap();
+
+            Self {
+                db,
+                api_key_id,
+                // Whole seconds, so timestamps survive Postgres' microseconds.
+                start: DateTime::from_timestamp(Utc::now().timestamp() - 3600, 0).unwrap(),
+            }
+        }
+
+        // Stores readings of s1, `(seconds after start, value)`, and
+        // evaluates them as one batch, as the handlers do.
+        async fn submit(&self, readings: &[(i64, f64)]) -> Vec<AlertEvent> {
+            let mut stored = Vec::new();
+            for (seconds, value) in readings {
+                let reading = sqlx::query_as::<_, Reading>(
+                    r#"
+                    INSERT INTO readings (id, api_key_id, sensor_id, value, unit, raw_unit, recorded_at)
+                    VALUES ($1, $2, 's1', $3, '°C', 'C', $4)
+                    RETURNING *
+                    "#,
+                )
+                .bind(Uuid::new_v4())
+                .bind(self.api_key_id)
+                .bind(value)
+                .bind(self.start + Duration::seconds(*seconds))
+                .fetch_one(&*self.db)
+                .await
+                .unwrap();
+                stored.push(reading);
+            }
+
+            evaluate(&self.db, self.api_key_id, &stored).await.unwrap()
+        }
+
+        async fn alerts(&self) -> Vec<Alert> {
+            sqlx::query_as("SELECT * FROM alerts WHERE api_key_id = $1 ORDER BY started_at")
+                .bind(self.api_key_id)
+                .fetch_all(&*self.db)
+                .await
+                .unwrap()
+        }
+    }
+
+    fn kinds(events: &[AlertEvent]) -> Vec<&'static str> {
+        events
+            .iter()
+            .map(|e| match e {
+                AlertEvent::Fired(_) => "fired",
+                AlertEvent::Resolved(_) => "resolved",
+            })
+            .collect()
+    }
+
+    #[sqlx::test]
+    async fn pending_alert_fires_once_the_breach_lasts_long_enough(pool: PgPool) {
+        let f = Fixture::new(pool, 60, 0.0).await;
+
+        assert!(f.submit(&[(0, 11.0)]).await.is_empty());
+        assert!(f.submit(&[(30, 12.0)]).await.is_empty());
+        assert_eq!(f.alerts().await[0].status, AlertStatus::Pending);
+
+        assert_eq!(kinds(&f.submit(&[(60, 12.0)]).await), ["fired"]);
+        let alert = &f.alerts().await[0];
+        assert_eq!(alert.status, AlertStatus::Firing);
+        assert_eq!(alert.started_at, f.start);
+        assert_eq!(alert.fired_at, Some(f.start + Duration::seconds(60)));
+        assert_eq!(alert.trigger_value, 11.0);
+    }
+
+    #[sqlx::test]
+    async fn pending_alert_is_dropped_when_the_breach_ends_early(pool: PgPool) {
+        let f = Fixture::new(pool, 60, 0.0).await;
+
+        f.submit(&[(0, 11.0)]).await;
+        assert!(f.submit(&[(30, 5.0)]).await.is_empty());
+
+        assert!(f.alerts().await.is_empty());
+    }
+
+    #[sqlx::test]
+    async fn firing_alert_resolves_only_past_the_hysteresis(pool: PgPool) {
+        let f = Fixture::new(pool, 0, 2.0).await;
+
+        assert_eq!(kinds(&f.submit(&[(0, 15.0)]).await), ["fired"]);
+        assert!(f.submit(&[(10, 9.0)]).await.is_empty());
+        assert_eq!(f.alerts().await[0].status, AlertStatus::Firing);
+
+        assert_eq!(kinds(&f.submit(&[(20, 8.0)]).await), ["resolved"]);
+        let alert = &f.alerts().await[0];
+        assert_eq!(alert.status, AlertStatus::Resolved);
+        assert_eq!(alert.resolved_at, Some(f.start + Duration::seconds(20)));
+    }
+
+    #[sqlx::test]
+    async fn late_readings_do_not_change_an_open_alert(pool: PgPool) {
+        let f = Fixture::new(pool, 0, 0.0).await;
+
+        f.submit(&[(60, 15.0)]).await;
+        assert!(f.submit(&[(0, 1.0)]).await.is_empty());
+
+        assert_eq!(f.alerts().await[0].status, AlertStatus::Firing);
+    }
+
+    #[sqlx::test]
+    async fn late_readings_do_not_open_an_alert(pool: PgPool) {
+        let f = Fixture::new(pool, 60, 0.0).await;
+
+        f.submit(&[(600, 5.0)]).await;
+        // Breaching, but older than the stored reading.
+        assert!(f.submit(&[(0, 20.0)]).await.is_empty());
+        assert!(f.alerts().await.is_empty());
+
+        // Had the late reading opened the alert, this would fire at once.
+        assert!(f.submit(&[(601, 20.0)]).await.is_empty());
+        let alerts = f.alerts().await;
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].status, AlertStatus::Pending);
+        assert_eq!(alerts[0].started_at, f.start + Duration::seconds(601));
+    }
+
+    #[sqlx::test]
+    async fn batch_can_resolve_and_reopen_for_the_same_sensor(pool: PgPool) {
+        let f = Fixture::new(pool, 0, 0.0).await;
+
+        let events = f.submit(&[(0, 15.0), (10, 5.0), (20, 15.0)]).await;
+
+        assert_eq!(kinds(&events), ["fired", "resolved", "fired"]);
+        let statuses: Vec<AlertStatus> = f.alerts().await.iter().map(|a| a.status).collect();
+        assert_eq!(statuses, [AlertStatus::Resolved, AlertStatus::Firing]);
+    }
+
+    #[sqlx::test]
+    async fn stored_alert_can_resolve_and_reopen_in_one_batch(pool: PgPool) {
+        let f = Fixture::new(pool, 0, 0.0).await;
+        f.submit(&[(0, 15.0)]).await;
+
+        let events = f.submit(&[(10, 5.0), (20, 15.0)]).await;
+
+        assert_eq!(kinds(&events), ["resolved", "fired"]);
+        let statuses: Vec<AlertStatus> = f.alerts().await.iter().map(|a| a.status).collect();
+        assert_eq!(statuses, [AlertStatus::Resolved, AlertStatus::Firing]);
+    }
+}
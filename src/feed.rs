@@ -0,0 +1,146 @@
+use crate::db::DbPool;
+use crate::middleware::concurrency::{ConcurrencyLimiter, ConcurrencyPermit};
+use crate::models::Reading;
+use std::sync::Arc;
+use tokio::sync::broadcast::{self, error::RecvError};
+use uuid::Uuid;
+
+// Postgres channel readings are announced on when instances share the feed.
+pub const NOTIFY_CHANNEL: &str = "readings";
+// Readings a slow subscriber can fall behind by before it skips ahead.
+const CHANNEL_CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeedBackend {
+    Memory,
+    Postgres,
+}
+
+impl FeedBackend {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "memory" => Some(FeedBackend::Memory),
+            "postgres" => Some(FeedBackend::Postgres),
+            _ => None,
+        }
+    }
+}
+
+// Fans newly stored readings out to open streams. With the memory backend
+// only streams on this instance see a reading; with the postgres backend it
+// goes through NOTIFY, and every instance's listener passes it on to its own
+// streams.
+#[derive(Clone)]
+pub struct ReadingFeed {
+    backend: FeedBackend,
+    db: DbPool,
+    sender: broadcast::Sender<Arc<Reading>>,
+    streams: ConcurrencyLimiter,
+    max_streams_per_key: i32,
+}
+
+impl ReadingFeed {
+    pub fn new(backend: FeedBackend, db: DbPool, max_streams_per_key: i32) -> Self {
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+
+        Self {
+            backend,
+            db,
+            sender,
+            streams: ConcurrencyLimiter::new(),
+            max_streams_per_key,
+        }
+    }
+
+    // The readings are already stored, so a failed NOTIFY is logged rather
+    // than failing the request.
+    pub async fn publish(&self, readings: &[Reading]) {
+        match self.backend {
+            FeedBackend::Memory => {
+                if self.sender.receiver_count() == 0 {
+                    return;
+                }
+                for reading in readings {
+                    self.broadcast(Arc::new(reading.clone()));
+                }
+            }
+            FeedBackend::Postgres => {
+                let payloads = readings
+                    .iter()
+                    .filter_map(|r| serde_json::to_string(r).ok())
+                    .collect::<Vec<_>>();
+
+                let result =
+                    sqlx::query("SELECT pg_notify($1, payload) FROM UNNEST($2::text[]) AS payload")
+                        .bind(NOTIFY_CHANNEL)
+                        .bind(&payloads)
+                        .execute(&*self.db)
+                        .await;
+
+                if let Err(e) = result {
+                    tracing::error!("Failed to announce readings: {:?}", e);
+                }
+            }
+        }
+    }
+
+    // Hands a reading to this instance's streams.
+    pub fn broadcast(&self, reading: Arc<Reading>) {
+        // Fails only when nobody is listening.
+        let _ = self.sender.send(reading);
+    }
+
+    // None when the key already has as many streams open as it may.
+    pub fn subscribe(&self, api_key_id: Uuid, sensor_id: Option<String>) -> Option<Subscription> {
+        let permit = self
+            .streams
+            .try_acquire(api_key_id, self.max_streams_per_key)?;
+
+        Some(Subscription {
+            receiver: self.sender.subscribe(),
+            api_key_id,
+            sensor_id,
+            _permit: permit,
+        })
+    }
+
+    pub fn max_streams_per_key(&self) -> i32 {
+        self.max_streams_per_key
+    }
+}
+
+pub enum FeedEvent {
+    Reading(Arc<Reading>),
+    // The subscriber fell too far behind and some readings were skipped.
+    Lagged,
+}
+
+// One open stream: the key's readings, optionally for a single sensor.
+pub struct Subscription {
+    receiver: broadcast::Receiver<Arc<Reading>>,
+    api_key_id: Uuid,
+    sensor_id: Option<String>,
+    // Counts against the key's open streams until the stream is dropped.
+    _permit: ConcurrencyPermit,
+}
+
+impl Subscription {
+    pub async fn next(&mut self) -> Option<FeedEvent> {
+        loop {
+            match self.receiver.recv().await {
+                Ok(reading) if self.wants(&reading) => return Some(FeedEvent::Reading(reading)),
+                Ok(_) => {}
+                Err(RecvError::Lagged(_)) => return Some(FeedEvent::Lagged),
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+
+    fn wants(&self, reading: &Reading) -> bool {
+        reading.api_key_id == self.api_key_id
+            && self
+                .sensor_id
+                .as_ref()
+                .is_none_or(|sensor_id| *sensor_id == reading.sensor_id)
+    }
+}
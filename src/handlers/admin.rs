@@ -1,51 +1,111 @@
 use crate::db::DbPool;
 use crate::models::{
     ApiKey, ApiKeyInfo, ApiKeyListResponse, CreateApiKeyRequest, CreateApiKeyResponse,
+    RotateApiKeyParams, RotateApiKeyResponse, Scope, UpdateApiKeyRequest, WebhookEvent,
 };
-use rand::Rng;
+use crate::secrets;
+use crate::webhooks::Webhooks;
+use chrono::Utc;
 use std::convert::Infallible;
 use uuid::Uuid;
 use warp::{Reply, http::StatusCode, reply};
 
 fn generate_api_key() -> String {
-    const CHARSET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
-    const KEY_LEN: usize = 32;
-    let mut rng = rand::rng();
-
-    let key: String = (0..KEY_LEN)
-        .map(|_| {
-            let idx = rng.random_range(0..CHARSET.len());
-            CHARSET[idx] as char
-        })
-        .collect();
-
-    format!("sk_{}", key)
+    secrets::generate_secret("sk")
 }
 
 pub async fn create_api_key(
     body: CreateApiKeyRequest,
     db: DbPool,
+    webhooks: Webhooks,
 ) -> Result<impl Reply, Infallible> {
+    if let (Some(not_before), Some(expires_at)) = (body.not_before, body.expires_at)
+        && expires_at <= not_before
+    {
+        return Ok(reply::with_status(
+            reply::json(&serde_json::json!({
+                "error": "expires_at must be later than not_before"
+            })),
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+
+    if let Some(anchor) = body.quota_anchor
+        && anchor > Utc::now()
+    {
+        return Ok(reply::with_status(
+            reply::json(&serde_json::json!({
+                "error": "quota_anchor cannot be in the future"
+            })),
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+
+    let scopes: Vec<String> = match body.scopes {
+        Some(requested) => {
+            if let Some(unknown) = requested.iter().find(|s| Scope::parse(s).is_none()) {
+                return Ok(reply::with_status(
+                    reply::json(&serde_json::json!({
+                        "error": format!("Unknown scope: {}", unknown)
+                    })),
+                    StatusCode::BAD_REQUEST,
+                ));
+            }
+            if let Some(duplicate) = requested
+                .iter()
+                .enumerate()
+                .find_map(|(i, s)| requested[..i].contains(s).then_some(s))
+            {
+                return Ok(reply::with_status(
+                    reply::json(&serde_json::json!({
+                        "error": format!("Duplicate scope: {}", duplicate)
+                    })),
+                    StatusCode::BAD_REQUEST,
+                ));
+            }
+            requested
+        }
+        None => Scope::ALL.iter().map(|s| s.as_str().to_string()).collect(),
+    };
+
     let key = generate_api_key();
+    let hashed = secrets::hash_secret(&key);
 
     let result = sqlx::query_as::<_, ApiKey>(
         r#"
-        INSERT INTO api_keys (key, name)
-        VALUES ($1, $2)
+        INSERT INTO api_keys (
+            key_prefix, key_salt, key_hash, name, not_before, expires_at, scopes,
+            quota_period, quota_anchor, period_started_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, COALESCE($8, 'monthly'), $9, NOW())
         RETURNING *
         "#,
     )
-    .bind(&key)
+    .bind(&hashed.prefix)
+    .bind(&hashed.salt)
+    .bind(&hashed.hash)
     .bind(&body.name)
+    .bind(body.not_before)
+    .bind(body.expires_at)
+    .bind(&scopes)
+    .bind(body.quota_period)
+    .bind(body.quota_anchor.unwrap_or_else(Utc::now))
     .fetch_one(&*db)
     .await;
 
     match result {
         Ok(api_key) => {
+            webhooks.key_event(WebhookEvent::KeyCreated, &api_key);
+
+            // The plaintext key is only ever returned here; only its hash is stored.
             let response = CreateApiKeyResponse {
                 id: api_key.id,
-                key: api_key.key,
+                key,
+                key_prefix: api_key.key_prefix,
                 name: api_key.name,
+                not_before: api_key.not_before,
+                expires_at: api_key.expires_at,
+                scopes: api_key.scopes,
             };
             Ok(reply::with_status(
                 reply::json(&response),
@@ -71,16 +131,7 @@ pub async fn list_api_keys(db: DbPool) -> Result<impl Reply, Infallible> {
 
     match result {
         Ok(keys) => {
-            let key_infos: Vec<ApiKeyInfo> = keys
-                .into_iter()
-                .map(|k| ApiKeyInfo {
-                    id: k.id,
-                    name: k.name,
-                    usage_count: k.usage_count,
-                    is_active: k.is_active,
-                    created_at: k.created_at,
-                })
-                .collect();
+            let key_infos: Vec<ApiKeyInfo> = keys.into_iter().map(ApiKeyInfo::from).collect();
 
             let response = ApiKeyListResponse { keys: key_infos };
             Ok(reply::with_status(reply::json(&response), StatusCode::OK))
@@ -97,7 +148,185 @@ pub async fn list_api_keys(db: DbPool) -> Result<impl Reply, Infallible> {
     }
 }
 
-pub async fn delete_api_key(id: String, db: DbPool) -> Result<impl Reply, Infallible> {
+const MAX_KEY_NAME_LENGTH: usize = 255;
+pub const MAX_RATE_LIMIT_PER_MINUTE: i32 = 1_000_000;
+pub const MAX_CONCURRENT_REQUESTS: i32 = 10_000;
+pub const MAX_GRACE_PERIOD_SECONDS: i64 = 30 * 24 * 60 * 60;
+
+fn validate_update(body: &UpdateApiKeyRequest) -> Result<(), String> {
+    if body.name.is_none()
+        && body.quota_limit.is_none()
+        && body.rate_limit_per_minute.is_none()
+        && body.rate_limit_burst.is_none()
+        && body.max_concurrent_requests.is_none()
+        && body.is_active.is_none()
+        && body.quota_period.is_none()
+        && body.quota_anchor.is_none()
+    {
+        return Err("No fields to update".to_string());
+    }
+
+    if let Some(name) = &body.name {
+        if name.trim().is_empty() {
+            return Err("name cannot be empty".to_string());
+        }
+        if name.len() > MAX_KEY_NAME_LENGTH {
+            return Err(format!(
+                "name exceeds maximum length of {}",
+                MAX_KEY_NAME_LENGTH
+            ));
+        }
+    }
+
+    if let Some(Some(quota)) = body.quota_limit
+        && quota < 0
+    {
+        return Err("quota_limit cannot be negative".to_string());
+    }
+
+    if let Some(rate) = body.rate_limit_per_minute
+        && !(1..=MAX_RATE_LIMIT_PER_MINUTE).contains(&rate)
+    {
+        return Err(format!(
+            "rate_limit_per_minute must be between 1 and {}",
+            MAX_RATE_LIMIT_PER_MINUTE
+        ));
+    }
+
+    if let Some(Some(burst)) = body.rate_limit_burst
+        && !(1..=MAX_RATE_LIMIT_PER_MINUTE).contains(&burst)
+    {
+        return Err(format!(
+            "rate_limit_burst must be between 1 and {}",
+            MAX_RATE_LIMIT_PER_MINUTE
+        ));
+    }
+
+    if let Some(Some(max)) = body.max_concurrent_requests
+        && !(1..=MAX_CONCURRENT_REQUESTS).contains(&max)
+    {
+        return Err(format!(
+            "max_concurrent_requests must be between 1 and {}",
+            MAX_CONCURRENT_REQUESTS
+        ));
+    }
+
+    if let Some(anchor) = body.quota_anchor
+        && anchor > Utc::now()
+    {
+        return Err("quota_anchor cannot be in the future".to_string());
+    }
+
+    Ok(())
+}
+
+pub async fn update_api_key(
+    id: String,
+    body: UpdateApiKeyRequest,
+    db: DbPool,
+) -> Result<impl Reply, Infallible> {
+    let uuid = match Uuid::parse_str(&id) {
+        Ok(u) => u,
+        Err(_) => {
+            return Ok(reply::with_status(
+                reply::json(&serde_json::json!({
+                    "error": "Invalid UUID format"
+                })),
+                StatusCode::BAD_REQUEST,
+            ));
+        }
+    };
+
+    if let Err(e) = validate_update(&body) {
+        return Ok(reply::with_status(
+            reply::json(&serde_json::json!({
+                "error": e
+            })),
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+
+    // Changing the period or its anchor closes the running period: its usage
+    // is archived and counting starts over, so the new schedule never bills
+    // against consumption from the old one.
+    let result = sqlx::query_as::<_, ApiKey>(
+        r#"
+        WITH current AS (
+            SELECT id, usage_count, quota_limit, period_started_at,
+                quota_period IS DISTINCT FROM COALESCE($7, quota_period)
+                    OR quota_anchor IS DISTINCT FROM COALESCE($8, quota_anchor) AS rescheduled
+            FROM api_keys
+            WHERE id = $1
+            FOR UPDATE
+        ),
+        archived AS (
+            INSERT INTO quota_period_usage (api_key_id, period_start, period_end, usage_count, quota_limit)
+            SELECT id, period_started_at, NOW(), usage_count, quota_limit
+            FROM current
+            WHERE rescheduled
+        )
+        UPDATE api_keys
+        SET name = COALESCE($2, name),
+            quota_limit = CASE WHEN $3 THEN $4 ELSE api_keys.quota_limit END,
+            rate_limit_per_minute = COALESCE($5, rate_limit_per_minute),
+            is_active = COALESCE($6, is_active),
+            quota_period = COALESCE($7, quota_period),
+            quota_anchor = COALESCE($8, quota_anchor),
+            rate_limit_burst = CASE WHEN $9 THEN $10 ELSE rate_limit_burst END,
+            max_concurrent_requests =
+                CASE WHEN $11 THEN $12 ELSE max_concurrent_requests END,
+            usage_count =
+                CASE WHEN current.rescheduled THEN 0 ELSE api_keys.usage_count END,
+            period_started_at =
+                CASE WHEN current.rescheduled THEN NOW() ELSE api_keys.period_started_at END
+        FROM current
+        WHERE api_keys.id = current.id
+        RETURNING api_keys.*
+        "#,
+    )
+    .bind(uuid)
+    .bind(body.name.as_deref().map(str::trim))
+    .bind(body.quota_limit.is_some())
+    .bind(body.quota_limit.flatten())
+    .bind(body.rate_limit_per_minute)
+    .bind(body.is_active)
+    .bind(body.quota_period)
+    .bind(body.quota_anchor)
+    .bind(body.rate_limit_burst.is_some())
+    .bind(body.rate_limit_burst.flatten())
+    .bind(body.max_concurrent_requests.is_some())
+    .bind(body.max_concurrent_requests.flatten())
+    .fetch_optional(&*db)
+    .await;
+
+    match result {
+        Ok(Some(api_key)) => Ok(reply::with_status(
+            reply::json(&ApiKeyInfo::from(api_key)),
+            StatusCode::OK,
+        )),
+        Ok(None) => Ok(reply::with_status(
+            reply::json(&serde_json::json!({
+                "message": "API key not found"
+            })),
+            StatusCode::NOT_FOUND,
+        )),
+        Err(e) => {
+            tracing::error!("Failed to update API key: {:?}", e);
+            Ok(reply::with_status(
+                reply::json(&serde_json::json!({
+                    "error": "Failed to update API key"
+                })),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ))
+        }
+    }
+}
+
+pub async fn delete_api_key(
+    id: String,
+    db: DbPool,
+    webhooks: Webhooks,
+) -> Result<impl Reply, Infallible> {
     let uuid = match Uuid::parse_str(&id) {
         Ok(u) => u,
         Err(_) => {
@@ -110,28 +339,29 @@ pub async fn delete_api_key(id: String, db: DbPool) -> Result<impl Reply, Infall
         }
     };
 
-    let result = sqlx::query("DELETE FROM api_keys WHERE id = $1")
+    let result = sqlx::query_as::<_, ApiKey>("DELETE FROM api_keys WHERE id = $1 RETURNING *")
         .bind(uuid)
-        .execute(&*db)
+        .fetch_optional(&*db)
         .await;
 
     match result {
-        Ok(res) => {
-            if res.rows_affected() == 0 {
-                Ok(reply::with_status(
-                    reply::json(&serde_json::json!({
-                        "message": "API key not found"
-                    })),
-                    StatusCode::NOT_FOUND,
-                ))
-            } else {
-                Ok(reply::with_status(
-                    reply::json(&serde_json::json!({
-                        "message": "API key deleted successfully"
-                    })),
-                    StatusCode::OK,
-                ))
-            }
+        Ok(None) => Ok(reply::with_status(
+            reply::json(&serde_json::json!({
+                "message": "API key not found"
+            })),
+            StatusCode::NOT_FOUND,
+        )),
+        Ok(Some(api_key)) => {
+            // The delete cascaded to the key's own webhooks, so only global
+            // ones hear about it.
+            webhooks.key_event(WebhookEvent::KeyDeleted, &api_key);
+
+            Ok(reply::with_status(
+                reply::json(&serde_json::json!({
+                    "message": "API key deleted successfully"
+                })),
+                StatusCode::OK,
+            ))
         }
         Err(e) => {
             tracing::error!("Failed to delete API key: {:?}", e);
@@ -144,3 +374,396 @@ pub async fn delete_api_key(id: String, db: DbPool) -> Result<impl Reply, Infall
         }
     }
 }
+
+pub async fn rotate_api_key(
+    id: String,
+    params: RotateApiKeyParams,
+    default_grace_seconds: i64,
+    db: DbPool,
+    webhooks: Webhooks,
+) -> Result<impl Reply, Infallible> {
+    let uuid = match Uuid::parse_str(&id) {
+        Ok(u) => u,
+        Err(_) => {
+            return Ok(reply::with_status(
+                reply::json(&serde_json::json!({
+                    "error": "Invalid UUID format"
+                })),
+                StatusCode::BAD_REQUEST,
+            ));
+        }
+    };
+
+    let grace_seconds = params.grace_period_seconds.unwrap_or(default_grace_seconds);
+    if !(0..=MAX_GRACE_PERIOD_SECONDS).contains(&grace_seconds) {
+        return Ok(reply::with_status(
+            reply::json(&serde_json::json!({
+                "error": format!(
+                    "grace_period_seconds must be between 0 and {}",
+                    MAX_GRACE_PERIOD_SECONDS
+                )
+            })),
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+
+    let key = generate_api_key();
+    let hashed = secrets::hash_secret(&key);
+
+    // The outgoing secret moves into the previous_* slot and stays valid until
+    // the grace window closes; rotating again replaces whatever was there.
+    let result = sqlx::query_as::<_, ApiKey>(
+        r#"
+        UPDATE api_keys
+        SET previous_key_prefix = key_prefix,
+            previous_key_salt = key_salt,
+            previous_key_hash = key_hash,
+            previous_key_last_used_at = last_used_at,
+            previous_key_expires_at = NOW() + make_interval(secs => $5),
+            key_prefix = $2,
+            key_salt = $3,
+            key_hash = $4,
+            last_used_at = NULL,
+            rotated_at = NOW()
+        WHERE id = $1
+        RETURNING *
+        "#,
+    )
+    .bind(uuid)
+    .bind(&hashed.prefix)
+    .bind(&hashed.salt)
+    .bind(&hashed.hash)
+    .bind(grace_seconds as f64)
+    .fetch_optional(&*db)
+    .await;
+
+    match result {
+        Ok(Some(api_key)) => {
+            webhooks.key_event(WebhookEvent::KeyRotated, &api_key);
+
+            let response = RotateApiKeyResponse {
+                id: api_key.id,
+                key,
+                key_prefix: api_key.key_prefix,
+                name: api_key.name,
+                previous_key_prefix: api_key.previous_key_prefix,
+                previous_key_expires_at: api_key.previous_key_expires_at,
+            };
+            Ok(reply::with_status(reply::json(&response), StatusCode::OK))
+        }
+        Ok(None) => Ok(reply::with_status(
+            reply::json(&serde_json::json!({
+                "message": "API key not found"
+            })),
+            StatusCode::NOT_FOUND,
+        )),
+        Err(e) => {
+            tracing::error!("Failed to rotate API key: {:?}", e);
+            Ok(reply::with_status(
+                reply::json(&serde_json::json!({
+                    "error": "Failed to rotate API key"
+                })),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::create_api_key as create_api_key_handler;
+    use super::*;
+    use crate::middleware::auth::{KeySecret, find_api_key};
+    use crate::test_support::{create_api_key, into_json, subscribe_webhook, wait_for_events};
+    use sqlx::PgPool;
+    use std::sync::Arc;
+
+    async fn rotate(db: &DbPool, id: Uuid, grace: i64) -> (StatusCode, serde_json::Value) {
+        let params = RotateApiKeyParams {
+            grace_period_seconds: Some(grace),
+        };
+        let Ok(reply) = rotate_api_key(
+            id.to_string(),
+            params,
+            86400,
+            db.clone(),
+            Webhooks::new(db.clone()),
+        )
+        .await;
+
+        into_json(reply).await
+    }
+
+    fn update_request(body: serde_json::Value) -> UpdateApiKeyRequest {
+        serde_json::from_value(body).unwrap()
+    }
+
+    async fn update(
+        db: &DbPool,
+        id: impl ToString,
+        body: serde_json::Value,
+    ) -> (StatusCode, serde_json::Value) {
+        let Ok(reply) = update_api_key(id.to_string(), update_request(body), db.clone()).await;
+
+        into_json(reply).await
+    }
+
+    async fn secret_used(db: &DbPool, key: &str) -> Option<KeySecret> {
+        find_api_key(db, key)
+            .await
+            .unwrap()
+            .map(|(_, secret)| secret)
+    }
+
+    #[sqlx::test]
+    async fn old_secret_works_during_grace_window(pool: PgPool) {
+        let db = Arc::new(pool);
+        let (id, old_key) = create_api_key(&db).await;
+
+        let (status, body) = rotate(&db, id, 600).await;
+        assert_eq!(status, StatusCode::OK);
+        let new_key = body["key"].as_str().unwrap();
+
+        assert_eq!(secret_used(&db, new_key).await, Some(KeySecret::Current));
+        assert_eq!(secret_used(&db, &old_key).await, Some(KeySecret::Previous));
+    }
+
+    #[sqlx::test]
+    async fn old_secret_stops_working_when_grace_window_closes(pool: PgPool) {
+        let db = Arc::new(pool);
+        let (id, old_key) = create_api_key(&db).await;
+
+        rotate(&db, id, 600).await;
+        sqlx::query(
+            "UPDATE api_keys SET previous_key_expires_at = NOW() - INTERVAL '1 second' WHERE id = $1",
+        )
+        .bind(id)
+        .execute(&*db)
+        .await
+        .unwrap();
+
+        assert_eq!(secret_used(&db, &old_key).await, None);
+    }
+
+    #[sqlx::test]
+    async fn zero_grace_retires_old_secret_at_once(pool: PgPool) {
+        let db = Arc::new(pool);
+        let (id, old_key) = create_api_key(&db).await;
+
+        rotate(&db, id, 0).await;
+
+        assert_eq!(secret_used(&db, &old_key).await, None);
+    }
+
+    #[sqlx::test]
+    async fn rotating_again_replaces_previous_secret(pool: PgPool) {
+        let db = Arc::new(pool);
+        let (id, first_key) = create_api_key(&db).await;
+
+        let (_, body) = rotate(&db, id, 600).await;
+        let second_key = body["key"].as_str().unwrap().to_string();
+        rotate(&db, id, 600).await;
+
+        assert_eq!(secret_used(&db, &first_key).await, None);
+        assert_eq!(
+            secret_used(&db, &second_key).await,
+            Some(KeySecret::Previous)
+        );
+    }
+
+    #[sqlx::test]
+    async fn grace_period_is_bounded(pool: PgPool) {
+        let db = Arc::new(pool);
+        let (id, _) = create_api_key(&db).await;
+
+        assert_eq!(rotate(&db, id, -1).await.0, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            rotate(&db, id, MAX_GRACE_PERIOD_SECONDS + 1).await.0,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            rotate(&db, id, MAX_GRACE_PERIOD_SECONDS).await.0,
+            StatusCode::OK
+        );
+    }
+
+    #[test]
+    fn update_requires_at_least_one_field() {
+        assert!(validate_update(&update_request(serde_json::json!({}))).is_err());
+        assert!(
+            validate_update(&update_request(serde_json::json!({ "is_active": false }))).is_ok()
+        );
+    }
+
+    #[test]
+    fn update_rejects_out_of_range_values() {
+        for body in [
+            serde_json::json!({ "name": "   " }),
+            serde_json::json!({ "name": "x".repeat(MAX_KEY_NAME_LENGTH + 1) }),
+            serde_json::json!({ "quota_limit": -1 }),
+            serde_json::json!({ "rate_limit_per_minute": 0 }),
+            serde_json::json!({ "rate_limit_per_minute": MAX_RATE_LIMIT_PER_MINUTE + 1 }),
+            serde_json::json!({ "rate_limit_burst": 0 }),
+            serde_json::json!({ "max_concurrent_requests": MAX_CONCURRENT_REQUESTS + 1 }),
+        ] {
+            assert!(
+                validate_update(&update_request(body.clone())).is_err(),
+                "{body}"
+            );
+        }
+    }
+
+    #[test]
+    fn update_accepts_null_to_clear_optional_limits() {
+        let body = update_request(serde_json::json!({
+            "quota_limit": null,
+            "rate_limit_burst": null,
+            "max_concurrent_requests": null,
+        }));
+
+        assert_eq!(body.quota_limit, Some(None));
+        assert_eq!(body.rate_limit_burst, Some(None));
+        assert_eq!(body.max_concurrent_requests, Some(None));
+        assert!(validate_update(&body).is_ok());
+    }
+
+    #[sqlx::test]
+    async fn patch_changes_only_the_given_fields(pool: PgPool) {
+        let db = Arc::new(pool);
+        let (id, _) = create_api_key(&db).await;
+        update(
+            &db,
+            id,
+            serde_json::json!({ "quota_limit": 50, "rate_limit_burst": 5 }),
+        )
+        .await;
+
+        let (status, body) = update(&db, id, serde_json::json!({ "name": "  renamed  " })).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["name"], "renamed");
+        assert_eq!(body["quota_limit"], 50);
+        assert_eq!(body["rate_limit_burst"], 5);
+        assert_eq!(body["is_active"], true);
+    }
+
+    #[sqlx::test]
+    async fn patch_with_null_clears_a_limit(pool: PgPool) {
+        let db = Arc::new(pool);
+        let (id, _) = create_api_key(&db).await;
+        update(&db, id, serde_json::json!({ "quota_limit": 50 })).await;
+
+        let (status, body) = update(&db, id, serde_json::json!({ "quota_limit": null })).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert!(body["quota_limit"].is_null());
+    }
+
+    #[sqlx::test]
+    async fn patch_reports_bad_ids_and_missing_keys(pool: PgPool) {
+        let db = Arc::new(pool);
+        let body = serde_json::json!({ "is_active": false });
+
+        assert_eq!(
+            update(&db, "not-a-uuid", body.clone()).await.0,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            update(&db, Uuid::new_v4(), body).await.0,
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    async fn usage_and_archive(db: &DbPool, id: Uuid) -> (i32, i64) {
+        sqlx::query_as(
+            r#"
+            SELECT usage_count,
+                (SELECT COUNT(*) FROM quota_period_usage WHERE api_key_id = $1)
+            FROM api_keys
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_one(&**db)
+        .await
+        .unwrap()
+    }
+
+    #[sqlx::test]
+    async fn quota_anchor_cannot_be_in_the_future(pool: PgPool) {
+        let db = Arc::new(pool);
+        let (id, _) = create_api_key(&db).await;
+        let tomorrow = Utc::now() + chrono::Duration::days(1);
+
+        let request = serde_json::from_value(serde_json::json!({
+            "name": "future",
+            "quota_anchor": tomorrow,
+        }))
+        .unwrap();
+        let Ok(reply) =
+            create_api_key_handler(request, db.clone(), Webhooks::new(db.clone())).await;
+        assert_eq!(into_json(reply).await.0, StatusCode::BAD_REQUEST);
+
+        let (status, _) = update(&db, id, serde_json::json!({ "quota_anchor": tomorrow })).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[sqlx::test]
+    async fn changing_the_quota_period_starts_a_fresh_period(pool: PgPool) {
+        let db = Arc::new(pool);
+        let (id, _) = create_api_key(&db).await;
+        sqlx::query("UPDATE api_keys SET usage_count = 40 WHERE id = $1")
+            .bind(id)
+            .execute(&*db)
+            .await
+            .unwrap();
+
+        let (status, body) = update(&db, id, serde_json::json!({ "quota_period": "daily" })).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["quota"]["used"], 0);
+        assert_eq!(usage_and_archive(&db, id).await, (0, 1));
+    }
+
+    #[sqlx::test]
+    async fn unrelated_or_unchanged_fields_keep_the_period(pool: PgPool) {
+        let db = Arc::new(pool);
+        let (id, _) = create_api_key(&db).await;
+        sqlx::query("UPDATE api_keys SET usage_count = 40 WHERE id = $1")
+            .bind(id)
+            .execute(&*db)
+            .await
+            .unwrap();
+
+        update(&db, id, serde_json::json!({ "quota_limit": 100 })).await;
+        update(&db, id, serde_json::json!({ "quota_period": "monthly" })).await;
+
+        assert_eq!(usage_and_archive(&db, id).await, (40, 0));
+    }
+
+    #[sqlx::test]
+    async fn deleted_key_is_announced_to_global_webhooks(pool: PgPool) {
+        let db = Arc::new(pool);
+        let (id, _) = create_api_key(&db).await;
+        subscribe_webhook(&db, &["key.deleted"]).await;
+        sqlx::query(
+            "INSERT INTO webhooks (url, secret, events, api_key_id) VALUES ('http://127.0.0.1:9/key', 'whsec_test', '{key.deleted}', $1)",
+        )
+        .bind(id)
+        .execute(&*db)
+        .await
+        .unwrap();
+
+        let Ok(reply) = delete_api_key(id.to_string(), db.clone(), Webhooks::new(db.clone())).await;
+        assert_eq!(into_json(reply).await.0, StatusCode::OK);
+
+        assert_eq!(wait_for_events(&db, "key.deleted", 1).await, 1);
+        let targets: Vec<Option<Uuid>> = sqlx::query_scalar(
+            "SELECT w.api_key_id FROM webhook_deliveries d JOIN webhooks w ON w.id = d.webhook_id",
+        )
+        .fetch_all(&*db)
+        .await
+        .unwrap();
+        assert_eq!(targets, [None]);
+    }
+}
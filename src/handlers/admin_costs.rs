@@ -0,0 +1,86 @@
+use crate::db::DbPool;
+use crate::models::{Endpoint, EndpointCost, EndpointCostListResponse, UpdateEndpointCostRequest};
+use std::convert::Infallible;
+use warp::{Reply, http::StatusCode, reply};
+
+const MAX_COST: i32 = 1_000_000;
+
+pub async fn list_endpoint_costs(db: DbPool) -> Result<impl Reply, Infallible> {
+    let result =
+        sqlx::query_as::<_, EndpointCost>("SELECT * FROM endpoint_costs ORDER BY route, method")
+            .fetch_all(&*db)
+            .await;
+
+    match result {
+        Ok(costs) => {
+            let response = EndpointCostListResponse { costs };
+            Ok(reply::with_status(reply::json(&response), StatusCode::OK))
+        }
+        Err(e) => {
+            tracing::error!("Failed to list endpoint costs: {:?}", e);
+            Ok(reply::with_status(
+                reply::json(&serde_json::json!({
+                    "error": "Failed to list endpoint costs"
+                })),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ))
+        }
+    }
+}
+
+pub async fn update_endpoint_cost(
+    body: UpdateEndpointCostRequest,
+    db: DbPool,
+) -> Result<impl Reply, Infallible> {
+    let Some(endpoint) = Endpoint::parse(&body.method, &body.route) else {
+        return Ok(reply::with_status(
+            reply::json(&serde_json::json!({
+                "error": format!("Unknown endpoint: {} {}", body.method, body.route)
+            })),
+            StatusCode::BAD_REQUEST,
+        ));
+    };
+
+    let in_range = |cost: i32| (0..=MAX_COST).contains(&cost);
+    if !in_range(body.base_cost) || !in_range(body.per_item_cost) {
+        return Ok(reply::with_status(
+            reply::json(&serde_json::json!({
+                "error": format!(
+                    "base_cost and per_item_cost must be between 0 and {}",
+                    MAX_COST
+                )
+            })),
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+
+    let result = sqlx::query_as::<_, EndpointCost>(
+        r#"
+        INSERT INTO endpoint_costs (method, route, base_cost, per_item_cost)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (method, route) DO UPDATE
+        SET base_cost = EXCLUDED.base_cost,
+            per_item_cost = EXCLUDED.per_item_cost
+        RETURNING *
+        "#,
+    )
+    .bind(endpoint.method())
+    .bind(endpoint.route())
+    .bind(body.base_cost)
+    .bind(body.per_item_cost)
+    .fetch_one(&*db)
+    .await;
+
+    match result {
+        Ok(cost) => Ok(reply::with_status(reply::json(&cost), StatusCode::OK)),
+        Err(e) => {
+            tracing::error!("Failed to update endpoint cost: {:?}", e);
+            Ok(reply::with_status(
+                reply::json(&serde_json::json!({
+                    "error": "Failed to update endpoint cost"
+                })),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ))
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use crate::db::DbPool;
+use crate::handlers::admin::MAX_RATE_LIMIT_PER_MINUTE;
+use crate::models::{Endpoint, KeyLimit, KeyLimitListResponse, SetKeyLimitRequest};
+use std::convert::Infallible;
+use uuid::Uuid;
+use warp::{Reply, http::StatusCode, reply};
+
+pub async fn list_key_limits(id: String, db: DbPool) -> Result<impl Reply, Infallible> {
+    let uuid = match Uuid::parse_str(&id) {
+        Ok(u) => u,
+        Err(_) => {
+            return Ok(reply::with_status(
+                reply::json(&serde_json::json!({
+                    "error": "Invalid UUID format"
+                })),
+                StatusCode::BAD_REQUEST,
+            ));
+        }
+    };
+
+    let result = sqlx::query_as::<_, KeyLimit>(
+        "SELECT * FROM key_limits WHERE api_key_id = $1 ORDER BY route, method",
+    )
+    .bind(uuid)
+    .fetch_all(&*db)
+    .await;
+
+    match result {
+        Ok(limits) => {
+            let response = KeyLimitListResponse { limits };
+            Ok(reply::with_status(reply::json(&response), StatusCode::OK))
+        }
+        Err(e) => {
+            tracing::error!("Failed to list key limits: {:?}", e);
+            Ok(reply::with_status(
+                reply::json(&serde_json::json!({
+                    "error": "Failed to list key limits"
+                })),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ))
+        }
+    }
+}
+
+fn validate_limit(body: &SetKeyLimitRequest) -> Result<Endpoint, String> {
+    let endpoint = Endpoint::parse(&body.method, &body.route)
+        .ok_or_else(|| format!("Unknown endpoint: {} {}", body.method, body.route))?;
+
+    if body.rate_limit_per_minute.is_none()
+        && body.rate_limit_burst.is_none()
+        && body.quota_limit.is_none()
+    {
+        return Err("Set at least one of rate_limit_per_minute or quota_limit".to_string());
+    }
+
+    if body.rate_limit_burst.is_some() && body.rate_limit_per_minute.is_none() {
+        return Err("rate_limit_burst requires rate_limit_per_minute".to_string());
+    }
+
+    for (field, value) in [
+        ("rate_limit_per_minute", body.rate_limit_per_minute),
+        ("rate_limit_burst", body.rate_limit_burst),
+    ] {
+        if let Some(v) = value
+            && !(1..=MAX_RATE_LIMIT_PER_MINUTE).contains(&v)
+        {
+            return Err(format!(
+                "{} must be between 1 and {}",
+                field, MAX_RATE_LIMIT_PER_MINUTE
+            ));
+        }
+    }
+
+    if let Some(quota) = body.quota_limit
+        && quota < 0
+    {
+        return Err("quota_limit cannot be negative".to_string());
+    }
+
+    Ok(endpoint)
+}
+
+// Creates or replaces the limits for one endpoint. Usage already counted
+// against an existing limit in the current period is kept.
+pub async fn set_key_limit(
+    id: String,
+    body: SetKeyLimitRequest,
+    db: DbPool,
+) -> Result<impl Reply, Infallible> {
+    let uuid = match Uuid::parse_str(&id) {
+        Ok(u) => u,
+        Err(_) => {
+            return Ok(reply::with_status(
+                reply::json(&serde_json::json!({
+                    "error": "Invalid UUID format"
+                })),
+                StatusCode::BAD_REQUEST,
+            ));
+        }
+    };
+
+    let endpoint = match validate_limit(&body) {
+        Ok(endpoint) => endpoint,
+        Err(e) => {
+            return Ok(reply::with_status(
+                reply::json(&serde_json::json!({
+                    "error": e
+                })),
+                StatusCode::BAD_REQUEST,
+            ));
+        }
+    };
+
+    let result = sqlx::query_as::<_, KeyLimit>(
+        r#"
+        INSERT INTO key_limits
+            (api_key_id, method, route, rate_limit_per_minute, rate_limit_burst, quota_limit, period_started_at)
+        SELECT id, $2, $3, $4, $5, $6, period_started_at
+        FROM api_keys
+        WHERE id = $1
+        ON CONFLICT (api_key_id, method, route) DO UPDATE
+        SET rate_limit_per_minute = EXCLUDED.rate_limit_per_minute,
+            rate_limit_burst = EXCLUDED.rate_limit_burst,
+            quota_limit = EXCLUDED.quota_limit
+        RETURNING *
+        "#,
+    )
+    .bind(uuid)
+    .bind(endpoint.method())
+    .bind(endpoint.route())
+    .bind(body.rate_limit_per_minute)
+    .bind(body.rate_limit_burst)
+    .bind(body.quota_limit)
+    .fetch_optional(&*db)
+    .await;
+
+    match result {
+        Ok(Some(limit)) => Ok(reply::with_status(reply::json(&limit), StatusCode::OK)),
+        Ok(None) => Ok(reply::with_status(
+            reply::json(&serde_json::json!({
+                "error": "API key not found"
+            })),
+            StatusCode::NOT_FOUND,
+        )),
+        Err(e) => {
+            tracing::error!("Failed to set key limit: {:?}", e);
+            Ok(reply::with_status(
+                reply::json(&serde_json::json!({
+                    "error": "Failed to set key limit"
+                })),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ))
+        }
+    }
+}
+
+pub async fn delete_key_limit(
+    id: String,
+    limit_id: String,
+    db: DbPool,
+) -> Result<impl Reply, Infallible> {
+    let (Ok(uuid), Ok(limit_uuid)) = (Uuid::parse_str(&id), Uuid::parse_str(&limit_id)) else {
+        return Ok(reply::with_status(
+            reply::json(&serde_json::json!({
+                "error": "Invalid UUID format"
+            })),
+            StatusCode::BAD_REQUEST,
+        ));
+    };
+
+    let result = sqlx::query("DELETE FROM key_limits WHERE id = $1 AND api_key_id = $2")
+        .bind(limit_uuid)
+        .bind(uuid)
+        .execute(&*db)
+        .await;
+
+    match result {
+        Ok(res) if res.rows_affected() == 0 => Ok(reply::with_status(
+            reply::json(&serde_json::json!({
+                "error": "Key limit not found"
+            })),
+            StatusCode::NOT_FOUND,
+        )),
+        Ok(_) => Ok(reply::with_status(
+            reply::json(&serde_json::json!({
+                "message": "Key limit deleted successfully"
+            })),
+            StatusCode::OK,
+        )),
+        Err(e) => {
+            tracing::error!("Failed to delete key limit: {:?}", e);
+            Ok(reply::with_status(
+                reply::json(&serde_json::json!({
+                    "error": "Failed to delete key limit"
+                })),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ))
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use crate::db::DbPool;
+use crate::models::{
+    AdminToken, AdminTokenListResponse, CreateAdminTokenRequest, CreateAdminTokenResponse,
+};
+use crate::secrets;
+use std::convert::Infallible;
+use uuid::Uuid;
+use warp::{Reply, http::StatusCode, reply};
+
+pub async fn create_admin_token(
+    body: CreateAdminTokenRequest,
+    db: DbPool,
+) -> Result<impl Reply, Infallible> {
+    let token = secrets::generate_secret("at");
+    let hashed = secrets::hash_secret(&token);
+
+    let result = sqlx::query_as::<_, AdminToken>(
+        r#"
+        INSERT INTO admin_tokens (token_prefix, token_salt, token_hash, name, role)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING *
+        "#,
+    )
+    .bind(&hashed.prefix)
+    .bind(&hashed.salt)
+    .bind(&hashed.hash)
+    .bind(&body.name)
+    .bind(body.role)
+    .fetch_one(&*db)
+    .await;
+
+    match result {
+        Ok(admin) => {
+            let response = CreateAdminTokenResponse {
+                id: admin.id,
+                token,
+                token_prefix: admin.token_prefix,
+                name: admin.name,
+                role: admin.role,
+            };
+            Ok(reply::with_status(
+                reply::json(&response),
+                StatusCode::CREATED,
+            ))
+        }
+        Err(e) => {
+            tracing::error!("Failed to create admin token: {:?}", e);
+            Ok(reply::with_status(
+                reply::json(&serde_json::json!({
+                    "error": "Failed to create admin token"
+                })),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ))
+        }
+    }
+}
+
+pub async fn list_admin_tokens(db: DbPool) -> Result<impl Reply, Infallible> {
+    let result =
+        sqlx::query_as::<_, AdminToken>("SELECT * FROM admin_tokens ORDER BY created_at DESC")
+            .fetch_all(&*db)
+            .await;
+
+    match result {
+        Ok(tokens) => {
+            let response = AdminTokenListResponse { tokens };
+            Ok(reply::with_status(reply::json(&response), StatusCode::OK))
+        }
+        Err(e) => {
+            tracing::error!("Failed to list admin tokens: {:?}", e);
+            Ok(reply::with_status(
+                reply::json(&serde_json::json!({
+                    "error": "Failed to list admin tokens"
+                })),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ))
+        }
+    }
+}
+
+pub async fn delete_admin_token(id: String, db: DbPool) -> Result<impl Reply, Infallible> {
+    let uuid = match Uuid::parse_str(&id) {
+        Ok(u) => u,
+        Err(_) => {
+            return Ok(reply::with_status(
+                reply::json(&serde_json::json!({
+                    "error": "Invalid UUID format"
+                })),
+                StatusCode::BAD_REQUEST,
+            ));
+        }
+    };
+
+    let result = sqlx::query("DELETE FROM admin_tokens WHERE id = $1")
+        .bind(uuid)
+        .execute(&*db)
+        .await;
+
+    match result {
+        Ok(res) => {
+            if res.rows_affected() == 0 {
+                Ok(reply::with_status(
+                    reply::json(&serde_json::json!({
+                        "message": "Admin token not found"
+                    })),
+                    StatusCode::NOT_FOUND,
+                ))
+            } else {
+                Ok(reply::with_status(
+                    reply::json(&serde_json::json!({
+                        "message": "Admin token deleted successfully"
+                    })),
+                    StatusCode::OK,
+                ))
+            }
+        }
+        Err(e) => {
+            tracing::error!("Failed to delete admin token: {:?}", e);
+            Ok(reply::with_status(
+                reply::json(&serde_json::json!({
+                    "error": "Failed to delete admin token"
+                })),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ))
+        }
+    }
+}
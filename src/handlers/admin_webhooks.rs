@@ -0,0 +1,359 @@
+use crate::db::DbPool;
+use crate::models::{
+    CreateWebhookRequest, CreateWebhookResponse, UpdateWebhookRequest, Webhook,
+    WebhookDeliveriesParams, WebhookDelivery, WebhookDeliveryListResponse, WebhookEvent,
+    WebhookListResponse,
+};
+use crate::secrets;
+use crate::webhooks::Webhooks;
+use std::convert::Infallible;
+use uuid::Uuid;
+use warp::{Reply, http::StatusCode, reply};
+
+const MAX_URL_LENGTH: usize = 2048;
+const MAX_DESCRIPTION_LENGTH: usize = 255;
+const DEFAULT_PAGE_SIZE: i64 = 100;
+const MAX_PAGE_SIZE: i64 = 1000;
+
+fn validate_url(url: &str) -> Result<(), String> {
+    if url.len() > MAX_URL_LENGTH {
+        return Err(format!("url exceeds maximum length of {}", MAX_URL_LENGTH));
+    }
+
+    match reqwest::Url::parse(url) {
+        Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => Ok(()),
+        _ => Err("url must be an absolute http or https URL".to_string()),
+    }
+}
+
+// Event names in the order given, without duplicates.
+fn parse_events(events: &[String]) -> Result<Vec<String>, String> {
+    if events.is_empty() {
+        return Err("events must name at least one event".to_string());
+    }
+
+    let mut parsed: Vec<String> = Vec::new();
+    for name in events {
+        let event =
+            WebhookEvent::parse(name).ok_or_else(|| format!("Unknown webhook event: {}", name))?;
+        if !parsed.iter().any(|e| e == event.as_str()) {
+            parsed.push(event.as_str().to_string());
+        }
+    }
+
+    Ok(parsed)
+}
+
+fn validate_description(description: &str) -> Result<(), String> {
+    if description.len() > MAX_DESCRIPTION_LENGTH {
+        return Err(format!(
+            "description exceeds maximum length of {}",
+            MAX_DESCRIPTION_LENGTH
+        ));
+    }
+    Ok(())
+}
+
+fn is_unknown_api_key(e: &sqlx::Error) -> bool {
+    matches!(e, sqlx::Error::Database(db) if db.is_foreign_key_violation())
+}
+
+fn bad_request(error: String) -> reply::WithStatus<reply::Json> {
+    reply::with_status(
+        reply::json(&serde_json::json!({
+            "error": error
+        })),
+        StatusCode::BAD_REQUEST,
+    )
+}
+
+fn invalid_uuid() -> reply::WithStatus<reply::Json> {
+    bad_request("Invalid UUID format".to_string())
+}
+
+pub async fn create_webhook(
+    body: CreateWebhookRequest,
+    db: DbPool,
+) -> Result<impl Reply, Infallible> {
+    if let Err(e) = validate_url(&body.url) {
+        return Ok(bad_request(e));
+    }
+    let events = match parse_events(&body.events) {
+        Ok(events) => events,
+        Err(e) => return Ok(bad_request(e)),
+    };
+    if let Some(Err(e)) = body.description.as_deref().map(validate_description) {
+        return Ok(bad_request(e));
+    }
+
+    let secret = secrets::generate_secret("whsec");
+
+    let result = sqlx::query_as::<_, Webhook>(
+        r#"
+        INSERT INTO webhooks (url, secret, events, api_key_id, description)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING *
+        "#,
+    )
+    .bind(&body.url)
+    .bind(&secret)
+    .bind(&events)
+    .bind(body.api_key_id)
+    .bind(&body.description)
+    .fetch_one(&*db)
+    .await;
+
+    match result {
+        Ok(webhook) => {
+            let response = CreateWebhookResponse { webhook, secret };
+            Ok(reply::with_status(
+                reply::json(&response),
+                StatusCode::CREATED,
+            ))
+        }
+        Err(e) if is_unknown_api_key(&e) => Ok(bad_request(
+            "api_key_id does not match any API key".to_string(),
+        )),
+        Err(e) => {
+            tracing::error!("Failed to create webhook: {:?}", e);
+            Ok(reply::with_status(
+                reply::json(&serde_json::json!({
+                    "error": "Failed to create webhook"
+                })),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ))
+        }
+    }
+}
+
+pub async fn list_webhooks(db: DbPool) -> Result<impl Reply, Infallible> {
+    let result = sqlx::query_as::<_, Webhook>("SELECT * FROM webhooks ORDER BY created_at DESC")
+        .fetch_all(&*db)
+        .await;
+
+    match result {
+        Ok(webhooks) => {
+            let response = WebhookListResponse { webhooks };
+            Ok(reply::with_status(reply::json(&response), StatusCode::OK))
+        }
+        Err(e) => {
+            tracing::error!("Failed to list webhooks: {:?}", e);
+            Ok(reply::with_status(
+                reply::json(&serde_json::json!({
+                    "error": "Failed to list webhooks"
+                })),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ))
+        }
+    }
+}
+
+pub async fn update_webhook(
+    id: String,
+    body: UpdateWebhookRequest,
+    db: DbPool,
+) -> Result<impl Reply, Infallible> {
+    let Ok(uuid) = Uuid::parse_str(&id) else {
+        return Ok(invalid_uuid());
+    };
+
+    if body.url.is_none()
+        && body.events.is_none()
+        && body.description.is_none()
+        && body.is_active.is_none()
+    {
+        return Ok(bad_request("No fields to update".to_string()));
+    }
+    if let Some(Err(e)) = body.url.as_deref().map(validate_url) {
+        return Ok(bad_request(e));
+    }
+    let events = match body.events.as_deref().map(parse_events).transpose() {
+        Ok(events) => events,
+        Err(e) => return Ok(bad_request(e)),
+    };
+    if let Some(Some(Err(e))) = body
+        .description
+        .as_ref()
+        .map(|d| d.as_deref().map(validate_description))
+    {
+        return Ok(bad_request(e));
+    }
+
+    let result = sqlx::query_as::<_, Webhook>(
+        r#"
+        UPDATE webhooks
+        SET url = COALESCE($2, url),
+            events = COALESCE($3, events),
+            description = CASE WHEN $4 THEN $5 ELSE description END,
+            is_active = COALESCE($6, is_active)
+        WHERE id = $1
+        RETURNING *
+        "#,
+    )
+    .bind(uuid)
+    .bind(&body.url)
+    .bind(&events)
+    .bind(body.description.is_some())
+    .bind(body.description.flatten())
+    .bind(body.is_active)
+    .fetch_optional(&*db)
+    .await;
+
+    match result {
+        Ok(Some(webhook)) => Ok(reply::with_status(reply::json(&webhook), StatusCode::OK)),
+        Ok(None) => Ok(reply::with_status(
+            reply::json(&serde_json::json!({
+                "error": "Webhook not found"
+            })),
+            StatusCode::NOT_FOUND,
+        )),
+        Err(e) => {
+            tracing::error!("Failed to update webhook: {:?}", e);
+            Ok(reply::with_status(
+                reply::json(&serde_json::json!({
+                    "error": "Failed to update webhook"
+                })),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ))
+        }
+    }
+}
+
+pub async fn delete_webhook(id: String, db: DbPool) -> Result<impl Reply, Infallible> {
+    let Ok(uuid) = Uuid::parse_str(&id) else {
+        return Ok(invalid_uuid());
+    };
+
+    let result = sqlx::query("DELETE FROM webhooks WHERE id = $1")
+        .bind(uuid)
+        .execute(&*db)
+        .await;
+
+    match result {
+        Ok(res) if res.rows_affected() == 0 => Ok(reply::with_status(
+            reply::json(&serde_json::json!({
+                "error": "Webhook not found"
+            })),
+            StatusCode::NOT_FOUND,
+        )),
+        Ok(_) => Ok(reply::with_status(
+            reply::json(&serde_json::json!({
+                "message": "Webhook deleted successfully"
+            })),
+            StatusCode::OK,
+        )),
+        Err(e) => {
+            tracing::error!("Failed to delete webhook: {:?}", e);
+            Ok(reply::with_status(
+                reply::json(&serde_json::json!({
+                    "error": "Failed to delete webhook"
+                })),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ))
+        }
+    }
+}
+
+// Newest first.
+pub async fn list_webhook_deliveries(
+    id: String,
+    params: WebhookDeliveriesParams,
+    db: DbPool,
+) -> Result<impl Reply, Infallible> {
+    let Ok(uuid) = Uuid::parse_str(&id) else {
+        return Ok(invalid_uuid());
+    };
+
+    let limit = params.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+        return Ok(bad_request(format!(
+            "limit must be between 1 and {}",
+            MAX_PAGE_SIZE
+        )));
+    }
+
+    let result = sqlx::query_as::<_, WebhookDelivery>(
+        r#"
+        SELECT d.*, e.event_type
+        FROM webhook_deliveries d
+        JOIN webhook_events e ON e.id = d.event_id
+        WHERE d.webhook_id = $1 AND ($2::webhook_delivery_status IS NULL OR d.status = $2)
+        ORDER BY d.created_at DESC, d.id DESC
+        LIMIT $3
+        "#,
+    )
+    .bind(uuid)
+    .bind(params.status)
+    .bind(limit)
+    .fetch_all(&*db)
+    .await;
+
+    match result {
+        Ok(deliveries) => {
+            let response = WebhookDeliveryListResponse { deliveries };
+            Ok(reply::with_status(reply::json(&response), StatusCode::OK))
+        }
+        Err(e) => {
+            tracing::error!("Failed to list webhook deliveries: {:?}", e);
+            Ok(reply::with_status(
+                reply::json(&serde_json::json!({
+                    "error": "Failed to list webhook deliveries"
+                })),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ))
+        }
+    }
+}
+
+// Queues the delivery's event again as a new delivery to the same webhook,
+// with the same event id so receivers can recognise it.
+pub async fn replay_webhook_delivery(
+    id: String,
+    db: DbPool,
+    webhooks: Webhooks,
+) -> Result<impl Reply, Infallible> {
+    let Ok(uuid) = Uuid::parse_str(&id) else {
+        return Ok(invalid_uuid());
+    };
+
+    let result = sqlx::query_as::<_, WebhookDelivery>(
+        r#"
+        WITH replay AS (
+            INSERT INTO webhook_deliveries (webhook_id, event_id)
+            SELECT webhook_id, event_id FROM webhook_deliveries WHERE id = $1
+            RETURNING *
+        )
+        SELECT replay.*, e.event_type
+        FROM replay
+        JOIN webhook_events e ON e.id = replay.event_id
+        "#,
+    )
+    .bind(uuid)
+    .fetch_optional(&*db)
+    .await;
+
+    match result {
+        Ok(Some(delivery)) => {
+            webhooks.wake_deliverer();
+            Ok(reply::with_status(
+                reply::json(&delivery),
+                StatusCode::ACCEPTED,
+            ))
+        }
+        Ok(None) => Ok(reply::with_status(
+            reply::json(&serde_json::json!({
+                "error": "Webhook delivery not found"
+            })),
+            StatusCode::NOT_FOUND,
+        )),
+        Err(e) => {
+            tracing::error!("Failed to replay webhook delivery: {:?}", e);
+            Ok(reply::with_status(
+                reply::json(&serde_json::json!({
+                    "error": "Failed to replay webhook delivery"
+                })),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ))
+        }
+    }
+}
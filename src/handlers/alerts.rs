@@ -0,0 +1,454 @@
+use crate::db::DbPool;
+use crate::middleware::auth::AuthContext;
+use crate::middleware::validation::Validator;
+use crate::models::{
+    Alert, AlertListResponse, AlertRule, AlertRuleListResponse, AlertsParams,
+    CreateAlertRuleRequest, UpdateAlertRuleRequest,
+};
+use crate::units;
+use std::convert::Infallible;
+use uuid::Uuid;
+use warp::{Reply, http::StatusCode, reply};
+
+const MAX_RULES_PER_KEY: i64 = 100;
+const MAX_NAME_LENGTH: usize = 255;
+const MAX_TAG_LENGTH: usize = 50;
+const MAX_DURATION_SECONDS: i32 = 7 * 24 * 60 * 60;
+const DEFAULT_PAGE_SIZE: i64 = 100;
+const MAX_PAGE_SIZE: i64 = 1000;
+
+fn validate_name(name: &str) -> Result<(), String> {
+    if name.trim().is_empty() {
+        return Err("name cannot be empty".to_string());
+    }
+    if name.len() > MAX_NAME_LENGTH {
+        return Err(format!(
+            "name exceeds maximum length of {}",
+            MAX_NAME_LENGTH
+        ));
+    }
+    Ok(())
+}
+
+fn validate_tag(tag: &str) -> Result<(), String> {
+    if tag.trim().is_empty() || tag.len() > MAX_TAG_LENGTH {
+        return Err(format!(
+            "sensor_tag must be between 1 and {} characters",
+            MAX_TAG_LENGTH
+        ));
+    }
+    Ok(())
+}
+
+fn validate_duration(duration_seconds: i32) -> Result<(), String> {
+    if !(0..=MAX_DURATION_SECONDS).contains(&duration_seconds) {
+        return Err(format!(
+            "duration_seconds must be between 0 and {}",
+            MAX_DURATION_SECONDS
+        ));
+    }
+    Ok(())
+}
+
+fn validate_hysteresis(hysteresis: f64) -> Result<(), String> {
+    if !hysteresis.is_finite() || hysteresis < 0.0 {
+        return Err("hysteresis must be a non-negative number".to_string());
+    }
+    Ok(())
+}
+
+fn validate_create(body: &CreateAlertRuleRequest) -> Result<(), String> {
+    validate_name(&body.name)?;
+
+    if let Some(sensor_id) = &body.sensor_id {
+        Validator::sensor_id(sensor_id).map_err(|e| format!("sensor_id: {}", e))?;
+    }
+    if let Some(tag) = &body.sensor_tag {
+        validate_tag(tag)?;
+    }
+    Validator::value(body.threshold).map_err(|e| format!("threshold: {}", e))?;
+    if let Some(unit) = &body.unit {
+        Validator::unit(unit).map_err(|e| format!("unit: {}", e))?;
+    }
+    if let Some(duration_seconds) = body.duration_seconds {
+        validate_duration(duration_seconds)?;
+    }
+    if let Some(hysteresis) = body.hysteresis {
+        validate_hysteresis(hysteresis)?;
+    }
+
+    Ok(())
+}
+
+fn validate_update(body: &UpdateAlertRuleRequest) -> Result<(), String> {
+    if body.name.is_none()
+        && body.sensor_id.is_none()
+        && body.sensor_tag.is_none()
+        && body.comparison.is_none()
+        && body.threshold.is_none()
+        && body.unit.is_none()
+        && body.duration_seconds.is_none()
+        && body.hysteresis.is_none()
+        && body.enabled.is_none()
+    {
+        return Err("No fields to update".to_string());
+    }
+
+    if let Some(name) = &body.name {
+        validate_name(name)?;
+    }
+    if let Some(Some(sensor_id)) = &body.sensor_id {
+        Validator::sensor_id(sensor_id).map_err(|e| format!("sensor_id: {}", e))?;
+    }
+    if let Some(Some(tag)) = &body.sensor_tag {
+        validate_tag(tag)?;
+    }
+    if let Some(threshold) = body.threshold {
+        Validator::value(threshold).map_err(|e| format!("threshold: {}", e))?;
+    }
+    if let Some(Some(unit)) = &body.unit {
+        Validator::unit(unit).map_err(|e| format!("unit: {}", e))?;
+    }
+    if let Some(duration_seconds) = body.duration_seconds {
+        validate_duration(duration_seconds)?;
+    }
+    if let Some(hysteresis) = body.hysteresis {
+        validate_hysteresis(hysteresis)?;
+    }
+
+    Ok(())
+}
+
+fn invalid_rule_id() -> reply::WithStatus<reply::Json> {
+    reply::with_status(
+        reply::json(&serde_json::json!({
+            "error": "Invalid UUID format"
+        })),
+        StatusCode::BAD_REQUEST,
+    )
+}
+
+fn rule_not_found() -> reply::WithStatus<reply::Json> {
+    reply::with_status(
+        reply::json(&serde_json::json!({
+            "error": "Alert rule not found"
+        })),
+        StatusCode::NOT_FOUND,
+    )
+}
+
+pub async fn list_alerts(
+    auth: AuthContext,
+    db: DbPool,
+    params: AlertsParams,
+) -> Result<impl Reply, Infallible> {
+    let limit = params.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+        return Ok(auth.respond(reply::with_status(
+            reply::json(&serde_json::json!({
+                "error": format!("limit must be between 1 and {}", MAX_PAGE_SIZE)
+            })),
+            StatusCode::BAD_REQUEST,
+        )));
+    }
+
+    let result = sqlx::query_as::<_, Alert>(
+        r#"
+        SELECT * FROM alerts
+        WHERE api_key_id = $1
+          AND ($2::alert_status IS NULL OR status = $2)
+          AND ($3::text IS NULL OR sensor_id = $3)
+          AND ($4::uuid IS NULL OR rule_id = $4)
+        ORDER BY started_at DESC, id DESC
+        LIMIT $5
+        "#,
+    )
+    .bind(auth.api_key.id)
+    .bind(params.status)
+    .bind(&params.sensor_id)
+    .bind(params.rule_id)
+    .bind(limit)
+    .fetch_all(&*db)
+    .await;
+
+    let response = match result {
+        Ok(alerts) => {
+            let response = AlertListResponse { alerts };
+            reply::with_status(reply::json(&response), StatusCode::OK)
+        }
+        Err(e) => {
+            tracing::error!("Failed to list alerts: {:?}", e);
+            reply::with_status(
+                reply::json(&serde_json::json!({
+                    "error": "Failed to list alerts"
+                })),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        }
+    };
+
+    Ok(auth.respond(response))
+}
+
+pub async fn list_alert_rules(auth: AuthContext, db: DbPool) -> Result<impl Reply, Infallible> {
+    let result = sqlx::query_as::<_, AlertRule>(
+        "SELECT * FROM alert_rules WHERE api_key_id = $1 ORDER BY created_at",
+    )
+    .bind(auth.api_key.id)
+    .fetch_all(&*db)
+    .await;
+
+    let response = match result {
+        Ok(rules) => {
+            let response = AlertRuleListResponse { rules };
+            reply::with_status(reply::json(&response), StatusCode::OK)
+        }
+        Err(e) => {
+            tracing::error!("Failed to list alert rules: {:?}", e);
+            reply::with_status(
+                reply::json(&serde_json::json!({
+                    "error": "Failed to list alert rules"
+                })),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        }
+    };
+
+    Ok(auth.respond(response))
+}
+
+pub async fn create_alert_rule(
+    auth: AuthContext,
+    db: DbPool,
+    body: CreateAlertRuleRequest,
+) -> Result<impl Reply, Infallible> {
+    if let Err(e) = validate_create(&body) {
+        return Ok(auth.respond(reply::with_status(
+            reply::json(&serde_json::json!({
+                "error": e
+            })),
+            StatusCode::BAD_REQUEST,
+        )));
+    }
+
+    // The count check and insert are not atomic, so concurrent creates can
+    // overshoot the cap slightly.
+    let result = sqlx::query_as::<_, AlertRule>(
+        r#"
+        INSERT INTO alert_rules (api_key_id, name, sensor_id, sensor_tag, comparison, threshold,
+                                 unit, duration_seconds, hysteresis, enabled)
+        SELECT $1, $2, $3, $4, $5, $6, $7, COALESCE($8, 0), COALESCE($9, 0), COALESCE($10, TRUE)
+        WHERE (SELECT COUNT(*) FROM alert_rules WHERE api_key_id = $1) < $11
+        RETURNING *
+        "#,
+    )
+    .bind(auth.api_key.id)
+    .bind(body.name.trim())
+    .bind(&body.sensor_id)
+    .bind(body.sensor_tag.as_deref().map(str::trim))
+    .bind(body.comparison)
+    .bind(body.threshold)
+    .bind(body.unit.as_deref().map(units::normalize))
+    .bind(body.duration_seconds)
+    .bind(body.hysteresis)
+    .bind(body.enabled)
+    .bind(MAX_RULES_PER_KEY)
+    .fetch_optional(&*db)
+    .await;
+
+    let response = match result {
+        Ok(Some(rule)) => reply::with_status(reply::json(&rule), StatusCode::CREATED),
+        Ok(None) => reply::with_status(
+            reply::json(&serde_json::json!({
+                "error": format!("An API key can have at most {} alert rules", MAX_RULES_PER_KEY)
+            })),
+            StatusCode::CONFLICT,
+        ),
+        Err(e) => {
+            tracing::error!("Failed to create alert rule: {:?}", e);
+            reply::with_status(
+                reply::json(&serde_json::json!({
+                    "error": "Failed to create alert rule"
+                })),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        }
+    };
+
+    Ok(auth.respond(response))
+}
+
+pub async fn get_alert_rule(
+    rule_id: String,
+    auth: AuthContext,
+    db: DbPool,
+) -> Result<impl Reply, Infallible> {
+    let Ok(rule_id) = Uuid::parse_str(&rule_id) else {
+        return Ok(auth.respond(invalid_rule_id()));
+    };
+
+    let result = sqlx::query_as::<_, AlertRule>(
+        "SELECT * FROM alert_rules WHERE api_key_id = $1 AND id = $2",
+    )
+    .bind(auth.api_key.id)
+    .bind(rule_id)
+    .fetch_optional(&*db)
+    .await;
+
+    let response = match result {
+        Ok(Some(rule)) => reply::with_status(reply::json(&rule), StatusCode::OK),
+        Ok(None) => rule_not_found(),
+        Err(e) => {
+            tracing::error!("Failed to fetch alert rule: {:?}", e);
+            reply::with_status(
+                reply::json(&serde_json::json!({
+                    "error": "Failed to fetch alert rule"
+                })),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        }
+    };
+
+    Ok(auth.respond(response))
+}
+
+// Disabling a rule closes its open alerts: pending ones are dropped and
+// firing ones resolved, since nothing would ever resolve them otherwise.
+async fn update_rule(
+    db: &DbPool,
+    api_key_id: Uuid,
+    rule_id: Uuid,
+    body: UpdateAlertRuleRequest,
+) -> Result<Option<AlertRule>, sqlx::Error> {
+    let mut tx = db.begin().await?;
+
+    let rule = sqlx::query_as::<_, AlertRule>(
+        r#"
+        UPDATE alert_rules
+        SET name = COALESCE($3, name),
+            sensor_id = CASE WHEN $4 THEN $5 ELSE sensor_id END,
+            sensor_tag = CASE WHEN $6 THEN $7 ELSE sensor_tag END,
+            comparison = COALESCE($8, comparison),
+            threshold = COALESCE($9, threshold),
+            unit = CASE WHEN $10 THEN $11 ELSE unit END,
+            duration_seconds = COALESCE($12, duration_seconds),
+            hysteresis = COALESCE($13, hysteresis),
+            enabled = COALESCE($14, enabled)
+        WHERE api_key_id = $1 AND id = $2
+        RETURNING *
+        "#,
+    )
+    .bind(api_key_id)
+    .bind(rule_id)
+    .bind(body.name.as_deref().map(str::trim))
+    .bind(body.sensor_id.is_some())
+    .bind(body.sensor_id.flatten())
+    .bind(body.sensor_tag.is_some())
+    .bind(body.sensor_tag.flatten().as_deref().map(str::trim))
+    .bind(body.comparison)
+    .bind(body.threshold)
+    .bind(body.unit.is_some())
+    .bind(body.unit.flatten().as_deref().map(units::normalize))
+    .bind(body.duration_seconds)
+    .bind(body.hysteresis)
+    .bind(body.enabled)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    if let Some(rule) = &rule
+        && !rule.enabled
+    {
+        sqlx::query("DELETE FROM alerts WHERE rule_id = $1 AND status = 'pending'")
+            .bind(rule.id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query(
+            r#"
+            UPDATE alerts SET status = 'resolved', resolved_at = NOW()
+            WHERE rule_id = $1 AND status = 'firing'
+            "#,
+        )
+        .bind(rule.id)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(rule)
+}
+
+pub async fn update_alert_rule(
+    rule_id: String,
+    auth: AuthContext,
+    db: DbPool,
+    body: UpdateAlertRuleRequest,
+) -> Result<impl Reply, Infallible> {
+    let Ok(rule_id) = Uuid::parse_str(&rule_id) else {
+        return Ok(auth.respond(invalid_rule_id()));
+    };
+
+    if let Err(e) = validate_update(&body) {
+        return Ok(auth.respond(reply::with_status(
+            reply::json(&serde_json::json!({
+                "error": e
+            })),
+            StatusCode::BAD_REQUEST,
+        )));
+    }
+
+    let response = match update_rule(&db, auth.api_key.id, rule_id, body).await {
+        Ok(Some(rule)) => reply::with_status(reply::json(&rule), StatusCode::OK),
+        Ok(None) => rule_not_found(),
+        Err(e) => {
+            tracing::error!("Failed to update alert rule: {:?}", e);
+            reply::with_status(
+                reply::json(&serde_json::json!({
+                    "error": "Failed to update alert rule"
+                })),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        }
+    };
+
+    Ok(auth.respond(response))
+}
+
+// Deleting a rule deletes its alert history too.
+pub async fn delete_alert_rule(
+    rule_id: String,
+    auth: AuthContext,
+    db: DbPool,
+) -> Result<impl Reply, Infallible> {
+    let Ok(rule_id) = Uuid::parse_str(&rule_id) else {
+        return Ok(auth.respond(invalid_rule_id()));
+    };
+
+    let result = sqlx::query("DELETE FROM alert_rules WHERE api_key_id = $1 AND id = $2")
+        .bind(auth.api_key.id)
+        .bind(rule_id)
+        .execute(&*db)
+        .await;
+
+    let response = match result {
+        Ok(res) if res.rows_affected() == 0 => rule_not_found(),
+        Ok(_) => reply::with_status(
+            reply::json(&serde_json::json!({
+                "message": "Alert rule deleted successfully"
+            })),
+            StatusCode::OK,
+        ),
+        Err(e) => {
+            tracing::error!("Failed to delete alert rule: {:?}", e);
+            reply::with_status(
+                reply::json(&serde_json::json!({
+                    "error": "Failed to delete alert rule"
+                })),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        }
+    };
+
+    Ok(auth.respond(response))
+}
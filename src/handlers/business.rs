@@ -1,57 +1,104 @@
-use chrono::Utc;
+use chrono::{DateTime, Duration, Utc};
+use sqlx::Row;
 use std::convert::Infallible;
 use uuid::Uuid;
 use warp::{Reply, http::StatusCode, reply};
 
 use crate::{
+    alerts::{self, AlertEvent},
     db::DbPool,
-    models::{ApiKey, Reading, ReadingData, ReadingRequest, ReadingResponse},
+    feed::ReadingFeed,
+    middleware::{auth::AuthContext, validation::Validator},
+    models::{
+        AggregateFn, AggregateParams, BatchItemResult, BucketWidth, Reading, ReadingBatchResponse,
+        ReadingCursor, ReadingData, ReadingRequest, ReadingResponse, ReadingsParams, SortOrder,
+        WebhookEvent,
+    },
+    sensors::{self, SensorPolicy},
+    units::{self, Unit},
+    webhooks::Webhooks,
 };
 
 pub async fn submit_reading(
-    api_key: ApiKey,
+    auth: AuthContext,
     db: DbPool,
+    feed: ReadingFeed,
+    sensor_policy: SensorPolicy,
     reading: ReadingRequest,
 ) -> Result<impl Reply, Infallible> {
+    let api_key = &auth.api_key;
+
     tracing::info!(
         "Received reading from API key {}: sensor={}, value={}, unit={}",
-        api_key.key,
+        api_key.key_prefix,
         reading.sensor_id,
         reading.value,
         reading.unit
     );
 
-    let result = sqlx::query(
+    match sensors::check_readings(&db, api_key.id, sensor_policy, &[&reading]).await {
+        Ok(checks) => {
+            if let Some(Err(e)) = checks.into_iter().next() {
+                let err_response = reply::json(&serde_json::json!({"error": e}));
+
+                return Ok(auth.respond(reply::with_status(err_response, StatusCode::BAD_REQUEST)));
+            }
+        }
+        Err(e) => {
+            tracing::error!("Failed to check reading against sensor registry: {:?}", e);
+
+            let err_response =
+                reply::json(&serde_json::json!({"error": "Failed to save the reading"}));
+
+            return Ok(auth.respond(reply::with_status(
+                err_response,
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )));
+        }
+    }
+
+    let result = sqlx::query_as::<_, Reading>(
         r#"
-        INSERT INTO readings (id, api_key_id, sensor_id, value, unit)
-        VALUES ($1, $2, $3, $4, $5)
+        INSERT INTO readings (id, api_key_id, sensor_id, value, unit, raw_unit, recorded_at)
+        VALUES ($1, $2, $3, $4, $5, $6, COALESCE($7, NOW()))
+        RETURNING *
         "#,
     )
     .bind(Uuid::new_v4())
     .bind(api_key.id)
     .bind(&reading.sensor_id)
     .bind(reading.value)
+    .bind(units::normalize(&reading.unit))
     .bind(&reading.unit)
-    .execute(&*db)
+    .bind(reading.recorded_at)
+    .fetch_one(&*db)
     .await;
 
-    match result {
-        Ok(_) => {
+    let response = match result {
+        Ok(stored) => {
+            evaluate_alerts(
+                &db,
+                &auth.webhooks,
+                api_key.id,
+                std::slice::from_ref(&stored),
+            )
+            .await;
+            feed.publish(std::slice::from_ref(&stored)).await;
+
             let response = ReadingResponse {
                 status: "success".to_string(),
                 message: "Reading recorded successfully".to_string(),
                 timestamp: Utc::now(),
                 data: ReadingData {
-                    sensor_id: reading.sensor_id,
-                    value: reading.value,
-                    unit: reading.unit,
+                    sensor_id: stored.sensor_id,
+                    value: stored.value,
+                    unit: stored.unit,
+                    raw_unit: stored.raw_unit,
+                    recorded_at: stored.recorded_at,
                 },
             };
 
-            Ok(reply::with_status(
-                reply::json(&response),
-                StatusCode::CREATED,
-            ))
+            reply::with_status(reply::json(&response), StatusCode::CREATED)
         }
         Err(e) => {
             tracing::error!("Failed to save reading: {:?}", e);
@@ -59,37 +106,298 @@ pub async fn submit_reading(
             let err_response =
                 reply::json(&serde_json::json!({"error": "Failed to save the reading"}));
 
-            Ok(reply::with_status(
+            reply::with_status(err_response, StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    };
+
+    Ok(auth.respond_with_items(response, 1))
+}
+
+pub async fn submit_reading_batch(
+    auth: AuthContext,
+    db: DbPool,
+    feed: ReadingFeed,
+    sensor_policy: SensorPolicy,
+    items: Vec<serde_json::Value>,
+) -> Result<impl Reply, Infallible> {
+    let api_key = &auth.api_key;
+
+    tracing::info!(
+        "Received batch of {} readings from API key {}",
+        items.len(),
+        api_key.key_prefix
+    );
+
+    let mut results = Vec::with_capacity(items.len());
+    let mut accepted = Vec::new();
+
+    for (index, item) in items.into_iter().enumerate() {
+        let reading = serde_json::from_value::<ReadingRequest>(item)
+            .map_err(|e| format!("Invalid reading: {}", e))
+            .and_then(|reading| Validator::reading(&reading).map(|_| reading));
+
+        match reading {
+            Ok(reading) => {
+                let id = Uuid::new_v4();
+                results.push(BatchItemResult {
+                    index,
+                    status: "accepted".to_string(),
+                    id: Some(id),
+                    error: None,
+                });
+                accepted.push((index, id, reading));
+            }
+            Err(e) => results.push(BatchItemResult {
+                index,
+                status: "rejected".to_string(),
+                id: None,
+                error: Some(e),
+            }),
+        }
+    }
+
+    let checks = {
+        let readings: Vec<&ReadingRequest> = accepted.iter().map(|(_, _, r)| r).collect();
+        sensors::check_readings(&db, api_key.id, sensor_policy, &readings).await
+    };
+
+    match checks {
+        Ok(checks) => {
+            let mut registered = Vec::with_capacity(accepted.len());
+            for ((index, id, reading), check) in accepted.into_iter().zip(checks) {
+                match check {
+                    Ok(()) => registered.push((index, id, reading)),
+                    Err(e) => {
+                        results[index] = BatchItemResult {
+                            index,
+                            status: "rejected".to_string(),
+                            id: None,
+                            error: Some(e),
+                        }
+                    }
+                }
+            }
+            accepted = registered;
+        }
+        Err(e) => {
+            tracing::error!("Failed to check readings against sensor registry: {:?}", e);
+
+            let err_response =
+                reply::json(&serde_json::json!({"error": "Failed to save the readings"}));
+
+            return Ok(auth.respond(reply::with_status(
                 err_response,
                 StatusCode::INTERNAL_SERVER_ERROR,
-            ))
+            )));
         }
     }
+
+    let rejected = results.len() - accepted.len();
+
+    if accepted.is_empty() {
+        let response = ReadingBatchResponse {
+            status: "error".to_string(),
+            accepted: 0,
+            rejected,
+            timestamp: Utc::now(),
+            results,
+        };
+
+        return Ok(auth.respond(reply::with_status(
+            reply::json(&response),
+            StatusCode::BAD_REQUEST,
+        )));
+    }
+
+    // Each stored reading is charged, so check the whole batch fits before
+    // storing any of it.
+    if !auth.items_fit_quota(accepted.len()) {
+        let err_response = reply::json(&serde_json::json!({
+            "error": "Batch would exceed the API key's request quota"
+        }));
+
+        return Ok(auth.respond(reply::with_status(err_response, StatusCode::FORBIDDEN)));
+    }
+
+    let ids: Vec<Uuid> = accepted.iter().map(|(_, id, _)| *id).collect();
+    let sensor_ids: Vec<&str> = accepted
+        .iter()
+        .map(|(_, _, r)| r.sensor_id.as_str())
+        .collect();
+    let values: Vec<f64> = accepted.iter().map(|(_, _, r)| r.value).collect();
+    let canonical_units: Vec<String> = accepted
+        .iter()
+        .map(|(_, _, r)| units::normalize(&r.unit))
+        .collect();
+    let raw_units: Vec<&str> = accepted.iter().map(|(_, _, r)| r.unit.as_str()).collect();
+    let recorded_at: Vec<Option<DateTime<Utc>>> =
+        accepted.iter().map(|(_, _, r)| r.recorded_at).collect();
+
+    let result = sqlx::query_as::<_, Reading>(
+        r#"
+        INSERT INTO readings (id, api_key_id, sensor_id, value, unit, raw_unit, recorded_at)
+        SELECT r.id, $2, r.sensor_id, r.value, r.unit, r.raw_unit, COALESCE(r.recorded_at, NOW())
+        FROM UNNEST($1::uuid[], $3::text[], $4::float8[], $5::text[], $6::text[], $7::timestamptz[])
+            AS r(id, sensor_id, value, unit, raw_unit, recorded_at)
+        RETURNING *
+        "#,
+    )
+    .bind(&ids)
+    .bind(api_key.id)
+    .bind(&sensor_ids)
+    .bind(&values)
+    .bind(&canonical_units)
+    .bind(&raw_units)
+    .bind(&recorded_at)
+    .fetch_all(&*db)
+    .await;
+
+    let response = match result {
+        Ok(stored) => {
+            evaluate_alerts(&db, &auth.webhooks, api_key.id, &stored).await;
+            feed.publish(&stored).await;
+
+            let response = ReadingBatchResponse {
+                status: if rejected == 0 { "success" } else { "partial" }.to_string(),
+                accepted: accepted.len(),
+                rejected,
+                timestamp: Utc::now(),
+                results,
+            };
+
+            reply::with_status(reply::json(&response), StatusCode::CREATED)
+        }
+        Err(e) => {
+            tracing::error!("Failed to save reading batch: {:?}", e);
+
+            let err_response =
+                reply::json(&serde_json::json!({"error": "Failed to save the readings"}));
+
+            reply::with_status(err_response, StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    };
+
+    Ok(auth.respond_with_items(response, accepted.len()))
 }
 
-pub async fn get_readings(api_key: ApiKey, db: DbPool) -> Result<impl Reply, Infallible> {
+const DEFAULT_PAGE_SIZE: i64 = 100;
+const MAX_PAGE_SIZE: i64 = 1000;
+
+pub async fn get_readings(
+    auth: AuthContext,
+    db: DbPool,
+    params: ReadingsParams,
+) -> Result<impl Reply, Infallible> {
+    let api_key = &auth.api_key;
+
     tracing::info!("Fetching readings for API key ID: {}", api_key.id);
 
-    let result = sqlx::query_as::<_, Reading>(
+    let limit = params.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+        let err_response = reply::json(&serde_json::json!({
+            "error": format!("limit must be between 1 and {}", MAX_PAGE_SIZE)
+        }));
+
+        return Ok(auth.respond(reply::with_status(err_response, StatusCode::BAD_REQUEST)));
+    }
+
+    let convert_to = match params.convert_to.as_deref().map(|u| (u, Unit::lookup(u))) {
+        Some((unit, None)) => {
+            let err_response = reply::json(&serde_json::json!({
+                "error": format!("convert_to: Unknown unit {}", unit)
+            }));
+
+            return Ok(auth.respond(reply::with_status(err_response, StatusCode::BAD_REQUEST)));
+        }
+        Some((_, unit)) => unit,
+        None => None,
+    };
+
+    let cursor = match params.cursor.as_deref().map(ReadingCursor::decode) {
+        Some(None) => {
+            let err_response = reply::json(&serde_json::json!({"error": "Invalid cursor"}));
+
+            return Ok(auth.respond(reply::with_status(err_response, StatusCode::BAD_REQUEST)));
+        }
+        Some(cursor) => cursor,
+        None => None,
+    };
+
+    // Keyset pagination: the next page starts after the cursor's
+    // (recorded_at, id) in the requested direction, which the
+    // (api_key_id, recorded_at, id) index serves without an offset.
+    let (after, direction) = match params.order {
+        SortOrder::Asc => (">", "ASC"),
+        SortOrder::Desc => ("<", "DESC"),
+    };
+
+    // One extra row tells whether there is another page.
+    let result = sqlx::query_as::<_, Reading>(&format!(
         r#"
         SELECT * FROM readings
         WHERE api_key_id = $1
-        ORDER BY created_at DESC
-        "#,
-    )
+            AND ($2::text IS NULL OR sensor_id = $2)
+            AND ($3::text IS NULL OR unit = $3)
+            AND ($4::timestamptz IS NULL OR recorded_at >= $4)
+            AND ($5::timestamptz IS NULL OR recorded_at <= $5)
+            AND ($6::float8 IS NULL OR value >= $6)
+            AND ($7::float8 IS NULL OR value <= $7)
+            AND ($8::timestamptz IS NULL OR (recorded_at, id) {after} ($8, $9::uuid))
+        ORDER BY recorded_at {direction}, id {direction}
+        LIMIT $10
+        "#
+    ))
     .bind(api_key.id)
+    .bind(params.sensor_id.as_deref())
+    .bind(params.unit.as_deref().map(units::normalize))
+    .bind(params.from)
+    .bind(params.to)
+    .bind(params.min_value)
+    .bind(params.max_value)
+    .bind(cursor.map(|c| c.recorded_at))
+    .bind(cursor.map(|c| c.id))
+    .bind(limit + 1)
     .fetch_all(&*db)
     .await;
 
-    match result {
-        Ok(readings) => {
+    let mut items = 0;
+
+    let response = match result {
+        Ok(mut readings) => {
+            let next_cursor = if readings.len() as i64 > limit {
+                readings.truncate(limit as usize);
+                readings.last().map(|r| {
+                    ReadingCursor {
+                        recorded_at: r.recorded_at,
+                        id: r.id,
+                    }
+                    .encode()
+                })
+            } else {
+                None
+            };
+
+            // Readings in units that cannot be converted keep their own.
+            if let Some(target) = convert_to {
+                for reading in &mut readings {
+                    if let Some(value) =
+                        Unit::lookup(&reading.unit).and_then(|u| u.convert(reading.value, target))
+                    {
+                        reading.value = value;
+                        reading.unit = target.symbol.to_string();
+                    }
+                }
+            }
+
+            items = readings.len();
             let response = serde_json::json!({
                 "status": "success",
                 "count": readings.len(),
-                "readings": readings
+                "readings": readings,
+                "next_cursor": next_cursor
             });
 
-            Ok(reply::with_status(reply::json(&response), StatusCode::OK))
+            reply::with_status(reply::json(&response), StatusCode::OK)
         }
         Err(e) => {
             tracing::error!("Failed to fetch readings: {:?}", e);
@@ -97,10 +405,620 @@ pub async fn get_readings(api_key: ApiKey, db: DbPool) -> Result<impl Reply, Inf
             let err_response =
                 reply::json(&serde_json::json!({"error": "Failed to fetch readings"}));
 
-            Ok(reply::with_status(
-                err_response,
-                StatusCode::INTERNAL_SERVER_ERROR,
-            ))
+            reply::with_status(err_response, StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    };
+
+    Ok(auth.respond_with_items(response, items))
+}
+
+const DEFAULT_AGGREGATE_WINDOW_HOURS: i64 = 24;
+const MAX_BUCKETS: i64 = 1000;
+
+pub async fn get_reading_aggregates(
+    auth: AuthContext,
+    db: DbPool,
+    params: AggregateParams,
+) -> Result<impl Reply, Infallible> {
+    let api_key = &auth.api_key;
+
+    tracing::info!("Aggregating readings for API key ID: {}", api_key.id);
+
+    let validated = Validator::sensor_id(&params.sensor_id)
+        .map_err(|e| format!("sensor_id: {}", e))
+        .and_then(|_| {
+            BucketWidth::parse(&params.bucket)
+                .ok_or_else(|| "bucket must be a width like 15m, 1h or 1d".to_string())
+        })
+        .and_then(|bucket| {
+            let functions = AggregateFn::parse_list(params.functions.as_deref().unwrap_or("avg"))?;
+
+            let to = params.to.unwrap_or_else(Utc::now);
+            let from = params
+                .from
+                .unwrap_or(to - Duration::hours(DEFAULT_AGGREGATE_WINDOW_HOURS));
+            if from >= to {
+                return Err("from must be earlier than to".to_string());
+            }
+
+            let buckets = (to - from).num_seconds() / bucket.0.num_seconds();
+            if buckets > MAX_BUCKETS {
+                return Err(format!(
+                    "Time range spans more than {} buckets; use a wider bucket or a shorter range",
+                    MAX_BUCKETS
+                ));
+            }
+
+            let convert_to = match params.convert_to.as_deref() {
+                Some(unit) => Some(
+                    Unit::lookup(unit)
+                        .ok_or_else(|| format!("convert_to: Unknown unit {}", unit))?,
+                ),
+                None => None,
+            };
+
+            Ok((bucket, functions, from, to, convert_to))
+        });
+
+    let (bucket, functions, from, to, convert_to) = match validated {
+        Ok(v) => v,
+        Err(e) => {
+            let err_response = reply::json(&serde_json::json!({"error": e}));
+
+            return Ok(auth.respond(reply::with_status(err_response, StatusCode::BAD_REQUEST)));
+        }
+    };
+
+    // Buckets are aligned to midnight UTC. Every bucket overlapping the range
+    // is returned, including empty ones, which have a count of 0 and null for
+    // everything else.
+    //
+    // With convert_to, values are converted before they are aggregated, and
+    // readings in units that cannot be converted are left out.
+    let (value, conversion_join) = match convert_to {
+        Some(_) => (
+            "(readings.value * c.scale + c.shift)",
+            r#"JOIN UNNEST($6::text[], $7::float8[], $8::float8[]) AS c(unit, scale, shift)
+                ON c.unit = readings.unit"#,
+        ),
+        None => ("readings.value", ""),
+    };
+    let conversions = convert_to
+        .map(|target| target.conversions_from_all())
+        .unwrap_or_default();
+
+    let aggregates = functions
+        .iter()
+        .map(|f| f.sql(value))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let result = sqlx::query(&format!(
+        r#"
+        WITH buckets AS (
+            SELECT generate_series(
+                date_bin($4, $3, TIMESTAMPTZ '2000-01-01 00:00:00+00'),
+                $5 - INTERVAL '1 microsecond',
+                $4
+            ) AS bucket_start
+        ),
+        aggregates AS (
+            SELECT date_bin($4, readings.recorded_at, TIMESTAMPTZ '2000-01-01 00:00:00+00') AS bucket_start,
+                {aggregates}
+            FROM readings
+            {conversion_join}
+            WHERE readings.api_key_id = $1
+                AND readings.sensor_id = $2
+                AND readings.recorded_at >= $3
+                AND readings.recorded_at < $5
+            GROUP BY 1
+        )
+        SELECT *
+        FROM buckets
+        LEFT JOIN aggregates USING (bucket_start)
+        ORDER BY bucket_start
+        "#
+    ))
+    .bind(api_key.id)
+    .bind(&params.sensor_id)
+    .bind(from)
+    .bind(bucket.0)
+    .bind(to)
+    .bind(conversions.iter().map(|(u, _, _)| *u).collect::<Vec<_>>())
+    .bind(conversions.iter().map(|(_, s, _)| *s).collect::<Vec<_>>())
+    .bind(conversions.iter().map(|(_, _, o)| *o).collect::<Vec<_>>())
+    .fetch_all(&*db)
+    .await
+    .and_then(|rows| {
+        rows.iter()
+            .map(|row| {
+                let mut point = serde_json::Map::new();
+                point.insert(
+                    "start".to_string(),
+                    serde_json::json!(row.try_get::<DateTime<Utc>, _>("bucket_start")?),
+                );
+
+                for f in &functions {
+                    let value = match f {
+                        AggregateFn::Count => {
+                            serde_json::json!(row.try_get::<Option<i64>, _>("count")?.unwrap_or(0))
+                        }
+                        _ => serde_json::json!(row.try_get::<Option<f64>, _>(f.as_str())?),
+                    };
+                    point.insert(f.as_str().to_string(), value);
+                }
+
+                Ok(serde_json::Value::Object(point))
+            })
+            .collect::<Result<Vec<_>, sqlx::Error>>()
+    });
+
+    let response = match result {
+        Ok(buckets) => {
+            let response = serde_json::json!({
+                "status": "success",
+                "sensor_id": params.sensor_id,
+                "bucket": params.bucket,
+                "unit": convert_to.map(|u| u.symbol),
+                "from": from,
+                "to": to,
+                "buckets": buckets
+            });
+
+            reply::with_status(reply::json(&response), StatusCode::OK)
+        }
+        Err(e) => {
+            tracing::error!("Failed to aggregate readings: {:?}", e);
+
+            let err_response =
+                reply::json(&serde_json::json!({"error": "Failed to aggregate readings"}));
+
+            reply::with_status(err_response, StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    };
+
+    Ok(auth.respond(response))
+}
+
+// The readings are already stored, so a failed evaluation is logged rather
+// than failing the request.
+async fn evaluate_alerts(db: &DbPool, webhooks: &Webhooks, api_key_id: Uuid, readings: &[Reading]) {
+    match alerts::evaluate(db, api_key_id, readings).await {
+        Ok(events) => {
+            for event in events {
+                match event {
+                    AlertEvent::Fired(alert) => {
+                        tracing::info!(
+                            "Alert {} fired: rule={}, sensor={}, value={}",
+                            alert.id,
+                            alert.rule_id,
+                            alert.sensor_id,
+                            alert.last_value
+                        );
+                        webhooks.alert_event(WebhookEvent::AlertFired, &alert);
+                    }
+                    AlertEvent::Resolved(alert) => {
+                        tracing::info!(
+                            "Alert {} resolved: rule={}, sensor={}, value={}",
+                            alert.id,
+                            alert.rule_id,
+                            alert.sensor_id,
+                            alert.last_value
+                        );
+                        webhooks.alert_event(WebhookEvent::AlertResolved, &alert);
+                    }
+                }
+            }
         }
+        Err(e) => tracing::error!("Failed to evaluate alert rules: {:?}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::feed::FeedBackend;
+    use crate::middleware::validation;
+    use crate::models::Endpoint;
+    use crate::sensors::UnknownSensors;
+    use crate::test_support::{authenticate, create_api_key, into_json};
+    use chrono::{DurationRound, SubsecRound};
+    use serde_json::{Value, json};
+    use sqlx::PgPool;
+    use std::sync::Arc;
+
+    const ALLOW_ALL: SensorPolicy = SensorPolicy {
+        enforce: false,
+        unknown: UnknownSensors::Allow,
+    };
+
+    async fn submit_batch(db: &DbPool, key: &str, items: Vec<Value>) -> (StatusCode, Value) {
+        let auth = authenticate(db, key, Endpoint::SubmitReadingBatch).await;
+        let feed = ReadingFeed::new(FeedBackend::Memory, db.clone(), 5);
+        let Ok(reply) = submit_reading_batch(auth, db.clone(), feed, ALLOW_ALL, items).await;
+
+        into_json(reply).await
+    }
+
+    async fn stored_readings(db: &DbPool, api_key_id: Uuid) -> i64 {
+        sqlx::query_scalar("SELECT COUNT(*) FROM readings WHERE api_key_id = $1")
+            .bind(api_key_id)
+            .fetch_one(&**db)
+            .await
+            .unwrap()
+    }
+
+    #[sqlx::test]
+    async fn batch_stores_valid_items_and_reports_each_rejection(pool: PgPool) {
+        let db = Arc::new(pool);
+        let (id, key) = create_api_key(&db).await;
+
+        let (status, body) = submit_batch(
+            &db,
+            &key,
+            vec![
+                json!({"sensor_id": "s1", "value": 20.5, "unit": "C"}),
+                json!({"sensor_id": "", "value": 1.0, "unit": "C"}),
+                json!({"sensor_id": "s1", "value": "warm", "unit": "C"}),
+                json!({"sensor_id": "s2", "value": 40.0, "unit": "%"}),
+            ],
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(body["status"], "partial");
+        assert_eq!(body["accepted"], 2);
+        assert_eq!(body["rejected"], 2);
+        let statuses: Vec<&str> = body["results"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|r| r["status"].as_str().unwrap())
+            .collect();
+        assert_eq!(statuses, ["accepted", "rejected", "rejected", "accepted"]);
+        assert!(
+            body["results"][1]["error"]
+                .as_str()
+                .unwrap()
+                .starts_with("sensor_id:")
+        );
+        assert!(
+            body["results"][2]["error"]
+                .as_str()
+                .unwrap()
+                .starts_with("Invalid reading")
+        );
+        assert_eq!(stored_readings(&db, id).await, 2);
+    }
+
+    #[sqlx::test]
+    async fn batch_with_no_valid_items_is_rejected(pool: PgPool) {
+        let db = Arc::new(pool);
+        let (id, key) = create_api_key(&db).await;
+
+        let (status, body) =
+            submit_batch(&db, &key, vec![json!({"sensor_id": "s1", "unit": "C"})]).await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["accepted"], 0);
+        assert_eq!(stored_readings(&db, id).await, 0);
+    }
+
+    #[sqlx::test]
+    async fn batch_that_would_exceed_the_quota_stores_nothing(pool: PgPool) {
+        let db = Arc::new(pool);
+        let (id, key) = create_api_key(&db).await;
+        sqlx::query("UPDATE api_keys SET quota_limit = 2 WHERE id = $1")
+            .bind(id)
+            .execute(&*db)
+            .await
+            .unwrap();
+        let reading = json!({"sensor_id": "s1", "value": 1.0, "unit": "C"});
+
+        let (status, _) = submit_batch(&db, &key, vec![reading.clone(); 3]).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(stored_readings(&db, id).await, 0);
+
+        let (status, _) = submit_batch(&db, &key, vec![reading; 2]).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(stored_readings(&db, id).await, 2);
+    }
+
+    #[tokio::test]
+    async fn batch_size_is_bounded() {
+        let filter = validation::validate_reading_batch(2);
+        let reading = json!({"sensor_id": "s1", "value": 1.0, "unit": "C"});
+        let send = |items: Vec<Value>| warp::test::request().json(&items).filter(&filter);
+
+        assert!(send(vec![]).await.is_err());
+        assert_eq!(send(vec![reading.clone(); 2]).await.unwrap().len(), 2);
+        assert!(send(vec![reading; 3]).await.is_err());
+    }
+
+    #[sqlx::test]
+    async fn reading_keeps_the_device_timestamp(pool: PgPool) {
+        let db = Arc::new(pool);
+        let (id, key) = create_api_key(&db).await;
+        let recorded_at = (Utc::now() - Duration::hours(2)).trunc_subsecs(3);
+        let reading: ReadingRequest = serde_json::from_value(json!({
+            "sensor_id": "s1",
+            "value": 20.5,
+            "unit": "C",
+            "recorded_at": recorded_at.timestamp_millis(),
+        }))
+        .unwrap();
+
+        let auth = authenticate(&db, &key, Endpoint::SubmitReading).await;
+        let feed = ReadingFeed::new(FeedBackend::Memory, db.clone(), 5);
+        let Ok(reply) = submit_reading(auth, db.clone(), feed, ALLOW_ALL, reading).await;
+        let (status, body) = into_json(reply).await;
+
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(body["data"]["recorded_at"], json!(recorded_at));
+        let stored: DateTime<Utc> =
+            sqlx::query_scalar("SELECT recorded_at FROM readings WHERE api_key_id = $1")
+                .bind(id)
+                .fetch_one(&*db)
+                .await
+                .unwrap();
+        assert_eq!(stored, recorded_at);
+    }
+
+    // Inserts readings `minutes_ago` old; equal ages share a timestamp.
+    async fn insert_readings(db: &DbPool, api_key_id: Uuid, minutes_ago: &[i64]) {
+        let now = Utc::now().trunc_subsecs(0);
+
+        for (i, minutes) in minutes_ago.iter().enumerate() {
+            sqlx::query(
+                r#"
+                INSERT INTO readings (id, api_key_id, sensor_id, value, unit, raw_unit, recorded_at)
+                VALUES ($1, $2, 's1', $3, '°C', 'C', $4)
+                "#,
+            )
+            .bind(Uuid::new_v4())
+            .bind(api_key_id)
+            .bind(i as f64)
+            .bind(now - Duration::minutes(*minutes))
+            .execute(&**db)
+            .await
+            .unwrap();
+        }
+    }
+
+    async fn list(db: &DbPool, key: &str, query: Value) -> (StatusCode, Value) {
+        let auth = authenticate(db, key, Endpoint::ListReadings).await;
+        let params: ReadingsParams = serde_json::from_value(query).unwrap();
+        let Ok(reply) = get_readings(auth, db.clone(), params).await;
+
+        into_json(reply).await
+    }
+
+    // Follows next_cursor to the end, returning the values in page order.
+    async fn all_pages(db: &DbPool, key: &str, order: &str) -> Vec<Vec<f64>> {
+        let mut pages = Vec::new();
+        let mut cursor = Value::Null;
+
+        loop {
+            let (status, body) = list(
+                db,
+                key,
+                json!({"order": order, "limit": 2, "cursor": cursor}),
+            )
+            .await;
+            assert_eq!(status, StatusCode::OK);
+            pages.push(
+                body["readings"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|r| r["value"].as_f64().unwrap())
+                    .collect(),
+            );
+
+            cursor = body["next_cursor"].clone();
+            if cursor.is_null() {
+                return pages;
+            }
+        }
+    }
+
+    #[sqlx::test]
+    async fn cursor_pages_cover_every_reading_once(pool: PgPool) {
+        let db = Arc::new(pool);
+        let (id, key) = create_api_key(&db).await;
+        // Values 1 and 2 share a timestamp, so a page boundary falls between
+        // two readings that only the id can order.
+        insert_readings(&db, id, &[50, 40, 40, 30, 20]).await;
+
+        let desc = all_pages(&db, &key, "desc").await;
+        let asc = all_pages(&db, &key, "asc").await;
+
+        assert_eq!(desc.len(), 3);
+        assert_eq!(desc[2].len(), 1);
+        let mut seen: Vec<f64> = desc.concat();
+        assert_eq!(seen[0], 4.0);
+        assert_eq!(seen[4], 0.0);
+        seen.sort_by(f64::total_cmp);
+        assert_eq!(seen, [0.0, 1.0, 2.0, 3.0, 4.0]);
+
+        let mut reversed = asc.concat();
+        reversed.reverse();
+        assert_eq!(reversed, desc.concat());
+    }
+
+    #[sqlx::test]
+    async fn listing_applies_filters_and_rejects_bad_paging(pool: PgPool) {
+        let db = Arc::new(pool);
+        let (id, key) = create_api_key(&db).await;
+        insert_readings(&db, id, &[50, 40, 30, 20]).await;
+
+        let (_, body) = list(&db, &key, json!({"min_value": 1.0, "max_value": 2.0})).await;
+        assert_eq!(body["count"], 2);
+        assert!(body["next_cursor"].is_null());
+
+        let (status, _) = list(&db, &key, json!({"cursor": "zz"})).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _) = list(&db, &key, json!({"limit": MAX_PAGE_SIZE + 1})).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    async fn insert_reading_at(db: &DbPool, api_key_id: Uuid, at: DateTime<Utc>, value: f64) {
+        insert_reading_in(db, api_key_id, at, value, "C").await;
+    }
+
+    async fn insert_reading_in(
+        db: &DbPool,
+        api_key_id: Uuid,
+        at: DateTime<Utc>,
+        value: f64,
+        raw_unit: &str,
+    ) {
+        sqlx::query(
+            r#"
+            INSERT INTO readings (id, api_key_id, sensor_id, value, unit, raw_unit, recorded_at)
+            VALUES ($1, $2, 's1', $3, $4, $5, $6)
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(api_key_id)
+        .bind(value)
+        .bind(units::normalize(raw_unit))
+        .bind(raw_unit)
+        .bind(at)
+        .execute(&**db)
+        .await
+        .unwrap();
+    }
+
+    async fn aggregate(db: &DbPool, key: &str, query: Value) -> (StatusCode, Value) {
+        let auth = authenticate(db, key, Endpoint::AggregateReadings).await;
+        let params: AggregateParams = serde_json::from_value(query).unwrap();
+        let Ok(reply) = get_reading_aggregates(auth, db.clone(), params).await;
+
+        into_json(reply).await
+    }
+
+    #[sqlx::test]
+    async fn empty_buckets_are_filled_in(pool: PgPool) {
+        let db = Arc::new(pool);
+        let (id, key) = create_api_key(&db).await;
+        let start = (Utc::now() - Duration::hours(5))
+            .duration_trunc(Duration::hours(1))
+            .unwrap();
+        insert_reading_at(&db, id, start + Duration::minutes(10), 1.0).await;
+        insert_reading_at(&db, id, start + Duration::minutes(50), 3.0).await;
+        insert_reading_at(&db, id, start + Duration::minutes(125), 10.0).await;
+        // Outside the range.
+        insert_reading_at(&db, id, start + Duration::hours(3), 99.0).await;
+
+        let (status, body) = aggregate(
+            &db,
+            &key,
+            json!({
+                "sensor_id": "s1",
+                "bucket": "1h",
+                "fn": "count,avg",
+                "from": start,
+                "to": start + Duration::hours(3),
+            }),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            body["buckets"],
+            json!([
+                {"start": start, "count": 2, "avg": 2.0},
+                {"start": start + Duration::hours(1), "count": 0, "avg": null},
+                {"start": start + Duration::hours(2), "count": 1, "avg": 10.0},
+            ])
+        );
+    }
+
+    #[sqlx::test]
+    async fn buckets_are_aligned_even_when_the_range_is_not(pool: PgPool) {
+        let db = Arc::new(pool);
+        let (id, key) = create_api_key(&db).await;
+        let start = (Utc::now() - Duration::hours(5))
+            .duration_trunc(Duration::hours(1))
+            .unwrap();
+        insert_reading_at(&db, id, start + Duration::minutes(10), 1.0).await;
+        insert_reading_at(&db, id, start + Duration::minutes(40), 5.0).await;
+
+        let (_, body) = aggregate(
+            &db,
+            &key,
+            json!({
+                "sensor_id": "s1",
+                "bucket": "1h",
+                "fn": "count,max",
+                "from": start + Duration::minutes(30),
+                "to": start + Duration::minutes(90),
+            }),
+        )
+        .await;
+
+        assert_eq!(
+            body["buckets"],
+            json!([
+                {"start": start, "count": 1, "max": 5.0},
+                {"start": start + Duration::hours(1), "count": 0, "max": null},
+            ])
+        );
+    }
+
+    #[sqlx::test]
+    async fn aggregate_rejects_too_many_buckets(pool: PgPool) {
+        let db = Arc::new(pool);
+        let (_, key) = create_api_key(&db).await;
+        let to = Utc::now();
+
+        let (status, _) = aggregate(
+            &db,
+            &key,
+            json!({
+                "sensor_id": "s1",
+                "bucket": "1m",
+                "from": to - Duration::minutes(MAX_BUCKETS + 1),
+                "to": to,
+            }),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[sqlx::test]
+    async fn aggregate_converts_before_aggregating(pool: PgPool) {
+        let db = Arc::new(pool);
+        let (id, key) = create_api_key(&db).await;
+        let start = (Utc::now() - Duration::hours(5))
+            .duration_trunc(Duration::hours(1))
+            .unwrap();
+        let at = start + Duration::minutes(10);
+        insert_reading_in(&db, id, at, 10.0, "celsius").await;
+        insert_reading_in(&db, id, at, 50.0, "F").await;
+        insert_reading_in(&db, id, at, 283.15, "K").await;
+        // Cannot be converted to °C, so it is left out.
+        insert_reading_in(&db, id, at, 90.0, "%").await;
+
+        let (status, body) = aggregate(
+            &db,
+            &key,
+            json!({
+                "sensor_id": "s1",
+                "bucket": "1h",
+                "fn": "count,avg",
+                "from": start,
+                "to": start + Duration::hours(1),
+                "convert_to": "c",
+            }),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["unit"], "°C");
+        assert_eq!(body["buckets"][0]["count"], 3);
+        assert!((body["buckets"][0]["avg"].as_f64().unwrap() - 10.0).abs() < 1e-9);
     }
 }
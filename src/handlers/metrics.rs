@@ -33,7 +33,7 @@ pub async fn get_metrics(db: DbPool) -> Result<impl Reply, Infallible> {
     .fetch_one(&*db)
     .await
     .ok()
-    .and_then(|r| r.avg.map(|v| v as f64));
+    .and_then(|r| r.avg);
 
     let requests_last_hour = sqlx::query!(
         r#"
@@ -104,7 +104,7 @@ pub async fn get_metrics(db: DbPool) -> Result<impl Reply, Infallible> {
     });
 
     let pool_stats = PoolStats {
-        size: db.size() as u32,
+        size: db.size(),
         num_idle: db.num_idle(),
     };
 
@@ -1,4 +1,11 @@
 pub mod admin;
+pub mod admin_costs;
+pub mod admin_limits;
+pub mod admin_tokens;
+pub mod admin_webhooks;
+pub mod alerts;
 pub mod business;
 pub mod metrics;
+pub mod sensors;
+pub mod stream;
 pub mod usage;
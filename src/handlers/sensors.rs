@@ -0,0 +1,340 @@
+use crate::db::DbPool;
+use crate::middleware::auth::AuthContext;
+use crate::middleware::validation::Validator;
+use crate::models::{CreateSensorRequest, Sensor, SensorListResponse, UpdateSensorRequest};
+use crate::units;
+use std::convert::Infallible;
+use warp::{Reply, http::StatusCode, reply};
+
+const MAX_NAME_LENGTH: usize = 255;
+const MAX_TAGS: usize = 20;
+const MAX_TAG_LENGTH: usize = 50;
+
+fn validate_name(field: &str, value: &str) -> Result<(), String> {
+    if value.trim().is_empty() {
+        return Err(format!("{} cannot be empty", field));
+    }
+    if value.len() > MAX_NAME_LENGTH {
+        return Err(format!(
+            "{} exceeds maximum length of {}",
+            field, MAX_NAME_LENGTH
+        ));
+    }
+    Ok(())
+}
+
+fn validate_tags(tags: &[String]) -> Result<(), String> {
+    if tags.len() > MAX_TAGS {
+        return Err(format!("A sensor can have at most {} tags", MAX_TAGS));
+    }
+    if tags
+        .iter()
+        .any(|t| t.trim().is_empty() || t.len() > MAX_TAG_LENGTH)
+    {
+        return Err(format!(
+            "Tags must be between 1 and {} characters",
+            MAX_TAG_LENGTH
+        ));
+    }
+    Ok(())
+}
+
+fn validate_value_bound(field: &str, value: f64) -> Result<(), String> {
+    Validator::value(value).map_err(|e| format!("{}: {}", field, e))
+}
+
+fn validate_create(body: &CreateSensorRequest) -> Result<(), String> {
+    Validator::sensor_id(&body.sensor_id).map_err(|e| format!("sensor_id: {}", e))?;
+
+    if let Some(name) = &body.name {
+        validate_name("name", name)?;
+    }
+    if let Some(location) = &body.location {
+        validate_name("location", location)?;
+    }
+    if let Some(tags) = &body.tags {
+        validate_tags(tags)?;
+    }
+    if let Some(unit) = &body.unit {
+        Validator::unit(unit).map_err(|e| format!("unit: {}", e))?;
+    }
+    if let Some(min) = body.min_value {
+        validate_value_bound("min_value", min)?;
+    }
+    if let Some(max) = body.max_value {
+        validate_value_bound("max_value", max)?;
+    }
+    if let (Some(min), Some(max)) = (body.min_value, body.max_value)
+        && min > max
+    {
+        return Err("min_value cannot be greater than max_value".to_string());
+    }
+
+    Ok(())
+}
+
+fn validate_update(body: &UpdateSensorRequest) -> Result<(), String> {
+    if body.name.is_none()
+        && body.location.is_none()
+        && body.tags.is_none()
+        && body.unit.is_none()
+        && body.min_value.is_none()
+        && body.max_value.is_none()
+    {
+        return Err("No fields to update".to_string());
+    }
+
+    if let Some(name) = &body.name {
+        validate_name("name", name)?;
+    }
+    if let Some(Some(location)) = &body.location {
+        validate_name("location", location)?;
+    }
+    if let Some(tags) = &body.tags {
+        validate_tags(tags)?;
+    }
+    if let Some(Some(unit)) = &body.unit {
+        Validator::unit(unit).map_err(|e| format!("unit: {}", e))?;
+    }
+    if let Some(Some(min)) = body.min_value {
+        validate_value_bound("min_value", min)?;
+    }
+    if let Some(Some(max)) = body.max_value {
+        validate_value_bound("max_value", max)?;
+    }
+
+    Ok(())
+}
+
+fn is_range_violation(e: &sqlx::Error) -> bool {
+    matches!(e, sqlx::Error::Database(db) if db.constraint() == Some("sensors_value_range"))
+}
+
+pub async fn list_sensors(auth: AuthContext, db: DbPool) -> Result<impl Reply, Infallible> {
+    let result = sqlx::query_as::<_, Sensor>(
+        "SELECT * FROM sensors WHERE api_key_id = $1 ORDER BY sensor_id",
+    )
+    .bind(auth.api_key.id)
+    .fetch_all(&*db)
+    .await;
+
+    let response = match result {
+        Ok(sensors) => {
+            let response = SensorListResponse { sensors };
+            reply::with_status(reply::json(&response), StatusCode::OK)
+        }
+        Err(e) => {
+            tracing::error!("Failed to list sensors: {:?}", e);
+            reply::with_status(
+                reply::json(&serde_json::json!({
+                    "error": "Failed to list sensors"
+                })),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        }
+    };
+
+    Ok(auth.respond(response))
+}
+
+pub async fn create_sensor(
+    auth: AuthContext,
+    db: DbPool,
+    body: CreateSensorRequest,
+) -> Result<impl Reply, Infallible> {
+    if let Err(e) = validate_create(&body) {
+        return Ok(auth.respond(reply::with_status(
+            reply::json(&serde_json::json!({
+                "error": e
+            })),
+            StatusCode::BAD_REQUEST,
+        )));
+    }
+
+    let result = sqlx::query_as::<_, Sensor>(
+        r#"
+        INSERT INTO sensors (api_key_id, sensor_id, name, location, tags, unit, min_value, max_value)
+        VALUES ($1, $2, COALESCE($3, $2), $4, COALESCE($5, '{}'), $6, $7, $8)
+        ON CONFLICT (api_key_id, sensor_id) DO NOTHING
+        RETURNING *
+        "#,
+    )
+    .bind(auth.api_key.id)
+    .bind(&body.sensor_id)
+    .bind(body.name.as_deref().map(str::trim))
+    .bind(body.location.as_deref().map(str::trim))
+    .bind(&body.tags)
+    .bind(body.unit.as_deref().map(units::normalize))
+    .bind(body.min_value)
+    .bind(body.max_value)
+    .fetch_optional(&*db)
+    .await;
+
+    let response = match result {
+        Ok(Some(sensor)) => reply::with_status(reply::json(&sensor), StatusCode::CREATED),
+        Ok(None) => reply::with_status(
+            reply::json(&serde_json::json!({
+                "error": format!("Sensor {} is already registered", body.sensor_id)
+            })),
+            StatusCode::CONFLICT,
+        ),
+        Err(e) => {
+            tracing::error!("Failed to create sensor: {:?}", e);
+            reply::with_status(
+                reply::json(&serde_json::json!({
+                    "error": "Failed to create sensor"
+                })),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        }
+    };
+
+    Ok(auth.respond(response))
+}
+
+pub async fn get_sensor(
+    sensor_id: String,
+    auth: AuthContext,
+    db: DbPool,
+) -> Result<impl Reply, Infallible> {
+    let result = sqlx::query_as::<_, Sensor>(
+        "SELECT * FROM sensors WHERE api_key_id = $1 AND sensor_id = $2",
+    )
+    .bind(auth.api_key.id)
+    .bind(&sensor_id)
+    .fetch_optional(&*db)
+    .await;
+
+    let response = match result {
+        Ok(Some(sensor)) => reply::with_status(reply::json(&sensor), StatusCode::OK),
+        Ok(None) => reply::with_status(
+            reply::json(&serde_json::json!({
+                "error": "Sensor not found"
+            })),
+            StatusCode::NOT_FOUND,
+        ),
+        Err(e) => {
+            tracing::error!("Failed to fetch sensor: {:?}", e);
+            reply::with_status(
+                reply::json(&serde_json::json!({
+                    "error": "Failed to fetch sensor"
+                })),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        }
+    };
+
+    Ok(auth.respond(response))
+}
+
+pub async fn update_sensor(
+    sensor_id: String,
+    auth: AuthContext,
+    db: DbPool,
+    body: UpdateSensorRequest,
+) -> Result<impl Reply, Infallible> {
+    if let Err(e) = validate_update(&body) {
+        return Ok(auth.respond(reply::with_status(
+            reply::json(&serde_json::json!({
+                "error": e
+            })),
+            StatusCode::BAD_REQUEST,
+        )));
+    }
+
+    let result = sqlx::query_as::<_, Sensor>(
+        r#"
+        UPDATE sensors
+        SET name = COALESCE($3, name),
+            location = CASE WHEN $4 THEN $5 ELSE location END,
+            tags = COALESCE($6, tags),
+            unit = CASE WHEN $7 THEN $8 ELSE unit END,
+            min_value = CASE WHEN $9 THEN $10 ELSE min_value END,
+            max_value = CASE WHEN $11 THEN $12 ELSE max_value END
+        WHERE api_key_id = $1 AND sensor_id = $2
+        RETURNING *
+        "#,
+    )
+    .bind(auth.api_key.id)
+    .bind(&sensor_id)
+    .bind(body.name.as_deref().map(str::trim))
+    .bind(body.location.is_some())
+    .bind(body.location.flatten().as_deref().map(str::trim))
+    .bind(&body.tags)
+    .bind(body.unit.is_some())
+    .bind(body.unit.flatten().as_deref().map(units::normalize))
+    .bind(body.min_value.is_some())
+    .bind(body.min_value.flatten())
+    .bind(body.max_value.is_some())
+    .bind(body.max_value.flatten())
+    .fetch_optional(&*db)
+    .await;
+
+    let response = match result {
+        Ok(Some(sensor)) => reply::with_status(reply::json(&sensor), StatusCode::OK),
+        Ok(None) => reply::with_status(
+            reply::json(&serde_json::json!({
+                "error": "Sensor not found"
+            })),
+            StatusCode::NOT_FOUND,
+        ),
+        // The new bounds are only known to be inconsistent once combined
+        // with the stored ones.
+        Err(e) if is_range_violation(&e) => reply::with_status(
+            reply::json(&serde_json::json!({
+                "error": "min_value cannot be greater than max_value"
+            })),
+            StatusCode::BAD_REQUEST,
+        ),
+        Err(e) => {
+            tracing::error!("Failed to update sensor: {:?}", e);
+            reply::with_status(
+                reply::json(&serde_json::json!({
+                    "error": "Failed to update sensor"
+                })),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        }
+    };
+
+    Ok(auth.respond(response))
+}
+
+// Readings already stored for the sensor are kept.
+pub async fn delete_sensor(
+    sensor_id: String,
+    auth: AuthContext,
+    db: DbPool,
+) -> Result<impl Reply, Infallible> {
+    let result = sqlx::query("DELETE FROM sensors WHERE api_key_id = $1 AND sensor_id = $2")
+        .bind(auth.api_key.id)
+        .bind(&sensor_id)
+        .execute(&*db)
+        .await;
+
+    let response = match result {
+        Ok(res) if res.rows_affected() == 0 => reply::with_status(
+            reply::json(&serde_json::json!({
+                "error": "Sensor not found"
+            })),
+            StatusCode::NOT_FOUND,
+        ),
+        Ok(_) => reply::with_status(
+            reply::json(&serde_json::json!({
+                "message": "Sensor deleted successfully"
+            })),
+            StatusCode::OK,
+        ),
+        Err(e) => {
+            tracing::error!("Failed to delete sensor: {:?}", e);
+            reply::with_status(
+                reply::json(&serde_json::json!({
+                    "error": "Failed to delete sensor"
+                })),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        }
+    };
+
+    Ok(auth.respond(response))
+}
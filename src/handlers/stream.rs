@@ -0,0 +1,99 @@
+use crate::feed::{FeedEvent, ReadingFeed, Subscription};
+use crate::middleware::auth::AuthContext;
+use crate::models::ReadingStreamParams;
+use futures_util::{SinkExt, StreamExt, stream};
+use std::convert::Infallible;
+use warp::sse::Event;
+use warp::ws::{Message, WebSocket, Ws};
+use warp::{Reply, http::StatusCode, reply};
+
+fn too_many_streams(feed: &ReadingFeed) -> reply::WithStatus<reply::Json> {
+    reply::with_status(
+        reply::json(&serde_json::json!({
+            "error": format!(
+                "This API key already has {} streams open",
+                feed.max_streams_per_key()
+            )
+        })),
+        StatusCode::TOO_MANY_REQUESTS,
+    )
+}
+
+// Server-Sent Events: each new reading is a `reading` event whose data is the
+// reading as GET /readings returns it.
+pub async fn stream_readings(
+    auth: AuthContext,
+    feed: ReadingFeed,
+    params: ReadingStreamParams,
+) -> Result<impl Reply, Infallible> {
+    let Some(subscription) = feed.subscribe(auth.api_key.id, params.sensor_id) else {
+        return Ok(auth.respond(too_many_streams(&feed)));
+    };
+
+    tracing::info!("Opened readings stream for API key ID: {}", auth.api_key.id);
+
+    let events = stream::unfold(subscription, |mut subscription| async move {
+        let event = match subscription.next().await? {
+            FeedEvent::Reading(reading) => Event::default()
+                .id(reading.id.to_string())
+                .event("reading")
+                .json_data(&*reading),
+            FeedEvent::Lagged => Event::default().event("lagged").json_data(
+                serde_json::json!({"error": "Stream fell behind; some readings were skipped"}),
+            ),
+        };
+
+        Some((event, subscription))
+    });
+
+    Ok(auth.respond(warp::sse::reply(warp::sse::keep_alive().stream(events))))
+}
+
+// The same feed over a WebSocket, as text messages of the form
+// {"event": "reading", "data": {...}}. Messages from the client are ignored.
+pub async fn readings_socket(
+    ws: Ws,
+    auth: AuthContext,
+    feed: ReadingFeed,
+    params: ReadingStreamParams,
+) -> Result<impl Reply, Infallible> {
+    let Some(subscription) = feed.subscribe(auth.api_key.id, params.sensor_id) else {
+        return Ok(auth.respond(too_many_streams(&feed)));
+    };
+
+    tracing::info!("Opened readings socket for API key ID: {}", auth.api_key.id);
+
+    Ok(auth.respond(ws.on_upgrade(move |socket| forward(socket, subscription))))
+}
+
+async fn forward(socket: WebSocket, mut subscription: Subscription) {
+    let (mut sender, mut receiver) = socket.split();
+
+    loop {
+        tokio::select! {
+            message = receiver.next() => match message {
+                Some(Ok(message)) if !message.is_close() => {}
+                _ => break,
+            },
+            event = subscription.next() => {
+                let message = match event {
+                    Some(FeedEvent::Reading(reading)) => serde_json::json!({
+                        "event": "reading",
+                        "data": &*reading,
+                    }),
+                    Some(FeedEvent::Lagged) => serde_json::json!({
+                        "event": "lagged",
+                        "data": {"error": "Stream fell behind; some readings were skipped"},
+                    }),
+                    None => break,
+                };
+
+                if sender.send(Message::text(message.to_string())).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+
+    let _ = sender.close().await;
+}
@@ -1,11 +1,38 @@
 use crate::db::DbPool;
-use crate::models::{DailyUsage, MonthlyReport, UsageStats};
+use crate::middleware::auth::AuthContext;
+use crate::models::{
+    ApiKey, DailyUsage, MonthlyReport, QuotaHistoryResponse, QuotaPeriod, QuotaPeriodUsage,
+    QuotaStatus, UsageStats,
+};
 use chrono::{Datelike, NaiveDate, TimeZone, Utc};
 use serde::Deserialize;
 use std::convert::Infallible;
+use uuid::Uuid;
 use warp::{Reply, http::StatusCode, reply};
 
-pub async fn get_usage_stats(key: String, db: DbPool) -> Result<impl Reply, Infallible> {
+pub async fn get_usage_stats(id: String, db: DbPool) -> Result<impl Reply, Infallible> {
+    let uuid = match Uuid::parse_str(&id) {
+        Ok(u) => u,
+        Err(_) => {
+            return Ok(reply::with_status(
+                reply::json(&serde_json::json!({
+                    "error": "Invalid UUID format"
+                })),
+                StatusCode::BAD_REQUEST,
+            ));
+        }
+    };
+
+    Ok(usage_stats_reply(uuid, &db).await)
+}
+
+pub async fn get_own_usage(auth: AuthContext, db: DbPool) -> Result<impl Reply, Infallible> {
+    let response = usage_stats_reply(auth.api_key.id, &db).await;
+
+    Ok(auth.respond(response))
+}
+
+async fn usage_stats_reply(uuid: Uuid, db: &DbPool) -> reply::WithStatus<reply::Json> {
     let stats_query = sqlx::query!(
         r#"
         SELECT
@@ -13,15 +40,20 @@ pub async fn get_usage_stats(key: String, db: DbPool) -> Result<impl Reply, Infa
             COUNT(r.id) as "total_requests!",
             COUNT(CASE WHEN DATE(r.created_at) = CURRENT_DATE THEN 1 END) as "requests_today!",
             COUNT(CASE WHEN DATE_TRUNC('month', r.created_at) = DATE_TRUNC('month', CURRENT_DATE) THEN 1 END) as "requests_this_month!",
-            MAX(r.created_at) as last_used
+            MAX(r.created_at) as last_used,
+            ak.usage_count,
+            ak.quota_limit,
+            ak.quota_period as "quota_period: QuotaPeriod",
+            ak.quota_anchor,
+            ak.period_started_at
         FROM api_keys ak
         LEFT JOIN requests r ON ak.id = r.api_key_id
-        WHERE ak.key = $1
+        WHERE ak.id = $1
         GROUP BY ak.id, ak.name
         "#,
-        key
+        uuid
     )
-    .fetch_optional(&*db)
+    .fetch_optional(&**db)
     .await;
 
     match stats_query {
@@ -32,25 +64,102 @@ pub async fn get_usage_stats(key: String, db: DbPool) -> Result<impl Reply, Infa
                 requests_today: record.requests_today,
                 requests_this_month: record.requests_this_month,
                 last_used: record.last_used,
+                quota: QuotaStatus::new(
+                    record.quota_period,
+                    record.quota_anchor,
+                    record.period_started_at,
+                    record.usage_count,
+                    record.quota_limit,
+                    Utc::now(),
+                ),
             };
 
-            Ok(reply::with_status(reply::json(&stats), StatusCode::OK))
+            reply::with_status(reply::json(&stats), StatusCode::OK)
         }
 
-        Ok(None) => Ok(reply::with_status(
+        Ok(None) => reply::with_status(
             reply::json(&serde_json::json!({
                 "error": "API key not found"
             })),
             StatusCode::NOT_FOUND,
-        )),
+        ),
 
         Err(e) => {
             tracing::error!("Failed to get usage stats: {:?}", e);
-            Ok(reply::with_status(
+            reply::with_status(
                 reply::json(&serde_json::json!({
                     "error": "Failed to retrieve usage statistics"
                 })),
                 StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        }
+    }
+}
+
+pub async fn get_quota_history(id: String, db: DbPool) -> Result<impl Reply, Infallible> {
+    let uuid = match Uuid::parse_str(&id) {
+        Ok(u) => u,
+        Err(_) => {
+            return Ok(reply::with_status(
+                reply::json(&serde_json::json!({
+                    "error": "Invalid UUID format"
+                })),
+                StatusCode::BAD_REQUEST,
+            ));
+        }
+    };
+
+    let api_key = match sqlx::query_as::<_, ApiKey>("SELECT * FROM api_keys WHERE id = $1")
+        .bind(uuid)
+        .fetch_optional(&*db)
+        .await
+    {
+        Ok(Some(k)) => k,
+        Ok(None) => {
+            return Ok(reply::with_status(
+                reply::json(&serde_json::json!({
+                    "error": "API key not found"
+                })),
+                StatusCode::NOT_FOUND,
+            ));
+        }
+        Err(e) => {
+            tracing::error!("Failed to load API key for quota history: {:?}", e);
+            return Ok(reply::with_status(
+                reply::json(&serde_json::json!({
+                    "error": "Failed to retrieve quota history"
+                })),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ));
+        }
+    };
+
+    let result = sqlx::query_as::<_, QuotaPeriodUsage>(
+        r#"
+        SELECT * FROM quota_period_usage
+        WHERE api_key_id = $1
+        ORDER BY period_start DESC
+        "#,
+    )
+    .bind(uuid)
+    .fetch_all(&*db)
+    .await;
+
+    match result {
+        Ok(periods) => {
+            let response = QuotaHistoryResponse {
+                current: api_key.quota_status(Utc::now()),
+                periods,
+            };
+            Ok(reply::with_status(reply::json(&response), StatusCode::OK))
+        }
+        Err(e) => {
+            tracing::error!("Failed to get quota history: {:?}", e);
+            Ok(reply::with_status(
+                reply::json(&serde_json::json!({
+                    "error": "Failed to retrieve quota history"
+                })),
+                StatusCode::INTERNAL_SERVER_ERROR,
             ))
         }
     }
@@ -70,11 +179,21 @@ struct DailyCount {
 }
 
 pub async fn get_monthly_report(
-    key: String,
+    id: String,
     params: ReportParams,
     db: DbPool,
 ) -> Result<impl Reply, Infallible> {
-    let api_key = match sqlx::query!("SELECT id, name FROM api_keys WHERE key = $1", key)
+    let uuid = match Uuid::parse_str(&id) {
+        Ok(u) => u,
+        Err(_) => {
+            let err = reply::json(&serde_json::json!({
+                "error": "Invalid UUID format"
+            }));
+            return Ok(reply::with_status(err, StatusCode::BAD_REQUEST).into_response());
+        }
+    };
+
+    let api_key = match sqlx::query!("SELECT id, name FROM api_keys WHERE id = $1", uuid)
         .fetch_optional(&*db)
         .await
     {
@@ -1,10 +1,33 @@
+// The combined warp filter type nests deeper than the default limit allows
+// in release builds.
+#![recursion_limit = "256"]
+
+mod alerts;
 mod db;
+mod feed;
 mod handlers;
 mod middleware;
 mod models;
+mod mqtt;
 mod openapi;
+mod secrets;
+mod sensors;
+mod tasks;
+#[cfg(test)]
+mod test_support;
+mod units;
+mod webhooks;
 
-use crate::middleware::rate_limiter::RateLimiter;
+use crate::feed::{FeedBackend, ReadingFeed};
+use crate::middleware::admin_auth::with_admin;
+use crate::middleware::auth::with_api_key;
+use crate::middleware::metering::{BillableStatuses, Metering};
+use crate::middleware::rate_limiter::{RateLimitBackend, RateLimiter};
+use crate::models::{AdminRole, Endpoint};
+use crate::mqtt::MqttIngest;
+use crate::sensors::{SensorPolicy, UnknownSensors};
+use crate::tasks::webhook_delivery::DeliveryConfig;
+use crate::webhooks::Webhooks;
 use anyhow::Result;
 use std::env;
 use warp::Filter;
@@ -21,6 +44,95 @@ async fn main() -> Result<()> {
         .unwrap_or_else(|_| "3030".to_string())
         .parse()
         .expect("SERVER_PORT must be a valid u16");
+    let rotation_grace_seconds: i64 = env::var("KEY_ROTATION_GRACE_SECONDS")
+        .unwrap_or_else(|_| "86400".to_string())
+        .parse()
+        .ok()
+        .filter(|n| (0..=handlers::admin::MAX_GRACE_PERIOD_SECONDS).contains(n))
+        .expect("KEY_ROTATION_GRACE_SECONDS must be between 0 and 2592000 seconds");
+    let expiry_sweep_seconds: u64 = env::var("KEY_EXPIRY_SWEEP_INTERVAL_SECONDS")
+        .unwrap_or_else(|_| "60".to_string())
+        .parse()
+        .ok()
+        .filter(|n| *n >= 1)
+        .expect("KEY_EXPIRY_SWEEP_INTERVAL_SECONDS must be a positive number of seconds");
+    let rate_limit_backend = RateLimitBackend::parse(
+        &env::var("RATE_LIMIT_BACKEND").unwrap_or_else(|_| "memory".to_string()),
+    )
+    .expect("RATE_LIMIT_BACKEND must be either memory or postgres");
+    let rate_limit_fail_open: bool = env::var("RATE_LIMIT_FAIL_OPEN")
+        .unwrap_or_else(|_| "false".to_string())
+        .parse()
+        .expect("RATE_LIMIT_FAIL_OPEN must be true or false");
+    let rate_limit_max_keys: usize = env::var("RATE_LIMIT_MAX_TRACKED_KEYS")
+        .unwrap_or_else(|_| "100000".to_string())
+        .parse()
+        .expect("RATE_LIMIT_MAX_TRACKED_KEYS must be a valid number");
+    let rate_limit_eviction_seconds: u64 = env::var("RATE_LIMIT_EVICTION_INTERVAL_SECONDS")
+        .unwrap_or_else(|_| "60".to_string())
+        .parse()
+        .ok()
+        .filter(|n| *n >= 1)
+        .expect("RATE_LIMIT_EVICTION_INTERVAL_SECONDS must be a positive number of seconds");
+    let max_batch_size: usize = env::var("READINGS_BATCH_MAX_SIZE")
+        .unwrap_or_else(|_| "500".to_string())
+        .parse()
+        .ok()
+        .filter(|n| *n >= 1)
+        .expect("READINGS_BATCH_MAX_SIZE must be a positive number");
+    let sensor_policy = SensorPolicy {
+        enforce: env::var("ENFORCE_SENSOR_SPECS")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .expect("ENFORCE_SENSOR_SPECS must be true or false"),
+        unknown: UnknownSensors::parse(
+            &env::var("UNKNOWN_SENSORS").unwrap_or_else(|_| "allow".to_string()),
+        )
+        .expect("UNKNOWN_SENSORS must be one of allow, register or reject"),
+    };
+    let feed_backend = FeedBackend::parse(
+        &env::var("READINGS_STREAM_BACKEND").unwrap_or_else(|_| "memory".to_string()),
+    )
+    .expect("READINGS_STREAM_BACKEND must be either memory or postgres");
+    let max_streams_per_key: i32 = env::var("READINGS_STREAM_MAX_PER_KEY")
+        .unwrap_or_else(|_| "5".to_string())
+        .parse()
+        .ok()
+        .filter(|n| *n >= 1)
+        .expect("READINGS_STREAM_MAX_PER_KEY must be a positive number");
+    let mqtt_listen_addr = env::var("MQTT_LISTEN_ADDR").ok().filter(|a| !a.is_empty());
+    let default_max_concurrent: i32 = env::var("DEFAULT_MAX_CONCURRENT_REQUESTS")
+        .unwrap_or_else(|_| "4".to_string())
+        .parse()
+        .ok()
+        .filter(|n| *n >= 1)
+        .expect("DEFAULT_MAX_CONCURRENT_REQUESTS must be a positive number");
+    let webhook_delivery = DeliveryConfig {
+        max_attempts: env::var("WEBHOOK_MAX_ATTEMPTS")
+            .unwrap_or_else(|_| "8".to_string())
+            .parse()
+            .ok()
+            .filter(|n| *n >= 1)
+            .expect("WEBHOOK_MAX_ATTEMPTS must be a positive number"),
+        timeout: std::time::Duration::from_secs(
+            env::var("WEBHOOK_TIMEOUT_SECONDS")
+                .unwrap_or_else(|_| "10".to_string())
+                .parse()
+                .expect("WEBHOOK_TIMEOUT_SECONDS must be a valid number of seconds"),
+        ),
+        poll_interval: std::time::Duration::from_secs(
+            env::var("WEBHOOK_POLL_INTERVAL_SECONDS")
+                .unwrap_or_else(|_| "5".to_string())
+                .parse()
+                .ok()
+                .filter(|n| *n >= 1)
+                .expect("WEBHOOK_POLL_INTERVAL_SECONDS must be a positive number of seconds"),
+        ),
+    };
+    let billable_statuses = BillableStatuses::parse(
+        &env::var("BILLABLE_STATUSES").unwrap_or_else(|_| "2xx".to_string()),
+    )
+    .expect("BILLABLE_STATUSES must be a comma-separated list like 2xx,404");
 
     tracing::info!("Connecting to database...");
     let db_pool = db::create_pool(&database_url).await?;
@@ -28,8 +140,72 @@ async fn main() -> Result<()> {
     tracing::info!("Running database migrations...");
     sqlx::migrate!("./migrations").run(&*db_pool).await?;
 
+    if let Ok(token) = env::var("ADMIN_BOOTSTRAP_TOKEN") {
+        middleware::admin_auth::ensure_bootstrap_owner(&db_pool, &token).await?;
+    }
+
+    tasks::key_expiry::spawn_key_expiry_sweeper(
+        db_pool.clone(),
+        std::time::Duration::from_secs(expiry_sweep_seconds),
+    );
+
     // Rate Limiter Instance
-    let rate_limiter = RateLimiter::new();
+    tracing::info!("Using {:?} rate limit backend", rate_limit_backend);
+    if rate_limit_backend == RateLimitBackend::Postgres {
+        tracing::info!(
+            "Rate limit checks that fail {} the request",
+            if rate_limit_fail_open {
+                "allow"
+            } else {
+                "reject"
+            }
+        );
+    }
+    let rate_limiter = RateLimiter::new(
+        rate_limit_backend,
+        db_pool.clone(),
+        rate_limit_max_keys,
+        rate_limit_fail_open,
+    );
+
+    tasks::rate_limit_eviction::spawn_rate_limit_evictor(
+        rate_limiter.clone(),
+        std::time::Duration::from_secs(rate_limit_eviction_seconds),
+    );
+
+    let webhooks = Webhooks::new(db_pool.clone());
+    tasks::webhook_delivery::spawn_webhook_deliverer(
+        db_pool.clone(),
+        webhooks.clone(),
+        webhook_delivery,
+    );
+
+    tracing::info!("Using {:?} readings stream backend", feed_backend);
+    let feed = ReadingFeed::new(feed_backend, db_pool.clone(), max_streams_per_key);
+    if feed_backend == FeedBackend::Postgres {
+        tasks::readings_listener::spawn_readings_listener(database_url.clone(), feed.clone());
+    }
+
+    let metering = Metering::new(
+        rate_limiter,
+        default_max_concurrent,
+        billable_statuses,
+        webhooks.clone(),
+    );
+
+    if let Some(addr) = mqtt_listen_addr {
+        let listener = tokio::net::TcpListener::bind(&addr).await?;
+        tracing::info!("MQTT listener on {}", addr);
+        mqtt::spawn_mqtt_listener(
+            listener,
+            MqttIngest {
+                db: db_pool.clone(),
+                metering: metering.clone(),
+                feed: feed.clone(),
+                sensor_policy,
+            },
+        );
+    }
 
     // health route
     let health = warp::path("health").map(|| {
@@ -42,61 +218,402 @@ async fn main() -> Result<()> {
     let admin_routes = {
         let create_key = warp::path!("admin" / "keys")
             .and(warp::post())
+            .and(with_admin(db_pool.clone(), AdminRole::Owner))
             .and(warp::body::json())
             .and(with_db(db_pool.clone()))
+            .and(with_webhooks(webhooks.clone()))
             .and_then(handlers::admin::create_api_key);
 
         let list_keys = warp::path!("admin" / "keys")
             .and(warp::get())
+            .and(with_admin(db_pool.clone(), AdminRole::Viewer))
             .and(with_db(db_pool.clone()))
             .and_then(handlers::admin::list_api_keys);
 
         let delete_key = warp::path!("admin" / "keys" / String)
             .and(warp::delete())
+            .and(with_admin(db_pool.clone(), AdminRole::Owner))
             .and(with_db(db_pool.clone()))
+            .and(with_webhooks(webhooks.clone()))
             .and_then(handlers::admin::delete_api_key);
 
+        let update_key = warp::path!("admin" / "keys" / String)
+            .and(warp::patch())
+            .and(with_admin(db_pool.clone(), AdminRole::Operator))
+            .and(warp::body::json())
+            .and(with_db(db_pool.clone()))
+            .and_then(handlers::admin::update_api_key);
+
+        let rotate_key = warp::path!("admin" / "keys" / String / "rotate")
+            .and(warp::post())
+            .and(with_admin(db_pool.clone(), AdminRole::Operator))
+            .and(warp::query::<models::RotateApiKeyParams>())
+            .and(warp::any().map(move || rotation_grace_seconds))
+            .and(with_db(db_pool.clone()))
+            .and(with_webhooks(webhooks.clone()))
+            .and_then(handlers::admin::rotate_api_key);
+
         let get_stats = warp::path!("admin" / "keys" / String / "stats")
             .and(warp::get())
+            .and(with_admin(db_pool.clone(), AdminRole::Viewer))
             .and(with_db(db_pool.clone()))
             .and_then(handlers::usage::get_usage_stats);
 
+        let get_quota_history = warp::path!("admin" / "keys" / String / "quota-history")
+            .and(warp::get())
+            .and(with_admin(db_pool.clone(), AdminRole::Viewer))
+            .and(with_db(db_pool.clone()))
+            .and_then(handlers::usage::get_quota_history);
+
         let get_report = warp::path!("admin" / "keys" / String / "report")
             .and(warp::get())
+            .and(with_admin(db_pool.clone(), AdminRole::Viewer))
             .and(warp::query::<handlers::usage::ReportParams>())
             .and(with_db(db_pool.clone()))
             .and_then(handlers::usage::get_monthly_report);
 
+        let create_token = warp::path!("admin" / "tokens")
+            .and(warp::post())
+            .and(with_admin(db_pool.clone(), AdminRole::Owner))
+            .and(warp::body::json())
+            .and(with_db(db_pool.clone()))
+            .and_then(handlers::admin_tokens::create_admin_token);
+
+        let list_tokens = warp::path!("admin" / "tokens")
+            .and(warp::get())
+            .and(with_admin(db_pool.clone(), AdminRole::Owner))
+            .and(with_db(db_pool.clone()))
+            .and_then(handlers::admin_tokens::list_admin_tokens);
+
+        let delete_token = warp::path!("admin" / "tokens" / String)
+            .and(warp::delete())
+            .and(with_admin(db_pool.clone(), AdminRole::Owner))
+            .and(with_db(db_pool.clone()))
+            .and_then(handlers::admin_tokens::delete_admin_token);
+
+        let list_limits = warp::path!("admin" / "keys" / String / "limits")
+            .and(warp::get())
+            .and(with_admin(db_pool.clone(), AdminRole::Viewer))
+            .and(with_db(db_pool.clone()))
+            .and_then(handlers::admin_limits::list_key_limits);
+
+        let set_limit = warp::path!("admin" / "keys" / String / "limits")
+            .and(warp::put())
+            .and(with_admin(db_pool.clone(), AdminRole::Operator))
+            .and(warp::body::json())
+            .and(with_db(db_pool.clone()))
+            .and_then(handlers::admin_limits::set_key_limit);
+
+        let delete_limit = warp::path!("admin" / "keys" / String / "limits" / String)
+            .and(warp::delete())
+            .and(with_admin(db_pool.clone(), AdminRole::Operator))
+            .and(with_db(db_pool.clone()))
+            .and_then(handlers::admin_limits::delete_key_limit);
+
+        let list_costs = warp::path!("admin" / "costs")
+            .and(warp::get())
+            .and(with_admin(db_pool.clone(), AdminRole::Viewer))
+            .and(with_db(db_pool.clone()))
+            .and_then(handlers::admin_costs::list_endpoint_costs);
+
+        let update_cost = warp::path!("admin" / "costs")
+            .and(warp::put())
+            .and(with_admin(db_pool.clone(), AdminRole::Owner))
+            .and(warp::body::json())
+            .and(with_db(db_pool.clone()))
+            .and_then(handlers::admin_costs::update_endpoint_cost);
+
+        let create_webhook = warp::path!("admin" / "webhooks")
+            .and(warp::post())
+            .and(with_admin(db_pool.clone(), AdminRole::Owner))
+            .and(warp::body::json())
+            .and(with_db(db_pool.clone()))
+            .and_then(handlers::admin_webhooks::create_webhook);
+
+        let list_webhooks = warp::path!("admin" / "webhooks")
+            .and(warp::get())
+            .and(with_admin(db_pool.clone(), AdminRole::Viewer))
+            .and(with_db(db_pool.clone()))
+            .and_then(handlers::admin_webhooks::list_webhooks);
+
+        let update_webhook = warp::path!("admin" / "webhooks" / String)
+            .and(warp::patch())
+            .and(with_admin(db_pool.clone(), AdminRole::Owner))
+            .and(warp::body::json())
+            .and(with_db(db_pool.clone()))
+            .and_then(handlers::admin_webhooks::update_webhook);
+
+        let delete_webhook = warp::path!("admin" / "webhooks" / String)
+            .and(warp::delete())
+            .and(with_admin(db_pool.clone(), AdminRole::Owner))
+            .and(with_db(db_pool.clone()))
+            .and_then(handlers::admin_webhooks::delete_webhook);
+
+        let list_webhook_deliveries = warp::path!("admin" / "webhooks" / String / "deliveries")
+            .and(warp::get())
+            .and(with_admin(db_pool.clone(), AdminRole::Viewer))
+            .and(warp::query::<models::WebhookDeliveriesParams>())
+            .and(with_db(db_pool.clone()))
+            .and_then(handlers::admin_webhooks::list_webhook_deliveries);
+
+        let replay_webhook_delivery =
+            warp::path!("admin" / "webhooks" / "deliveries" / String / "replay")
+                .and(warp::post())
+                .and(with_admin(db_pool.clone(), AdminRole::Operator))
+                .and(with_db(db_pool.clone()))
+                .and(with_webhooks(webhooks.clone()))
+                .and_then(handlers::admin_webhooks::replay_webhook_delivery);
+
         create_key
             .or(list_keys)
             .or(delete_key)
+            .or(update_key)
+            .or(rotate_key)
             .or(get_stats)
+            .or(get_quota_history)
             .or(get_report)
+            .or(create_token)
+            .or(list_tokens)
+            .or(delete_token)
+            .or(list_limits)
+            .or(set_limit)
+            .or(delete_limit)
+            .or(list_costs)
+            .or(update_cost)
+            .or(create_webhook)
+            .or(list_webhooks)
+            .or(update_webhook)
+            .or(delete_webhook)
+            .or(list_webhook_deliveries)
+            .or(replay_webhook_delivery)
     };
 
     // Protected business routes
     let protected_routes = {
         let submit_reading = warp::path!("readings")
             .and(warp::post())
-            .and(middleware::auth::with_api_key(
+            .and(with_api_key(
                 db_pool.clone(),
-                rate_limiter.clone(),
+                metering.clone(),
+                Endpoint::SubmitReading,
             ))
             .and(middleware::validation::Validator::body_limit())
             .and(with_db(db_pool.clone()))
+            .and(with_feed(feed.clone()))
+            .and(warp::any().map(move || sensor_policy))
             .and(middleware::validation::validate_reading_request())
             .and_then(handlers::business::submit_reading);
 
+        let submit_reading_batch = warp::path!("readings" / "batch")
+            .and(warp::post())
+            .and(with_api_key(
+                db_pool.clone(),
+                metering.clone(),
+                Endpoint::SubmitReadingBatch,
+            ))
+            .and(middleware::validation::Validator::body_limit())
+            .and(with_db(db_pool.clone()))
+            .and(with_feed(feed.clone()))
+            .and(warp::any().map(move || sensor_policy))
+            .and(middleware::validation::validate_reading_batch(
+                max_batch_size,
+            ))
+            .and_then(handlers::business::submit_reading_batch);
+
         let get_readings = warp::path!("readings")
             .and(warp::get())
-            .and(middleware::auth::with_api_key(
+            .and(with_api_key(
                 db_pool.clone(),
-                rate_limiter.clone(),
+                metering.clone(),
+                Endpoint::ListReadings,
             ))
             .and(with_db(db_pool.clone()))
+            .and(warp::query::<models::ReadingsParams>())
             .and_then(handlers::business::get_readings);
 
-        submit_reading.or(get_readings)
+        let get_reading_aggregates = warp::path!("readings" / "aggregate")
+            .and(warp::get())
+            .and(with_api_key(
+                db_pool.clone(),
+                metering.clone(),
+                Endpoint::AggregateReadings,
+            ))
+            .and(with_db(db_pool.clone()))
+            .and(warp::query::<models::AggregateParams>())
+            .and_then(handlers::business::get_reading_aggregates);
+
+        let stream_readings = warp::path!("readings" / "stream")
+            .and(warp::get())
+            .and(with_api_key(
+                db_pool.clone(),
+                metering.clone(),
+                Endpoint::StreamReadings,
+            ))
+            .and(with_feed(feed.clone()))
+            .and(warp::query::<models::ReadingStreamParams>())
+            .and_then(handlers::stream::stream_readings);
+
+        // warp::ws() comes first so requests that are not upgrades are turned
+        // away before they are charged.
+        let readings_socket = warp::path!("readings" / "ws")
+            .and(warp::get())
+            .and(warp::ws())
+            .and(with_api_key(
+                db_pool.clone(),
+                metering.clone(),
+                Endpoint::ReadingsSocket,
+            ))
+            .and(with_feed(feed.clone()))
+            .and(warp::query::<models::ReadingStreamParams>())
+            .and_then(handlers::stream::readings_socket);
+
+        let list_sensors = warp::path!("sensors")
+            .and(warp::get())
+            .and(with_api_key(
+                db_pool.clone(),
+                metering.clone(),
+                Endpoint::ListSensors,
+            ))
+            .and(with_db(db_pool.clone()))
+            .and_then(handlers::sensors::list_sensors);
+
+        let create_sensor = warp::path!("sensors")
+            .and(warp::post())
+            .and(with_api_key(
+                db_pool.clone(),
+                metering.clone(),
+                Endpoint::CreateSensor,
+            ))
+            .and(middleware::validation::Validator::body_limit())
+            .and(with_db(db_pool.clone()))
+            .and(warp::body::json())
+            .and_then(handlers::sensors::create_sensor);
+
+        let get_sensor = warp::path!("sensors" / String)
+            .and(warp::get())
+            .and(with_api_key(
+                db_pool.clone(),
+                metering.clone(),
+                Endpoint::GetSensor,
+            ))
+            .and(with_db(db_pool.clone()))
+            .and_then(handlers::sensors::get_sensor);
+
+        let update_sensor = warp::path!("sensors" / String)
+            .and(warp::patch())
+            .and(with_api_key(
+                db_pool.clone(),
+                metering.clone(),
+                Endpoint::UpdateSensor,
+            ))
+            .and(middleware::validation::Validator::body_limit())
+            .and(with_db(db_pool.clone()))
+            .and(warp::body::json())
+            .and_then(handlers::sensors::update_sensor);
+
+        let delete_sensor = warp::path!("sensors" / String)
+            .and(warp::delete())
+            .and(with_api_key(
+                db_pool.clone(),
+                metering.clone(),
+                Endpoint::DeleteSensor,
+            ))
+            .and(with_db(db_pool.clone()))
+            .and_then(handlers::sensors::delete_sensor);
+
+        let list_alerts = warp::path!("alerts")
+            .and(warp::get())
+            .and(with_api_key(
+                db_pool.clone(),
+                metering.clone(),
+                Endpoint::ListAlerts,
+            ))
+            .and(with_db(db_pool.clone()))
+            .and(warp::query::<models::AlertsParams>())
+            .and_then(handlers::alerts::list_alerts);
+
+        let list_alert_rules = warp::path!("alerts" / "rules")
+            .and(warp::get())
+            .and(with_api_key(
+                db_pool.clone(),
+                metering.clone(),
+                Endpoint::ListAlertRules,
+            ))
+            .and(with_db(db_pool.clone()))
+            .and_then(handlers::alerts::list_alert_rules);
+
+        let create_alert_rule = warp::path!("alerts" / "rules")
+            .and(warp::post())
+            .and(with_api_key(
+                db_pool.clone(),
+                metering.clone(),
+                Endpoint::CreateAlertRule,
+            ))
+            .and(middleware::validation::Validator::body_limit())
+            .and(with_db(db_pool.clone()))
+            .and(warp::body::json())
+            .and_then(handlers::alerts::create_alert_rule);
+
+        let get_alert_rule = warp::path!("alerts" / "rules" / String)
+            .and(warp::get())
+            .and(with_api_key(
+                db_pool.clone(),
+                metering.clone(),
+                Endpoint::GetAlertRule,
+            ))
+            .and(with_db(db_pool.clone()))
+            .and_then(handlers::alerts::get_alert_rule);
+
+        let update_alert_rule = warp::path!("alerts" / "rules" / String)
+            .and(warp::patch())
+            .and(with_api_key(
+                db_pool.clone(),
+                metering.clone(),
+                Endpoint::UpdateAlertRule,
+            ))
+            .and(middleware::validation::Validator::body_limit())
+            .and(with_db(db_pool.clone()))
+            .and(warp::body::json())
+            .and_then(handlers::alerts::update_alert_rule);
+
+        let delete_alert_rule = warp::path!("alerts" / "rules" / String)
+            .and(warp::delete())
+            .and(with_api_key(
+                db_pool.clone(),
+                metering.clone(),
+                Endpoint::DeleteAlertRule,
+            ))
+            .and(with_db(db_pool.clone()))
+            .and_then(handlers::alerts::delete_alert_rule);
+
+        let get_usage = warp::path!("usage")
+            .and(warp::get())
+            .and(with_api_key(
+                db_pool.clone(),
+                metering.clone(),
+                Endpoint::OwnUsage,
+            ))
+            .and(with_db(db_pool.clone()))
+            .and_then(handlers::usage::get_own_usage);
+
+        submit_reading
+            .or(submit_reading_batch)
+            .or(get_readings)
+            .or(get_reading_aggregates)
+            .or(stream_readings)
+            .or(readings_socket)
+            .or(get_usage)
+            .or(list_sensors)
+            .or(create_sensor)
+            .or(get_sensor)
+            .or(update_sensor)
+            .or(delete_sensor)
+            .or(list_alerts)
+            .or(list_alert_rules)
+            .or(create_alert_rule)
+            .or(get_alert_rule)
+            .or(update_alert_rule)
+            .or(delete_alert_rule)
     };
 
     let metrics = warp::path!("metrics")
@@ -110,7 +627,7 @@ async fn main() -> Result<()> {
         .or(protected_routes)
         .recover(middleware::auth::handle_rejection);
 
-    let routes = routes.with(middleware::auth::with_request_logging(db_pool.clone()));
+    let routes = middleware::auth::with_request_logging(routes, db_pool.clone());
 
     tracing::info!("Server starting on {}:{}", host, port);
 
@@ -127,3 +644,15 @@ fn with_db(
 ) -> impl Filter<Extract = (db::DbPool,), Error = std::convert::Infallible> + Clone {
     warp::any().map(move || db.clone())
 }
+
+fn with_webhooks(
+    webhooks: Webhooks,
+) -> impl Filter<Extract = (Webhooks,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || webhooks.clone())
+}
+
+fn with_feed(
+    feed: ReadingFeed,
+) -> impl Filter<Extract = (ReadingFeed,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || feed.clone())
+}
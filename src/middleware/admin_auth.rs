@@ -0,0 +1,101 @@
+use crate::db::DbPool;
+use crate::models::{AdminRole, AdminToken};
+use crate::secrets;
+use warp::{Filter, Rejection, reject};
+
+#[derive(Debug)]
+pub struct AdminUnauthorized;
+impl reject::Reject for AdminUnauthorized {}
+
+#[derive(Debug)]
+pub struct InsufficientRole;
+impl reject::Reject for InsufficientRole {}
+
+pub fn with_admin(
+    db: DbPool,
+    required: AdminRole,
+) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::header::optional::<String>("x-admin-token")
+        .and(warp::any().map(move || db.clone()))
+        .and(warp::any().map(move || required))
+        .and_then(validate_admin_token)
+        .untuple_one()
+}
+
+async fn validate_admin_token(
+    token: Option<String>,
+    db: DbPool,
+    required: AdminRole,
+) -> Result<(), Rejection> {
+    let token = token.ok_or(reject::custom(AdminUnauthorized))?;
+
+    let result = find_admin_token(&db, &token).await;
+
+    match result {
+        Ok(Some(admin)) if admin.role >= required => {
+            if let Err(e) =
+                sqlx::query("UPDATE admin_tokens SET last_used_at = NOW() WHERE id = $1")
+                    .bind(admin.id)
+                    .execute(&*db)
+                    .await
+            {
+                tracing::error!("Failed to record admin token usage: {:?}", e);
+            }
+            Ok(())
+        }
+        Ok(Some(admin)) => {
+            tracing::warn!(
+                "Admin token '{}' ({:?}) denied, {:?} role required",
+                admin.name,
+                admin.role,
+                required
+            );
+            Err(reject::custom(InsufficientRole))
+        }
+        Ok(None) => Err(reject::custom(AdminUnauthorized)),
+        Err(e) => {
+            tracing::error!("Database error during admin token validation: {:?}", e);
+            Err(reject::custom(AdminUnauthorized))
+        }
+    }
+}
+
+async fn find_admin_token(db: &DbPool, token: &str) -> Result<Option<AdminToken>, sqlx::Error> {
+    let candidates = sqlx::query_as::<_, AdminToken>(
+        "SELECT * FROM admin_tokens WHERE token_prefix = $1 AND is_active = true",
+    )
+    .bind(secrets::prefix_of(token))
+    .fetch_all(&**db)
+    .await?;
+
+    Ok(candidates
+        .into_iter()
+        .find(|t| secrets::verify_secret(token, &t.token_salt, &t.token_hash)))
+}
+
+// Seeds an owner token from the environment so a fresh deployment can
+// bootstrap itself. Does nothing once an active owner exists.
+pub async fn ensure_bootstrap_owner(db: &DbPool, token: &str) -> Result<(), sqlx::Error> {
+    let hashed = secrets::hash_secret(token);
+
+    let result = sqlx::query(
+        r#"
+        INSERT INTO admin_tokens (token_prefix, token_salt, token_hash, name, role)
+        SELECT $1, $2, $3, 'bootstrap', 'owner'
+        WHERE NOT EXISTS (
+            SELECT 1 FROM admin_tokens WHERE role = 'owner' AND is_active = true
+        )
+        "#,
+    )
+    .bind(&hashed.prefix)
+    .bind(&hashed.salt)
+    .bind(&hashed.hash)
+    .execute(&**db)
+    .await?;
+
+    if result.rows_affected() > 0 {
+        tracing::info!("Created bootstrap owner admin token");
+    }
+
+    Ok(())
+}
@@ -1,116 +1,535 @@
 use crate::db::DbPool;
-use crate::middleware::rate_limiter::{RateLimitExceeded, RateLimiter};
-use crate::models::ApiKey;
+use crate::middleware::admin_auth::{AdminUnauthorized, InsufficientRole};
+use crate::middleware::concurrency::{ConcurrencyLimitExceeded, ConcurrencyPermit};
+use crate::middleware::metering::{BillableStatuses, Charge, MeteredRequest, Metering};
+use crate::middleware::rate_limiter::{RateLimitExceeded, RateLimitStatus};
+use crate::middleware::validation::ValidationError;
+use crate::middleware::{limit_headers, quota};
+use crate::models::{ApiKey, Endpoint, KeyLimit, QuotaStatus};
+use crate::secrets;
+use crate::webhooks::Webhooks;
+use chrono::Utc;
+use sqlx::{FromRow, Row};
+use std::convert::Infallible;
+use std::time::Instant;
 use uuid::Uuid;
-use warp::http::StatusCode;
-use warp::{Filter, Rejection, log, reject, reply};
+use warp::http::{HeaderMap, HeaderValue, Method, StatusCode};
+use warp::path::FullPath;
+use warp::{Filter, Rejection, Reply, reject, reply};
 
 #[derive(Debug)]
 pub struct Unauthorized;
 impl reject::Reject for Unauthorized {}
 
 #[derive(Debug)]
-pub struct QuotaExceeded;
+pub struct QuotaExceeded {
+    pub quota: QuotaStatus,
+    pub rate_limit: RateLimitStatus,
+}
 impl reject::Reject for QuotaExceeded {}
 
+#[derive(Debug)]
+pub struct InsufficientScope;
+impl reject::Reject for InsufficientScope {}
+
+#[derive(Debug)]
+pub struct KeyExpired;
+impl reject::Reject for KeyExpired {}
+
+#[derive(Debug)]
+pub struct KeyNotYetActive;
+impl reject::Reject for KeyNotYetActive {}
+
+// An authenticated request: the key with the endpoint's base cost reserved,
+// plus the limit state that protected handlers report back through `respond`.
+pub struct AuthContext {
+    pub api_key: ApiKey,
+    pub key_limit: Option<KeyLimit>,
+    pub rate_limit: RateLimitStatus,
+    base_cost: i32,
+    per_item_cost: i32,
+    charge: Charge,
+    billable: BillableStatuses,
+    pub webhooks: Webhooks,
+    // Holds the key's in-flight slot until the response has been built.
+    _permit: ConcurrencyPermit,
+}
+
+impl AuthContext {
+    pub fn respond(self, reply: impl Reply) -> reply::Response {
+        self.respond_with_items(reply, 0)
+    }
+
+    // Keeps the reserved cost, plus the per-item cost of the readings the
+    // request returned or stored, only if the response status is billable.
+    pub fn respond_with_items(mut self, reply: impl Reply, items: usize) -> reply::Response {
+        let mut response = reply.into_response();
+
+        // An accepted WebSocket upgrade is as good as a 2xx.
+        let billed = self.billable.contains(response.status())
+            || response.status() == StatusCode::SWITCHING_PROTOCOLS;
+        let (cost, delta) = settle(billed, self.base_cost, self.per_item_cost, items);
+
+        self.api_key.usage_count = self.api_key.usage_count.saturating_add(delta);
+        if let Some(limit) = &mut self.key_limit {
+            limit.usage_count = limit.usage_count.saturating_add(delta);
+        }
+
+        let quota = self.quota_status(Utc::now());
+
+        limit_headers::apply(response.headers_mut(), Some(&self.rate_limit), Some(&quota));
+        response.extensions_mut().insert(MeteredRequest {
+            api_key_id: self.api_key.id,
+            cost,
+        });
+
+        // Otherwise dropping the charge refunds the reservation.
+        if billed {
+            self.charge.commit(delta, self.api_key, self.webhooks);
+        }
+
+        response
+    }
+
+    // Whether the per-item cost of `items` more readings still fits in the
+    // quota, for handlers that can turn work away before doing it.
+    pub fn items_fit_quota(&self, items: usize) -> bool {
+        let cost = i64::from(self.per_item_cost) * items as i64;
+
+        self.quota_status(Utc::now())
+            .remaining
+            .is_none_or(|remaining| cost <= i64::from(remaining))
+    }
+
+    // Whichever of the key and endpoint quotas has less left.
+    fn quota_status(&self, now: chrono::DateTime<Utc>) -> QuotaStatus {
+        let key_quota = self.api_key.quota_status(now);
+
+        match &self.key_limit {
+            Some(limit) if limit.quota_limit.is_some() => {
+                let endpoint_quota = limit.quota_status(&self.api_key, now);
+                if key_quota
+                    .remaining
+                    .is_none_or(|r| endpoint_quota.remaining < Some(r))
+                {
+                    endpoint_quota
+                } else {
+                    key_quota
+                }
+            }
+            _ => key_quota,
+        }
+    }
+}
+
+// What a finished request is charged, and how far that moves the key's usage
+// from the base cost reserved up front: the per-item cost is added when the
+// response is billable, and the whole reservation is handed back when not.
+fn settle(billed: bool, base_cost: i32, per_item_cost: i32, items: usize) -> (i32, i32) {
+    if !billed {
+        return (0, -base_cost);
+    }
+
+    let extra = per_item_cost.saturating_mul(i32::try_from(items).unwrap_or(i32::MAX));
+
+    (base_cost.saturating_add(extra), extra)
+}
+
 pub fn with_api_key(
     db: DbPool,
-    limiter: RateLimiter,
-) -> impl Filter<Extract = (ApiKey,), Error = Rejection> + Clone {
+    metering: Metering,
+    endpoint: Endpoint,
+) -> impl Filter<Extract = (AuthContext,), Error = Rejection> + Clone {
     warp::header::optional::<String>("x-api-key")
         .and(warp::any().map(move || db.clone()))
-        .and(warp::any().map(move || limiter.clone()))
+        .and(warp::any().map(move || metering.clone()))
+        .and(warp::any().map(move || endpoint))
         .and_then(validate_api_key)
 }
 
-async fn validate_api_key(
+// Shared by the HTTP filter above and the MQTT listener.
+pub async fn validate_api_key(
     api_key: Option<String>,
     db: DbPool,
-    limiter: RateLimiter,
-) -> Result<ApiKey, Rejection> {
+    metering: Metering,
+    endpoint: Endpoint,
+) -> Result<AuthContext, Rejection> {
     let key = api_key.ok_or(warp::reject::custom(Unauthorized))?;
 
-    let result = sqlx::query_as::<_, ApiKey>(
+    let (api_key_record, secret) = match find_api_key(&db, &key).await {
+        Ok(Some(found)) => found,
+        Ok(None) => return Err(reject::custom(Unauthorized)),
+        Err(e) => {
+            tracing::error!("Database error during API key validation: {:?}", e);
+            return Err(reject::custom(Unauthorized));
+        }
+    };
+
+    // Checked before is_active: the expiry sweeper deactivates expired keys,
+    // and callers should still be told why their key stopped working.
+    let now = Utc::now();
+    if api_key_record.expires_at.is_some_and(|t| t <= now) {
+        return Err(reject::custom(KeyExpired));
+    }
+    if api_key_record.not_before.is_some_and(|t| t > now) {
+        return Err(reject::custom(KeyNotYetActive));
+    }
+    if !api_key_record.is_active {
+        return Err(reject::custom(Unauthorized));
+    }
+    if !api_key_record.has_scope(endpoint.scope()) {
+        return Err(reject::custom(InsufficientScope));
+    }
+
+    // Taken before the rate limit and the quota so requests turned away for
+    // being over the cap use up neither.
+    let max_concurrent = api_key_record
+        .max_concurrent_requests
+        .unwrap_or(metering.default_max_concurrent);
+    let permit = metering
+        .concurrency
+        .try_acquire(api_key_record.id, max_concurrent)
+        .ok_or_else(|| {
+            reject::custom(ConcurrencyLimitExceeded {
+                limit: max_concurrent,
+            })
+        })?;
+
+    let key_limit = match sqlx::query_as::<_, KeyLimit>(
+        "SELECT * FROM key_limits WHERE api_key_id = $1 AND method = $2 AND route = $3",
+    )
+    .bind(api_key_record.id)
+    .bind(endpoint.method())
+    .bind(endpoint.route())
+    .fetch_optional(&*db)
+    .await
+    {
+        Ok(limit) => limit,
+        Err(e) => {
+            tracing::error!("Database error loading endpoint limits: {:?}", e);
+            return Err(reject::custom(Unauthorized));
+        }
+    };
+
+    // Rate limited before charging, so rejected bursts do not eat into the quota.
+    // An endpoint limit has its own bucket, and the headers report whichever
+    // of the two buckets has less left.
+    let mut rate_limit = metering
+        .limiter
+        .check_rate_limit(
+            api_key_record.id,
+            api_key_record.rate_limit_per_minute,
+            api_key_record.burst(),
+        )
+        .await
+        .inspect_err(|_| metering.webhooks.rate_limited(&api_key_record, endpoint))?;
+
+    if let Some(limit) = &key_limit
+        && let Some(rate) = limit.rate_limit_per_minute
+    {
+        let endpoint_rate_limit = match metering
+            .limiter
+            .check_rate_limit(limit.id, rate, limit.rate_limit_burst.unwrap_or(rate))
+            .await
+        {
+            Ok(status) => status,
+            Err(rejection) => {
+                // The key-wide token goes back, so throttling one endpoint
+                // does not use up the key's budget for the others.
+                metering
+                    .limiter
+                    .refund(
+                        api_key_record.id,
+                        api_key_record.rate_limit_per_minute,
+                        api_key_record.burst(),
+                    )
+                    .await;
+                metering.webhooks.rate_limited(&api_key_record, endpoint);
+                return Err(rejection);
+            }
+        };
+
+        if endpoint_rate_limit.remaining < rate_limit.remaining {
+            rate_limit = endpoint_rate_limit;
+        }
+    }
+
+    if let Err(e) = quota::roll_over_if_due(&db, &api_key_record, now).await {
+        tracing::error!("Failed to start new quota period: {:?}", e);
+    }
+
+    // Endpoints missing from endpoint_costs cost one unit, as before costs
+    // were configurable.
+    let result = sqlx::query(
         r#"
         UPDATE api_keys
-        SET usage_count = usage_count + 1,
+        SET usage_count = usage_count + c.base_cost,
+            last_used_at = CASE WHEN $2 THEN last_used_at ELSE NOW() END,
+            previous_key_last_used_at = CASE WHEN $2 THEN NOW() ELSE previous_key_last_used_at END,
             updated_at = NOW()
-        WHERE key = $1 
+        FROM (
+            SELECT
+                COALESCE(MAX(base_cost), 1) AS base_cost,
+                COALESCE(MAX(per_item_cost), 0) AS per_item_cost
+            FROM endpoint_costs
+            WHERE method = $3 AND route = $4
+        ) c
+        WHERE id = $1
             AND is_active = true
-            AND (quota_limit IS NULL or usage_count < quota_limit)
+            AND (not_before IS NULL OR not_before <= NOW())
+            AND (expires_at IS NULL OR expires_at > NOW())
+            AND (quota_limit IS NULL or usage_count + c.base_cost <= quota_limit)
+        RETURNING api_keys.*, c.base_cost, c.per_item_cost
+        "#,
+    )
+    .bind(api_key_record.id)
+    .bind(secret == KeySecret::Previous)
+    .bind(endpoint.method())
+    .bind(endpoint.route())
+    .fetch_optional(&*db)
+    .await
+    .and_then(|row| {
+        row.map(|row| {
+            Ok((
+                ApiKey::from_row(&row)?,
+                row.try_get::<i32, _>("base_cost")?,
+                row.try_get::<i32, _>("per_item_cost")?,
+            ))
+        })
+        .transpose()
+    });
+
+    let (api_key, base_cost, per_item_cost) = match result {
+        Ok(Some(reserved)) => reserved,
+        Ok(None) => {
+            return Err(reject::custom(QuotaExceeded {
+                quota: api_key_record.quota_status(now),
+                rate_limit,
+            }));
+        }
+        Err(e) => {
+            tracing::error!("Database error during API key validation: {:?}", e);
+            return Err(reject::custom(Unauthorized));
+        }
+    };
+
+    let charge = Charge::new(db.clone(), api_key.id, api_key.period_started_at, base_cost);
+
+    let Some(limit) = key_limit.filter(|l| l.quota_limit.is_some()) else {
+        return Ok(AuthContext {
+            api_key,
+            key_limit: None,
+            rate_limit,
+            base_cost,
+            per_item_cost,
+            charge,
+            billable: metering.billable,
+            webhooks: metering.webhooks,
+            _permit: permit,
+        });
+    };
+
+    // The endpoint counter is reset lazily: a period_started_at that differs
+    // from the key's means the key has moved on to a new quota period. If the
+    // endpoint quota is spent, dropping `charge` refunds the key-wide reservation.
+    let result = sqlx::query_as::<_, KeyLimit>(
+        r#"
+        UPDATE key_limits
+        SET usage_count = CASE WHEN period_started_at = $2 THEN usage_count ELSE 0 END + $3,
+            period_started_at = $2
+        WHERE id = $1
+            AND CASE WHEN period_started_at = $2 THEN usage_count ELSE 0 END + $3 <= quota_limit
         RETURNING *
         "#,
     )
-    .bind(key)
+    .bind(limit.id)
+    .bind(api_key.period_started_at)
+    .bind(base_cost)
     .fetch_optional(&*db)
     .await;
 
     match result {
-        Ok(Some(api_key_record)) => {
-            limiter
-                .check_rate_limit(api_key_record.id, api_key_record.rate_limit_per_minute)
-                .await?;
-
-            Ok(api_key_record)
+        Ok(Some(limit)) => Ok(AuthContext {
+            charge: charge.with_key_limit(limit.id),
+            api_key,
+            key_limit: Some(limit),
+            rate_limit,
+            base_cost,
+            per_item_cost,
+            billable: metering.billable,
+            webhooks: metering.webhooks,
+            _permit: permit,
+        }),
+        Ok(None) => {
+            // Like an endpoint rate limit, a spent endpoint quota leaves the
+            // key's budget for other endpoints untouched.
+            refund_rate_limits(&metering, &api_key, &limit).await;
+            Err(reject::custom(QuotaExceeded {
+                quota: limit.quota_status(&api_key, now),
+                rate_limit,
+            }))
         }
-        Ok(None) => Err(reject::custom(QuotaExceeded)),
         Err(e) => {
-            tracing::error!("Database error during API key validation: {:?}", e);
+            tracing::error!("Database error reserving endpoint quota: {:?}", e);
             Err(reject::custom(Unauthorized))
         }
     }
 }
 
-pub fn with_request_logging(db: DbPool) -> log::Log<impl Fn(log::Info) + Clone> {
-    log::custom(move |info: log::Info| {
-        let path = info.path().to_owned();
-        let method = info.method().clone();
-        let status = info.status();
-        let elapsed = info.elapsed();
-        let headers = info.request_headers().clone();
-        let db = db.clone();
-
-        tokio::spawn(async move {
-            let api_key_id =
-                if let Some(key) = headers.get("x-api-key").and_then(|v| v.to_str().ok()) {
-                    sqlx::query!(
-                        r#"
-                    SELECT id FROM api_keys
-                    WHERE key = $1
-                    "#,
-                        key
-                    )
-                    .fetch_optional(&*db)
-                    .await
-                    .ok()
-                    .flatten()
-                    .map(|rec| rec.id)
-                } else {
-                    None
-                };
-
-            if let Some(id) = api_key_id {
-                let result = sqlx::query!(
-                    r#"
-                    INSERT INTO requests (id, api_key_id, endpoint, method, status_code, response_time_ms)
-                    VALUES ($1, $2, $3, $4, $5, $6)
-                    "#,
-                    Uuid::new_v4(),
-                    id,
-                    path,
-                    method.as_str(),
-                    status.as_u16() as i16,
-                    elapsed.as_millis() as i32
-                )
-                .execute(&*db)
-                .await;
-
-                if let Err(e) = result {
-                    tracing::error!("Failed to log request to database: {:?}", e);
-                }
-            }
-        });
-    })
+// Hands back the key-wide token and, if the endpoint has its own rate limit,
+// the endpoint token a request took before it was turned away.
+async fn refund_rate_limits(metering: &Metering, api_key: &ApiKey, limit: &KeyLimit) {
+    metering
+        .limiter
+        .refund(api_key.id, api_key.rate_limit_per_minute, api_key.burst())
+        .await;
+
+    if let Some(rate) = limit.rate_limit_per_minute {
+        metering
+            .limiter
+            .refund(limit.id, rate, limit.rate_limit_burst.unwrap_or(rate))
+            .await;
+    }
+}
+
+// Which of a key's secrets authenticated the request. `Previous` is only
+// accepted while a rotation's grace window is open.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeySecret {
+    Current,
+    Previous,
+}
+
+// Keys are looked up by their plaintext prefix, then confirmed with a
+// constant-time comparison against the stored salted hash.
+pub async fn find_api_key(
+    db: &DbPool,
+    key: &str,
+) -> Result<Option<(ApiKey, KeySecret)>, sqlx::Error> {
+    let prefix = secrets::prefix_of(key);
+
+    let candidates = sqlx::query_as::<_, ApiKey>(
+        r#"
+        SELECT * FROM api_keys
+        WHERE key_prefix = $1
+            OR (previous_key_prefix = $1 AND previous_key_expires_at > NOW())
+        "#,
+    )
+    .bind(prefix)
+    .fetch_all(&**db)
+    .await?;
+
+    let now = Utc::now();
+
+    Ok(candidates.into_iter().find_map(|k| {
+        if k.key_prefix == prefix && secrets::verify_secret(key, &k.key_salt, &k.key_hash) {
+            return Some((k, KeySecret::Current));
+        }
+
+        let previous_valid = k.previous_key_expires_at.is_some_and(|t| t > now)
+            && k.previous_key_prefix.as_deref() == Some(prefix)
+            && match (&k.previous_key_salt, &k.previous_key_hash) {
+                (Some(salt), Some(hash)) => secrets::verify_secret(key, salt, hash),
+                _ => false,
+            };
+
+        previous_valid.then_some((k, KeySecret::Previous))
+    }))
+}
+
+// Records every request made with a known API key in the `requests` table,
+// along with the cost charged for it. Metered responses carry their key and
+// cost; anything else (rejections, unknown routes) is looked up by header and
+// logged at no cost.
+pub fn with_request_logging<F, T>(
+    routes: F,
+    db: DbPool,
+) -> impl Filter<Extract = (reply::Response,), Error = Infallible> + Clone
+where
+    F: Filter<Extract = (T,), Error = Infallible> + Clone + Send + Sync + 'static,
+    T: Reply,
+{
+    warp::any()
+        .map(Instant::now)
+        .and(warp::method())
+        .and(warp::path::full())
+        .and(warp::header::headers_cloned())
+        .and(routes)
+        .map(
+            move |started: Instant,
+                  method: Method,
+                  path: FullPath,
+                  headers: HeaderMap,
+                  reply: T| {
+                let response = reply.into_response();
+                let status = response.status();
+                let elapsed = started.elapsed();
+                let metered = response.extensions().get::<MeteredRequest>().copied();
+                let db = db.clone();
+
+                tokio::spawn(async move {
+                    let (api_key_id, cost) = match metered {
+                        Some(m) => (Some(m.api_key_id), m.cost),
+                        None => {
+                            let id = match headers.get("x-api-key").and_then(|v| v.to_str().ok()) {
+                                Some(key) => find_api_key(&db, key)
+                                    .await
+                                    .ok()
+                                    .flatten()
+                                    .map(|(rec, _)| rec.id),
+                                None => None,
+                            };
+                            (id, 0)
+                        }
+                    };
+
+                    if let Some(id) = api_key_id {
+                        log_request(
+                            &db,
+                            id,
+                            path.as_str(),
+                            method.as_str(),
+                            status,
+                            elapsed,
+                            cost,
+                        )
+                        .await;
+                    }
+                });
+
+                response
+            },
+        )
+}
+
+pub async fn log_request(
+    db: &DbPool,
+    api_key_id: Uuid,
+    endpoint: &str,
+    method: &str,
+    status: StatusCode,
+    elapsed: std::time::Duration,
+    cost: i32,
+) {
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO requests (id, api_key_id, endpoint, method, status_code, response_time_ms, cost)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        "#,
+        Uuid::new_v4(),
+        api_key_id,
+        endpoint,
+        method,
+        status.as_u16() as i16,
+        elapsed.as_millis() as i32,
+        cost
+    )
+    .execute(&**db)
+    .await;
+
+    if let Err(e) = result {
+        tracing::error!("Failed to log request to database: {:?}", e);
+    }
 }
 
 pub async fn handle_rejection(
@@ -118,6 +537,11 @@ pub async fn handle_rejection(
 ) -> Result<impl warp::Reply, std::convert::Infallible> {
     let code;
     let message;
+    let mut rate_limit = None;
+    let mut quota = None;
+    let mut retry_after = None;
+    let mut error_code = None;
+    let mut concurrency_limit = None;
 
     if err.is_not_found() {
         code = StatusCode::NOT_FOUND;
@@ -125,12 +549,64 @@ pub async fn handle_rejection(
     } else if err.find::<Unauthorized>().is_some() {
         code = StatusCode::UNAUTHORIZED;
         message = "Authentication error: API key is invalid or missing.";
-    } else if err.find::<QuotaExceeded>().is_some() {
+    } else if err.find::<AdminUnauthorized>().is_some() {
+        code = StatusCode::UNAUTHORIZED;
+        message = "Authentication error: admin token is invalid or missing.";
+    } else if err.find::<InsufficientRole>().is_some() {
+        code = StatusCode::FORBIDDEN;
+        message = "Admin token does not have the required role for this operation.";
+    } else if err.find::<InsufficientScope>().is_some() {
+        code = StatusCode::FORBIDDEN;
+        message = "API key does not have the scope required for this endpoint.";
+    } else if err.find::<KeyExpired>().is_some() {
+        code = StatusCode::UNAUTHORIZED;
+        message = "API key has expired.";
+    } else if err.find::<KeyNotYetActive>().is_some() {
+        code = StatusCode::FORBIDDEN;
+        message = "API key is not active yet.";
+    } else if let Some(e) = err.find::<QuotaExceeded>() {
         code = StatusCode::FORBIDDEN;
         message = "API key has exceeded its request quota.";
-    } else if err.find::<RateLimitExceeded>().is_some() {
+        rate_limit = Some(&e.rate_limit);
+        quota = Some(&e.quota);
+        retry_after = e
+            .quota
+            .period_end
+            .and_then(|end| (end - Utc::now()).to_std().ok());
+    } else if let Some(RateLimitExceeded(status)) = err.find::<RateLimitExceeded>() {
         code = StatusCode::TOO_MANY_REQUESTS;
         message = "Rate limit exceeded. Please slow down.";
+        error_code = Some("rate_limit_exceeded");
+        rate_limit = Some(status);
+    } else if let Some(e) = err.find::<ConcurrencyLimitExceeded>() {
+        code = StatusCode::TOO_MANY_REQUESTS;
+        message = "Too many concurrent requests for this API key.";
+        error_code = Some("concurrency_limit_exceeded");
+        concurrency_limit = Some(e.limit);
+        retry_after = Some(std::time::Duration::from_secs(1));
+    } else if let Some(ValidationError(e)) = err.find::<ValidationError>() {
+        code = StatusCode::BAD_REQUEST;
+        message = e.as_str();
+    } else if err
+        .find::<warp::filters::body::BodyDeserializeError>()
+        .is_some()
+    {
+        code = StatusCode::BAD_REQUEST;
+        message = "Request body is not valid JSON for this endpoint.";
+    } else if err.find::<warp::reject::InvalidQuery>().is_some() {
+        code = StatusCode::BAD_REQUEST;
+        message = "Query parameters are not valid for this endpoint.";
+    } else if err
+        .find::<warp::filters::ws::MissingConnectionUpgrade>()
+        .is_some()
+    {
+        code = StatusCode::BAD_REQUEST;
+        message = "This endpoint expects a WebSocket upgrade request.";
+    } else if err.find::<warp::reject::MissingHeader>().is_some()
+        || err.find::<warp::reject::InvalidHeader>().is_some()
+    {
+        code = StatusCode::BAD_REQUEST;
+        message = "Request headers are not valid for this endpoint.";
     } else if err.find::<warp::reject::MethodNotAllowed>().is_some() {
         code = StatusCode::METHOD_NOT_ALLOWED;
         message = "HTTP method is not allowed for the requested resource.";
@@ -140,9 +616,304 @@ pub async fn handle_rejection(
         message = "Internal Server Error.";
     }
 
-    let json = warp::reply::json(&serde_json::json!({
+    let mut body = serde_json::json!({
         "error": message
-    }));
+    });
+    if let Some(error_code) = error_code {
+        body["code"] = error_code.into();
+    }
+    let json = warp::reply::json(&body);
+
+    let mut response = reply::with_status(json, code).into_response();
+    limit_headers::apply(response.headers_mut(), rate_limit, quota);
+    if let Some(limit) = concurrency_limit {
+        response
+            .headers_mut()
+            .insert("x-concurrency-limit", HeaderValue::from(limit));
+    }
+    if let Some(retry_after) = retry_after {
+        response.headers_mut().insert(
+            "retry-after",
+            HeaderValue::from(limit_headers::ceil_secs(retry_after)),
+        );
+    }
+
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::feed::{FeedBackend, ReadingFeed};
+    use crate::handlers;
+    use crate::middleware::rate_limiter::{RateLimitBackend, RateLimiter};
+    use crate::middleware::validation::{self, Validator};
+    use crate::sensors::{SensorPolicy, UnknownSensors};
+    use crate::test_support::{self, metering};
+    use sqlx::PgPool;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn billable_response_keeps_base_cost_and_adds_item_cost() {
+        assert_eq!(settle(true, 1, 0, 1), (1, 0));
+        assert_eq!(settle(true, 2, 3, 4), (14, 12));
+    }
 
-    Ok(reply::with_status(json, code))
+    #[test]
+    fn large_item_costs_saturate_instead_of_overflowing() {
+        assert_eq!(settle(true, 5, i32::MAX, 2), (i32::MAX, i32::MAX));
+        assert_eq!(
+            settle(true, 1_000_000, 1_000_000, 5_000),
+            (i32::MAX, i32::MAX)
+        );
+    }
+
+    #[test]
+    fn unbillable_response_refunds_base_cost_and_skips_item_cost() {
+        assert_eq!(settle(false, 1, 0, 1), (0, -1));
+        assert_eq!(settle(false, 2, 3, 4), (0, -2));
+    }
+
+    async fn set_validity(db: &DbPool, id: Uuid, not_before: &str, expires_at: &str) {
+        sqlx::query(&format!(
+            "UPDATE api_keys SET not_before = NOW() + INTERVAL '{}', expires_at = NOW() + INTERVAL '{}' WHERE id = $1",
+            not_before, expires_at
+        ))
+        .bind(id)
+        .execute(&**db)
+        .await
+        .unwrap();
+    }
+
+    async fn validate(db: &DbPool, key: &str) -> Result<AuthContext, Rejection> {
+        validate_api_key(
+            Some(key.to_string()),
+            db.clone(),
+            metering(db),
+            Endpoint::ListReadings,
+        )
+        .await
+    }
+
+    #[sqlx::test]
+    async fn key_is_usable_inside_its_validity_window(pool: PgPool) {
+        let db: DbPool = Arc::new(pool);
+        let (id, key) = test_support::create_api_key(&db).await;
+        set_validity(&db, id, "-1 hour", "1 hour").await;
+
+        assert!(validate(&db, &key).await.is_ok());
+    }
+
+    #[sqlx::test]
+    async fn key_is_rejected_before_not_before(pool: PgPool) {
+        let db: DbPool = Arc::new(pool);
+        let (id, key) = test_support::create_api_key(&db).await;
+        set_validity(&db, id, "1 hour", "2 hours").await;
+
+        let rejection = validate(&db, &key).await.err().unwrap();
+        assert!(rejection.find::<KeyNotYetActive>().is_some());
+        assert_eq!(usage_count(&db, id).await, 0);
+    }
+
+    #[sqlx::test]
+    async fn expired_key_is_rejected_even_after_sweep(pool: PgPool) {
+        let db: DbPool = Arc::new(pool);
+        let (id, key) = test_support::create_api_key(&db).await;
+        set_validity(&db, id, "-2 hours", "-1 hour").await;
+
+        let rejection = validate(&db, &key).await.err().unwrap();
+        assert!(rejection.find::<KeyExpired>().is_some());
+
+        // The sweeper marks it inactive; callers are still told it expired.
+        crate::tasks::key_expiry::deactivate_expired_keys(&db)
+            .await
+            .unwrap();
+        let rejection = validate(&db, &key).await.err().unwrap();
+        assert!(rejection.find::<KeyExpired>().is_some());
+        assert_eq!(usage_count(&db, id).await, 0);
+    }
+
+    #[sqlx::test]
+    async fn quota_webhooks_fire_only_for_committed_charges(pool: PgPool) {
+        let db: DbPool = Arc::new(pool);
+        let (id, key) = test_support::create_api_key(&db).await;
+        test_support::subscribe_webhook(&db, &["quota.warning", "quota.exhausted"]).await;
+        sqlx::query("UPDATE api_keys SET quota_limit = 5, usage_count = 3 WHERE id = $1")
+            .bind(id)
+            .execute(&*db)
+            .await
+            .unwrap();
+
+        // Reserving the 4th unit reaches 80%, but the request is refunded.
+        let ctx = validate(&db, &key).await.unwrap();
+        ctx.respond(StatusCode::BAD_REQUEST);
+        assert_usage_settles(&db, id, 3).await;
+        assert_eq!(
+            test_support::wait_for_events(&db, "quota.warning", 0).await,
+            0
+        );
+
+        let ctx = validate(&db, &key).await.unwrap();
+        ctx.respond(StatusCode::OK);
+        assert_eq!(
+            test_support::wait_for_events(&db, "quota.warning", 1).await,
+            1
+        );
+
+        let ctx = validate(&db, &key).await.unwrap();
+        ctx.respond(StatusCode::OK);
+        assert_eq!(
+            test_support::wait_for_events(&db, "quota.exhausted", 1).await,
+            1
+        );
+        assert_eq!(
+            test_support::wait_for_events(&db, "quota.warning", 1).await,
+            1
+        );
+    }
+
+    #[sqlx::test]
+    async fn spent_endpoint_quota_hands_back_rate_limit_tokens(pool: PgPool) {
+        let db: DbPool = Arc::new(pool);
+        let (id, key) = test_support::create_api_key(&db).await;
+        sqlx::query("UPDATE api_keys SET rate_limit_per_minute = 1 WHERE id = $1")
+            .bind(id)
+            .execute(&*db)
+            .await
+            .unwrap();
+        sqlx::query(
+            r#"
+            INSERT INTO key_limits (api_key_id, method, route, rate_limit_per_minute, quota_limit)
+            VALUES ($1, $2, $3, 1, 0)
+            "#,
+        )
+        .bind(id)
+        .bind(Endpoint::ListReadings.method())
+        .bind(Endpoint::ListReadings.route())
+        .execute(&*db)
+        .await
+        .unwrap();
+        let metering = metering(&db);
+        let call =
+            |endpoint| validate_api_key(Some(key.clone()), db.clone(), metering.clone(), endpoint);
+
+        let rejection = call(Endpoint::ListReadings).await.err().unwrap();
+        assert!(rejection.find::<QuotaExceeded>().is_some());
+
+        sqlx::query("UPDATE key_limits SET quota_limit = 10 WHERE api_key_id = $1")
+            .bind(id)
+            .execute(&*db)
+            .await
+            .unwrap();
+        assert!(call(Endpoint::ListReadings).await.is_ok());
+        let rejection = call(Endpoint::SubmitReading).await.err().unwrap();
+        assert!(rejection.find::<RateLimitExceeded>().is_some());
+    }
+
+    async fn usage_count(db: &DbPool, id: Uuid) -> i32 {
+        sqlx::query_scalar("SELECT usage_count FROM api_keys WHERE id = $1")
+            .bind(id)
+            .fetch_one(&**db)
+            .await
+            .unwrap()
+    }
+
+    // Refunds run in the background once the request's charge is dropped.
+    async fn assert_usage_settles(db: &DbPool, id: Uuid, expected: i32) {
+        for _ in 0..50 {
+            if usage_count(db, id).await == expected {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+
+        assert_eq!(usage_count(db, id).await, expected);
+    }
+
+    // POST /readings wired up as in main, against a key allowed three
+    // requests before it is rate limited.
+    #[sqlx::test]
+    async fn rejected_requests_are_not_billed(pool: PgPool) {
+        let db: DbPool = Arc::new(pool);
+        let key = secrets::generate_secret("sk");
+        let hashed = secrets::hash_secret(&key);
+        let api_key_id: Uuid = sqlx::query_scalar(
+            r#"
+            INSERT INTO api_keys (key_prefix, key_salt, key_hash, name, rate_limit_per_minute)
+            VALUES ($1, $2, $3, 'billing test', 3)
+            RETURNING id
+            "#,
+        )
+        .bind(&hashed.prefix)
+        .bind(&hashed.salt)
+        .bind(&hashed.hash)
+        .fetch_one(&*db)
+        .await
+        .unwrap();
+
+        let webhooks = Webhooks::new(db.clone());
+        let metering = Metering::new(
+            RateLimiter::new(RateLimitBackend::Memory, db.clone(), 100, false),
+            4,
+            BillableStatuses::parse("2xx").unwrap(),
+            webhooks,
+        );
+        let feed = ReadingFeed::new(FeedBackend::Memory, db.clone(), 5);
+        let sensor_policy = SensorPolicy {
+            enforce: false,
+            unknown: UnknownSensors::Allow,
+        };
+        let routes = warp::path!("readings")
+            .and(warp::post())
+            .and(with_api_key(db.clone(), metering, Endpoint::SubmitReading))
+            .and(Validator::body_limit())
+            .and(warp::any().map({
+                let db = db.clone();
+                move || db.clone()
+            }))
+            .and(warp::any().map(move || feed.clone()))
+            .and(warp::any().map(move || sensor_policy))
+            .and(validation::validate_reading_request())
+            .and_then(handlers::business::submit_reading)
+            .recover(handle_rejection);
+
+        let submit = |body: serde_json::Value| {
+            warp::test::request()
+                .method("POST")
+                .path("/readings")
+                .header("x-api-key", &key)
+                .json(&body)
+                .reply(&routes)
+        };
+        let reading = serde_json::json!({"sensor_id": "s1", "value": 21.5, "unit": "C"});
+
+        let response = submit(reading.clone()).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_usage_settles(&db, api_key_id, 1).await;
+
+        // 400: the body fails validation after the base cost was reserved.
+        let response =
+            submit(serde_json::json!({"sensor_id": "", "value": 1.0, "unit": "C"})).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_usage_settles(&db, api_key_id, 1).await;
+
+        // 500: the handler cannot store the reading.
+        sqlx::query("ALTER TABLE readings RENAME TO readings_unavailable")
+            .execute(&*db)
+            .await
+            .unwrap();
+        let response = submit(reading.clone()).await;
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_usage_settles(&db, api_key_id, 1).await;
+        sqlx::query("ALTER TABLE readings_unavailable RENAME TO readings")
+            .execute(&*db)
+            .await
+            .unwrap();
+
+        // 429: the three requests above used up the key's burst.
+        let response = submit(reading).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_usage_settles(&db, api_key_id, 1).await;
+    }
 }
@@ -0,0 +1,63 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+use warp::reject;
+
+#[derive(Debug)]
+pub struct ConcurrencyLimitExceeded {
+    pub limit: i32,
+}
+impl reject::Reject for ConcurrencyLimitExceeded {}
+
+// Counts in-flight requests per key, like a semaphore per key whose size
+// can change between requests. Entries exist only while a key has requests
+// in flight, so the map never outgrows the number of open connections.
+// Process-local: each replica enforces the cap on its own.
+#[derive(Clone, Default)]
+pub struct ConcurrencyLimiter {
+    in_flight: Arc<Mutex<HashMap<Uuid, i32>>>,
+}
+
+impl ConcurrencyLimiter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn try_acquire(&self, api_key_id: Uuid, limit: i32) -> Option<ConcurrencyPermit> {
+        let mut in_flight = self.in_flight.lock().unwrap();
+        let count = in_flight.entry(api_key_id).or_insert(0);
+
+        if *count >= limit {
+            if *count == 0 {
+                in_flight.remove(&api_key_id);
+            }
+            return None;
+        }
+
+        *count += 1;
+
+        Some(ConcurrencyPermit {
+            limiter: self.clone(),
+            api_key_id,
+        })
+    }
+}
+
+// Released when the request's AuthContext is dropped.
+pub struct ConcurrencyPermit {
+    limiter: ConcurrencyLimiter,
+    api_key_id: Uuid,
+}
+
+impl Drop for ConcurrencyPermit {
+    fn drop(&mut self) {
+        let mut in_flight = self.limiter.in_flight.lock().unwrap();
+
+        if let Some(count) = in_flight.get_mut(&self.api_key_id) {
+            *count -= 1;
+            if *count <= 0 {
+                in_flight.remove(&self.api_key_id);
+            }
+        }
+    }
+}
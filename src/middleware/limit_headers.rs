@@ -0,0 +1,150 @@
+use crate::middleware::rate_limiter::RateLimitStatus;
+use crate::models::QuotaStatus;
+use chrono::Utc;
+use std::time::Duration;
+use warp::http::{HeaderMap, HeaderValue};
+
+// Reset and Retry-After values are delta-seconds, rounded up so a client
+// that waits exactly that long is never early.
+pub fn ceil_secs(d: Duration) -> u64 {
+    d.as_secs() + u64::from(d.subsec_nanos() > 0)
+}
+
+pub fn apply(
+    headers: &mut HeaderMap,
+    rate_limit: Option<&RateLimitStatus>,
+    quota: Option<&QuotaStatus>,
+) {
+    if let Some(rate_limit) = rate_limit {
+        let reset = ceil_secs(rate_limit.reset);
+
+        headers.insert("x-ratelimit-limit", HeaderValue::from(rate_limit.limit));
+        headers.insert(
+            "x-ratelimit-remaining",
+            HeaderValue::from(rate_limit.remaining),
+        );
+        headers.insert("x-ratelimit-reset", HeaderValue::from(reset));
+
+        // draft-ietf-httpapi-ratelimit-headers
+        if let Ok(policy) =
+            HeaderValue::from_str(&format!("\"per-minute\";q={};w=60", rate_limit.limit))
+        {
+            headers.insert("ratelimit-policy", policy);
+        }
+        if let Ok(state) = HeaderValue::from_str(&format!(
+            "\"per-minute\";r={};t={}",
+            rate_limit.remaining, reset
+        )) {
+            headers.insert("ratelimit", state);
+        }
+
+        if let Some(retry_after) = rate_limit.retry_after {
+            headers.insert("retry-after", HeaderValue::from(ceil_secs(retry_after)));
+        }
+    }
+
+    if let Some(quota) = quota
+        && let (Some(limit), Some(remaining)) = (quota.limit, quota.remaining)
+    {
+        headers.insert("x-quota-limit", HeaderValue::from(limit));
+        headers.insert("x-quota-remaining", HeaderValue::from(remaining));
+
+        if let Some(end) = quota.period_end
+            && let Ok(until_end) = (end - Utc::now()).to_std()
+        {
+            headers.insert("x-quota-reset", HeaderValue::from(ceil_secs(until_end)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::QuotaPeriod;
+
+    fn header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+        headers.get(name).map(|v| v.to_str().unwrap())
+    }
+
+    #[test]
+    fn ceil_secs_rounds_partial_seconds_up() {
+        assert_eq!(ceil_secs(Duration::ZERO), 0);
+        assert_eq!(ceil_secs(Duration::from_secs(2)), 2);
+        assert_eq!(ceil_secs(Duration::from_millis(2001)), 3);
+        assert_eq!(ceil_secs(Duration::from_nanos(1)), 1);
+    }
+
+    #[test]
+    fn allowed_request_reports_remaining_without_retry_after() {
+        let mut headers = HeaderMap::new();
+        let status = RateLimitStatus {
+            limit: 60,
+            remaining: 4,
+            reset: Duration::from_millis(1500),
+            retry_after: None,
+        };
+
+        apply(&mut headers, Some(&status), None);
+
+        assert_eq!(header(&headers, "x-ratelimit-limit"), Some("60"));
+        assert_eq!(header(&headers, "x-ratelimit-remaining"), Some("4"));
+        assert_eq!(header(&headers, "x-ratelimit-reset"), Some("2"));
+        assert_eq!(
+            header(&headers, "ratelimit-policy"),
+            Some("\"per-minute\";q=60;w=60")
+        );
+        assert_eq!(
+            header(&headers, "ratelimit"),
+            Some("\"per-minute\";r=4;t=2")
+        );
+        assert_eq!(header(&headers, "retry-after"), None);
+        assert_eq!(header(&headers, "x-quota-limit"), None);
+    }
+
+    #[test]
+    fn rejected_request_reports_retry_after() {
+        let mut headers = HeaderMap::new();
+        let status = RateLimitStatus {
+            limit: 60,
+            remaining: 0,
+            reset: Duration::from_secs(10),
+            retry_after: Some(Duration::from_millis(200)),
+        };
+
+        apply(&mut headers, Some(&status), None);
+
+        assert_eq!(header(&headers, "x-ratelimit-remaining"), Some("0"));
+        assert_eq!(header(&headers, "retry-after"), Some("1"));
+    }
+
+    #[test]
+    fn quota_headers_only_for_limited_keys() {
+        let now = Utc::now();
+        let limited = QuotaStatus::new(QuotaPeriod::Daily, now, now, 30, Some(100), now);
+        let unlimited = QuotaStatus::new(QuotaPeriod::Daily, now, now, 30, None, now);
+
+        let mut headers = HeaderMap::new();
+        apply(&mut headers, None, Some(&limited));
+        assert_eq!(header(&headers, "x-quota-limit"), Some("100"));
+        assert_eq!(header(&headers, "x-quota-remaining"), Some("70"));
+        let reset: u64 = header(&headers, "x-quota-reset").unwrap().parse().unwrap();
+        assert!((86_399..=86_400).contains(&reset));
+        assert_eq!(header(&headers, "x-ratelimit-limit"), None);
+
+        let mut headers = HeaderMap::new();
+        apply(&mut headers, None, Some(&unlimited));
+        assert!(headers.is_empty());
+    }
+
+    #[test]
+    fn lifetime_quota_has_no_reset() {
+        let now = Utc::now();
+        let lifetime = QuotaStatus::new(QuotaPeriod::Lifetime, now, now, 0, Some(5), now);
+
+        let mut headers = HeaderMap::new();
+        apply(&mut headers, None, Some(&lifetime));
+
+        assert_eq!(header(&headers, "x-quota-remaining"), Some("5"));
+        assert_eq!(header(&headers, "x-quota-reset"), None);
+    }
+}
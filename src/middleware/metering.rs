@@ -0,0 +1,267 @@
+use crate::db::DbPool;
+use crate::middleware::concurrency::ConcurrencyLimiter;
+use crate::middleware::rate_limiter::RateLimiter;
+use crate::models::ApiKey;
+use crate::webhooks::Webhooks;
+use chrono::{DateTime, Utc};
+use std::sync::Arc;
+use uuid::Uuid;
+use warp::http::StatusCode;
+
+// Everything the auth filter needs to meter a request.
+#[derive(Clone)]
+pub struct Metering {
+    pub limiter: RateLimiter,
+    pub concurrency: ConcurrencyLimiter,
+    // Applied to keys without their own max_concurrent_requests.
+    pub default_max_concurrent: i32,
+    pub billable: BillableStatuses,
+    // Told about quota thresholds and rate limit hits.
+    pub webhooks: Webhooks,
+}
+
+impl Metering {
+    pub fn new(
+        limiter: RateLimiter,
+        default_max_concurrent: i32,
+        billable: BillableStatuses,
+        webhooks: Webhooks,
+    ) -> Self {
+        Self {
+            limiter,
+            concurrency: ConcurrencyLimiter::new(),
+            default_max_concurrent,
+            billable,
+            webhooks,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum StatusMatch {
+    Class(u16),
+    Code(u16),
+}
+
+// Which response statuses count against the quota, e.g. "2xx" or "2xx,404".
+#[derive(Debug, Clone)]
+pub struct BillableStatuses(Arc<Vec<StatusMatch>>);
+
+impl BillableStatuses {
+    pub fn parse(s: &str) -> Option<Self> {
+        let matches = s
+            .split(',')
+            .map(str::trim)
+            .filter(|part| !part.is_empty())
+            .map(|part| {
+                let lower = part.to_ascii_lowercase();
+                match lower.strip_suffix("xx") {
+                    Some(class) => class
+                        .parse::<u16>()
+                        .ok()
+                        .filter(|c| (1..=5).contains(c))
+                        .map(StatusMatch::Class),
+                    None => lower
+                        .parse::<u16>()
+                        .ok()
+                        .filter(|c| (100..=599).contains(c))
+                        .map(StatusMatch::Code),
+                }
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        Some(Self(Arc::new(matches)))
+    }
+
+    pub fn contains(&self, status: StatusCode) -> bool {
+        let code = status.as_u16();
+
+        self.0.iter().any(|m| match *m {
+            StatusMatch::Class(class) => code / 100 == class,
+            StatusMatch::Code(c) => code == c,
+        })
+    }
+}
+
+// Attached to the extensions of a metered response so the request log can
+// record what was charged without looking the key up again.
+#[derive(Debug, Clone, Copy)]
+pub struct MeteredRequest {
+    pub api_key_id: Uuid,
+    pub cost: i32,
+}
+
+// The endpoint's base cost, reserved by the auth filter. Reserving up front
+// keeps concurrent requests from overshooting the quota; the units are handed
+// back unless the request ends in a billable response. That includes requests
+// rejected by a later filter (validation), which simply drop the charge.
+pub struct Charge {
+    db: DbPool,
+    api_key_id: Uuid,
+    // Set when the same amount was also reserved against an endpoint quota.
+    key_limit_id: Option<Uuid>,
+    period_started_at: DateTime<Utc>,
+    amount: i32,
+    committed: bool,
+}
+
+impl Charge {
+    pub fn new(
+        db: DbPool,
+        api_key_id: Uuid,
+        period_started_at: DateTime<Utc>,
+        amount: i32,
+    ) -> Self {
+        Self {
+            db,
+            api_key_id,
+            key_limit_id: None,
+            period_started_at,
+            amount,
+            committed: false,
+        }
+    }
+
+    pub fn with_key_limit(mut self, key_limit_id: Uuid) -> Self {
+        self.key_limit_id = Some(key_limit_id);
+        self
+    }
+
+    // Keeps the reservation and adds `extra` units for work that could only
+    // be priced once the request was handled. The extra units are not checked
+    // against the quota; a key that goes over is rejected from its next
+    // request on.
+    //
+    // Quota thresholds are reported from here rather than when the base cost
+    // is reserved, so a request that ends up refunded never announces a
+    // crossing. The usage read back still includes the reservations of other
+    // in-flight requests for the key.
+    pub fn commit(mut self, extra: i32, api_key: ApiKey, webhooks: Webhooks) {
+        self.committed = true;
+
+        let extra = extra.max(0);
+        if extra == 0 && api_key.quota_limit.is_none() {
+            return;
+        }
+
+        let db = self.db.clone();
+        let key_limit_id = self.key_limit_id;
+        let charged = self.amount.saturating_add(extra);
+
+        tokio::spawn(async move {
+            let result = if extra > 0 {
+                sqlx::query_as::<_, (i32, DateTime<Utc>)>(
+                    r#"
+                    WITH endpoint AS (
+                        UPDATE key_limits SET usage_count = usage_count + $2 WHERE id = $3
+                    )
+                    UPDATE api_keys SET usage_count = usage_count + $2 WHERE id = $1
+                    RETURNING usage_count, period_started_at
+                    "#,
+                )
+                .bind(api_key.id)
+                .bind(extra)
+                .bind(key_limit_id)
+                .fetch_one(&*db)
+                .await
+            } else {
+                sqlx::query_as::<_, (i32, DateTime<Utc>)>(
+                    "SELECT usage_count, period_started_at FROM api_keys WHERE id = $1",
+                )
+                .bind(api_key.id)
+                .fetch_one(&*db)
+                .await
+            };
+
+            match result {
+                // A period that rolled over meanwhile archived this charge.
+                Ok((usage_count, period_started_at))
+                    if period_started_at == api_key.period_started_at =>
+                {
+                    let api_key = ApiKey {
+                        usage_count,
+                        ..api_key
+                    };
+                    webhooks.quota_charged(&api_key, usage_count.saturating_sub(charged));
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    tracing::error!("Failed to commit charge for {}: {:?}", api_key.id, e);
+                }
+            }
+        });
+    }
+}
+
+impl Drop for Charge {
+    fn drop(&mut self) {
+        if self.committed || self.amount == 0 {
+            return;
+        }
+
+        let db = self.db.clone();
+        let api_key_id = self.api_key_id;
+        let key_limit_id = self.key_limit_id;
+        let period_started_at = self.period_started_at;
+        let amount = self.amount;
+
+        tokio::spawn(async move {
+            // Skipped if the quota period rolled over in the meantime; the
+            // unit was archived with the old period.
+            let result = sqlx::query(
+                r#"
+                WITH endpoint AS (
+                    UPDATE key_limits
+                    SET usage_count = GREATEST(usage_count - $3, 0)
+                    WHERE id = $4 AND period_started_at = $2
+                )
+                UPDATE api_keys
+                SET usage_count = GREATEST(usage_count - $3, 0)
+                WHERE id = $1 AND period_started_at = $2
+                "#,
+            )
+            .bind(api_key_id)
+            .bind(period_started_at)
+            .bind(amount)
+            .bind(key_limit_id)
+            .execute(&*db)
+            .await;
+
+            if let Err(e) = result {
+                tracing::error!("Failed to refund quota for {}: {:?}", api_key_id, e);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_classes_and_codes() {
+        let statuses = BillableStatuses::parse("2xx, 404").unwrap();
+
+        assert!(statuses.contains(StatusCode::OK));
+        assert!(statuses.contains(StatusCode::CREATED));
+        assert!(statuses.contains(StatusCode::NOT_FOUND));
+        assert!(!statuses.contains(StatusCode::FORBIDDEN));
+    }
+
+    #[test]
+    fn rejects_invalid_entries() {
+        assert!(BillableStatuses::parse("6xx").is_none());
+        assert!(BillableStatuses::parse("2x").is_none());
+        assert!(BillableStatuses::parse("99").is_none());
+        assert!(BillableStatuses::parse("2xx,abc").is_none());
+    }
+
+    #[test]
+    fn default_does_not_bill_failed_requests() {
+        let statuses = BillableStatuses::parse("2xx").unwrap();
+
+        assert!(!statuses.contains(StatusCode::BAD_REQUEST));
+        assert!(!statuses.contains(StatusCode::TOO_MANY_REQUESTS));
+        assert!(!statuses.contains(StatusCode::INTERNAL_SERVER_ERROR));
+    }
+}
@@ -1,3 +1,8 @@
+pub mod admin_auth;
 pub mod auth;
+pub mod concurrency;
+pub mod limit_headers;
+pub mod metering;
+pub mod quota;
 pub mod rate_limiter;
 pub mod validation;
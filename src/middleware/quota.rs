@@ -0,0 +1,144 @@
+use crate::db::DbPool;
+use crate::models::ApiKey;
+use chrono::{DateTime, Utc};
+
+// Starts a new quota period for the key if the current one has ended,
+// archiving the finished period's consumption. Safe to race: only the first
+// caller sees the stale period_started_at and performs the reset.
+pub async fn roll_over_if_due(
+    db: &DbPool,
+    api_key: &ApiKey,
+    now: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    let Some((period_start, _)) = api_key.quota_period.bounds(api_key.quota_anchor, now) else {
+        return Ok(());
+    };
+
+    if api_key.period_started_at >= period_start {
+        return Ok(());
+    }
+
+    let result = sqlx::query(
+        r#"
+        WITH finished AS (
+            SELECT id, usage_count, quota_limit, period_started_at
+            FROM api_keys
+            WHERE id = $1 AND period_started_at < $2
+            FOR UPDATE
+        ),
+        reset AS (
+            UPDATE api_keys
+            SET usage_count = 0,
+                period_started_at = $2
+            FROM finished
+            WHERE api_keys.id = finished.id
+        )
+        INSERT INTO quota_period_usage (api_key_id, period_start, period_end, usage_count, quota_limit)
+        SELECT id, period_started_at, $2, usage_count, quota_limit
+        FROM finished
+        "#,
+    )
+    .bind(api_key.id)
+    .bind(period_start)
+    .execute(&**db)
+    .await?;
+
+    if result.rows_affected() > 0 {
+        tracing::info!(
+            "Started new {:?} quota period for API key {}",
+            api_key.quota_period,
+            api_key.key_prefix
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{QuotaPeriod, QuotaStatus};
+    use crate::test_support::{apply_migrations, create_api_key};
+    use sqlx::PgPool;
+    use std::sync::Arc;
+
+    const QUOTA_PERIODS_MIGRATION: i64 = 20250825140000;
+
+    async fn fetch_key(db: &DbPool, id: uuid::Uuid) -> ApiKey {
+        sqlx::query_as("SELECT * FROM api_keys WHERE id = $1")
+            .bind(id)
+            .fetch_one(&**db)
+            .await
+            .unwrap()
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn existing_keys_keep_their_lifetime_usage(pool: PgPool) {
+        apply_migrations(&pool, |v| v < QUOTA_PERIODS_MIGRATION).await;
+        let id: uuid::Uuid = sqlx::query_scalar(
+            r#"
+            INSERT INTO api_keys (key_prefix, key_salt, key_hash, name, usage_count, quota_limit, created_at)
+            VALUES ('sk_old', 'salt', 'hash', 'old key', 70, 100, NOW() - INTERVAL '90 days')
+            RETURNING id
+            "#,
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        apply_migrations(&pool, |v| v >= QUOTA_PERIODS_MIGRATION).await;
+        let db = Arc::new(pool);
+
+        let api_key = fetch_key(&db, id).await;
+        assert_eq!(api_key.quota_period, QuotaPeriod::Lifetime);
+        roll_over_if_due(&db, &api_key, Utc::now()).await.unwrap();
+
+        let status: QuotaStatus = fetch_key(&db, id).await.quota_status(Utc::now());
+        assert_eq!(status.used, 70);
+        assert_eq!(status.remaining, Some(30));
+
+        let (_, _new_key) = create_api_key(&db).await;
+        let default_period: QuotaPeriod =
+            sqlx::query_scalar("SELECT quota_period FROM api_keys WHERE id <> $1")
+                .bind(id)
+                .fetch_one(&*db)
+                .await
+                .unwrap();
+        assert_eq!(default_period, QuotaPeriod::Monthly);
+    }
+
+    #[sqlx::test]
+    async fn finished_period_is_archived_and_reset(pool: PgPool) {
+        let db = Arc::new(pool);
+        let (id, _) = create_api_key(&db).await;
+        sqlx::query(
+            r#"
+            UPDATE api_keys
+            SET quota_period = 'daily', usage_count = 12,
+                quota_anchor = NOW() - INTERVAL '36 hours',
+                period_started_at = NOW() - INTERVAL '36 hours'
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .execute(&*db)
+        .await
+        .unwrap();
+
+        let api_key = fetch_key(&db, id).await;
+        roll_over_if_due(&db, &api_key, Utc::now()).await.unwrap();
+        // A second caller holding the stale row must not archive it again.
+        roll_over_if_due(&db, &api_key, Utc::now()).await.unwrap();
+
+        let rolled = fetch_key(&db, id).await;
+        assert_eq!(rolled.usage_count, 0);
+        assert!(rolled.period_started_at > api_key.period_started_at);
+
+        let archived: Vec<i32> =
+            sqlx::query_scalar("SELECT usage_count FROM quota_period_usage WHERE api_key_id = $1")
+                .bind(id)
+                .fetch_all(&*db)
+                .await
+                .unwrap();
+        assert_eq!(archived, vec![12]);
+    }
+}
@@ -0,0 +1,151 @@
+use std::time::{Duration, Instant};
+
+// Outcome of a check, reported back to the client in response headers.
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimitStatus {
+    pub limit: i32,
+    pub remaining: i32,
+    // Until the key's full burst is available again.
+    pub reset: Duration,
+    // Set when the request was rejected.
+    pub retry_after: Option<Duration>,
+}
+
+// GCRA (generic cell rate algorithm), the virtual-scheduling form of a token
+// bucket. Each key stores only its theoretical arrival time (TAT): the time
+// at which its bucket would be full again. A request is allowed when the TAT
+// is no more than `tolerance` ahead of now, and pushes the TAT forward by one
+// emission interval.
+#[derive(Debug, Clone, Copy)]
+pub struct Gcra {
+    pub limit_per_minute: i32,
+    pub emission_interval: Duration,
+    pub tolerance: Duration,
+}
+
+impl Gcra {
+    pub fn new(limit_per_minute: i32, burst: i32) -> Self {
+        let emission_interval = Duration::from_secs(60) / limit_per_minute.max(1) as u32;
+        let tolerance = emission_interval * (burst.max(1) as u32 - 1);
+
+        Self {
+            limit_per_minute,
+            emission_interval,
+            tolerance,
+        }
+    }
+
+    // Returns the new TAT if the request is allowed, or the unchanged TAT if
+    // it is not.
+    pub fn check(&self, tat: Option<Instant>, now: Instant) -> Result<Instant, Instant> {
+        let tat = tat.map_or(now, |t| t.max(now));
+
+        if tat.duration_since(now) > self.tolerance {
+            return Err(tat);
+        }
+
+        Ok(tat + self.emission_interval)
+    }
+
+    // `ahead` is how far the TAT returned by `check` is ahead of now.
+    pub fn status(&self, allowed: bool, ahead: Duration) -> RateLimitStatus {
+        if !allowed {
+            return RateLimitStatus {
+                limit: self.limit_per_minute,
+                remaining: 0,
+                reset: ahead,
+                retry_after: Some(ahead.saturating_sub(self.tolerance)),
+            };
+        }
+
+        let headroom = (self.tolerance + self.emission_interval).saturating_sub(ahead);
+
+        RateLimitStatus {
+            limit: self.limit_per_minute,
+            remaining: (headroom.as_nanos() / self.emission_interval.as_nanos()) as i32,
+            reset: ahead,
+            retry_after: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Sends `count` back-to-back requests at `now`, returning the final TAT.
+    fn drain(gcra: &Gcra, mut tat: Option<Instant>, now: Instant, count: usize) -> Instant {
+        for _ in 0..count {
+            tat = Some(gcra.check(tat, now).expect("request within burst"));
+        }
+        tat.unwrap()
+    }
+
+    #[test]
+    fn burst_allows_that_many_requests_at_once() {
+        let gcra = Gcra::new(60, 5);
+        let now = Instant::now();
+
+        let tat = drain(&gcra, None, now, 5);
+
+        assert_eq!(tat - now, Duration::from_secs(5));
+        assert_eq!(gcra.check(Some(tat), now), Err(tat));
+    }
+
+    #[test]
+    fn burst_of_one_spaces_requests_by_the_emission_interval() {
+        let gcra = Gcra::new(120, 1);
+        let now = Instant::now();
+
+        assert_eq!(gcra.tolerance, Duration::ZERO);
+        let tat = drain(&gcra, None, now, 1);
+        assert!(gcra.check(Some(tat), now).is_err());
+        assert!(
+            gcra.check(Some(tat), now + Duration::from_millis(499))
+                .is_err()
+        );
+        assert!(
+            gcra.check(Some(tat), now + Duration::from_millis(500))
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn idle_time_refills_the_bucket_but_not_beyond_the_burst() {
+        let gcra = Gcra::new(60, 3);
+        let now = Instant::now();
+        let tat = drain(&gcra, None, now, 3);
+
+        let later = now + Duration::from_secs(60);
+        let tat = drain(&gcra, Some(tat), later, 3);
+
+        assert!(gcra.check(Some(tat), later).is_err());
+    }
+
+    #[test]
+    fn status_counts_down_remaining_and_reports_retry_after() {
+        let gcra = Gcra::new(60, 3);
+        let now = Instant::now();
+
+        let remaining: Vec<i32> = (1..=3)
+            .map(|n| {
+                gcra.status(true, drain(&gcra, None, now, n) - now)
+                    .remaining
+            })
+            .collect();
+        assert_eq!(remaining, vec![2, 1, 0]);
+
+        let rejected = gcra.status(false, Duration::from_secs(3));
+        assert_eq!(rejected.remaining, 0);
+        assert_eq!(rejected.reset, Duration::from_secs(3));
+        assert_eq!(rejected.retry_after, Some(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn non_positive_limits_are_treated_as_one() {
+        let gcra = Gcra::new(0, 0);
+
+        assert_eq!(gcra.emission_interval, Duration::from_secs(60));
+        assert_eq!(gcra.tolerance, Duration::ZERO);
+    }
+}
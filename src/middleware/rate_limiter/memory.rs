@@ -0,0 +1,168 @@
+use super::gcra::{Gcra, RateLimitStatus};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use uuid::Uuid;
+
+const SHARDS: usize = 64;
+
+// Process-local state. Each replica enforces the full limit on its own.
+// Keys are spread over independently locked shards so concurrent requests
+// for different keys rarely contend, and each shard holds at most its share
+// of `max_tracked_keys`.
+#[derive(Clone)]
+pub struct MemoryRateLimiter {
+    shards: Arc<Vec<Mutex<HashMap<Uuid, Instant>>>>,
+    max_per_shard: usize,
+}
+
+impl MemoryRateLimiter {
+    pub fn new(max_tracked_keys: usize) -> Self {
+        Self {
+            shards: Arc::new((0..SHARDS).map(|_| Mutex::new(HashMap::new())).collect()),
+            max_per_shard: max_tracked_keys.div_ceil(SHARDS).max(1),
+        }
+    }
+
+    fn shard(&self, bucket: Uuid) -> &Mutex<HashMap<Uuid, Instant>> {
+        &self.shards[(bucket.as_u128() % SHARDS as u128) as usize]
+    }
+
+    pub(super) fn check(&self, bucket: Uuid, gcra: Gcra) -> RateLimitStatus {
+        let now = Instant::now();
+
+        let mut arrivals = self.shard(bucket).lock().unwrap();
+        let tat = arrivals.get(&bucket).copied();
+
+        match gcra.check(tat, now) {
+            Ok(new_tat) => {
+                if tat.is_none() && arrivals.len() >= self.max_per_shard {
+                    make_room(&mut arrivals, self.max_per_shard, now);
+                }
+                arrivals.insert(bucket, new_tat);
+                gcra.status(true, new_tat - now)
+            }
+            Err(tat) => gcra.status(false, tat - now),
+        }
+    }
+
+    pub(super) fn refund(&self, bucket: Uuid, gcra: Gcra) {
+        let mut arrivals = self.shard(bucket).lock().unwrap();
+
+        if let Some(tat) = arrivals.get_mut(&bucket)
+            && let Some(earlier) = tat.checked_sub(gcra.emission_interval)
+        {
+            *tat = earlier;
+        }
+    }
+
+    pub(super) fn evict_idle(&self) -> usize {
+        let now = Instant::now();
+
+        self.shards
+            .iter()
+            .map(|shard| {
+                let mut arrivals = shard.lock().unwrap();
+                let before = arrivals.len();
+                arrivals.retain(|_, tat| *tat > now);
+                before - arrivals.len()
+            })
+            .sum()
+    }
+}
+
+// Called when a full shard sees a new key. Idle keys go first; if every key
+// is still active, the one closest to a full bucket is dropped, which at
+// worst lets that key burst slightly early.
+fn make_room(arrivals: &mut HashMap<Uuid, Instant>, max: usize, now: Instant) {
+    arrivals.retain(|_, tat| *tat > now);
+
+    if arrivals.len() >= max
+        && let Some(oldest) = arrivals
+            .iter()
+            .min_by_key(|(_, tat)| **tat)
+            .map(|(id, _)| *id)
+    {
+        arrivals.remove(&oldest);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn refund_returns_a_token() {
+        let limiter = MemoryRateLimiter::new(100);
+        let gcra = Gcra::new(60, 1);
+        let key = Uuid::new_v4();
+
+        assert!(limiter.check(key, gcra).retry_after.is_none());
+        assert!(limiter.check(key, gcra).retry_after.is_some());
+
+        limiter.refund(key, gcra);
+        assert!(limiter.check(key, gcra).retry_after.is_none());
+    }
+
+    #[test]
+    fn make_room_drops_idle_keys_first() {
+        let now = Instant::now();
+        let (idle, busy, busier) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let mut arrivals = HashMap::from([
+            (idle, now - Duration::from_secs(1)),
+            (busy, now + Duration::from_secs(1)),
+            (busier, now + Duration::from_secs(5)),
+        ]);
+
+        make_room(&mut arrivals, 3, now);
+
+        assert_eq!(arrivals.len(), 2);
+        assert!(!arrivals.contains_key(&idle));
+    }
+
+    #[test]
+    fn make_room_drops_the_key_closest_to_a_full_bucket() {
+        let now = Instant::now();
+        let (busy, busier) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut arrivals = HashMap::from([
+            (busy, now + Duration::from_secs(1)),
+            (busier, now + Duration::from_secs(5)),
+        ]);
+
+        make_room(&mut arrivals, 2, now);
+
+        assert_eq!(arrivals.keys().collect::<Vec<_>>(), vec![&busier]);
+    }
+
+    #[test]
+    fn tracked_keys_never_exceed_the_cap() {
+        let limiter = MemoryRateLimiter::new(SHARDS);
+        let gcra = Gcra::new(1, 1);
+
+        for _ in 0..SHARDS * 10 {
+            assert!(limiter.check(Uuid::new_v4(), gcra).retry_after.is_none());
+        }
+
+        for shard in limiter.shards.iter() {
+            assert!(shard.lock().unwrap().len() <= 1);
+        }
+    }
+
+    #[test]
+    fn evict_idle_keeps_keys_that_are_still_limited() {
+        let limiter = MemoryRateLimiter::new(100);
+        let key = Uuid::new_v4();
+        let idle = Uuid::new_v4();
+
+        limiter.check(key, Gcra::new(1, 1));
+        limiter
+            .shard(idle)
+            .lock()
+            .unwrap()
+            .insert(idle, Instant::now() - Duration::from_secs(1));
+
+        assert_eq!(limiter.evict_idle(), 1);
+        assert!(limiter.check(key, Gcra::new(1, 1)).retry_after.is_some());
+    }
+}
@@ -0,0 +1,228 @@
+use crate::db::DbPool;
+use std::time::Duration;
+use uuid::Uuid;
+use warp::{Rejection, reject};
+
+mod gcra;
+mod memory;
+
+pub use gcra::{Gcra, RateLimitStatus};
+pub use memory::MemoryRateLimiter;
+
+#[derive(Debug)]
+pub struct RateLimitExceeded(pub RateLimitStatus);
+impl reject::Reject for RateLimitExceeded {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitBackend {
+    Memory,
+    Postgres,
+}
+
+impl RateLimitBackend {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "memory" => Some(RateLimitBackend::Memory),
+            "postgres" => Some(RateLimitBackend::Postgres),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub enum RateLimiter {
+    Memory(MemoryRateLimiter),
+    Postgres(PostgresRateLimiter),
+}
+
+impl RateLimiter {
+    // `fail_open` only matters for the Postgres backend, whose checks can fail.
+    pub fn new(
+        backend: RateLimitBackend,
+        db: DbPool,
+        max_tracked_keys: usize,
+        fail_open: bool,
+    ) -> Self {
+        match backend {
+            RateLimitBackend::Memory => {
+                RateLimiter::Memory(MemoryRateLimiter::new(max_tracked_keys))
+            }
+            RateLimitBackend::Postgres => {
+                RateLimiter::Postgres(PostgresRateLimiter::new(db, fail_open))
+            }
+        }
+    }
+
+    // Drops state for keys whose TAT has passed. Such a key has its full
+    // burst available again, exactly as if it had never been seen, so this
+    // never changes a decision.
+    pub async fn evict_idle(&self) -> usize {
+        match self {
+            RateLimiter::Memory(limiter) => limiter.evict_idle(),
+            RateLimiter::Postgres(limiter) => limiter.evict_idle().await,
+        }
+    }
+
+    // `bucket` is the key's id, or a key_limits id for an endpoint limit.
+    pub async fn check_rate_limit(
+        &self,
+        bucket: Uuid,
+        limit: i32,
+        burst: i32,
+    ) -> Result<RateLimitStatus, Rejection> {
+        let gcra = Gcra::new(limit, burst);
+
+        let status = match self {
+            RateLimiter::Memory(limiter) => limiter.check(bucket, gcra),
+            RateLimiter::Postgres(limiter) => limiter.check(bucket, gcra).await,
+        };
+
+        match status.retry_after {
+            None => Ok(status),
+            Some(_) => Err(reject::custom(RateLimitExceeded(status))),
+        }
+    }
+
+    // Hands back the token an allowed `check_rate_limit` took, for a request
+    // that a later check turned away before it ran.
+    pub async fn refund(&self, bucket: Uuid, limit: i32, burst: i32) {
+        let gcra = Gcra::new(limit, burst);
+
+        match self {
+            RateLimiter::Memory(limiter) => limiter.refund(bucket, gcra),
+            RateLimiter::Postgres(limiter) => limiter.refund(bucket, gcra).await,
+        }
+    }
+}
+
+// Shared state in the `rate_limit_state` table, so every replica pointed at
+// the same database enforces one limit per key. The GCRA step runs as a single
+// UPSERT against the database clock, which keeps it atomic and independent of
+// clock skew between replicas. When the database cannot be reached, requests
+// are rejected unless `fail_open` is set.
+#[derive(Clone)]
+pub struct PostgresRateLimiter {
+    db: DbPool,
+    fail_open: bool,
+}
+
+impl PostgresRateLimiter {
+    pub fn new(db: DbPool, fail_open: bool) -> Self {
+        Self { db, fail_open }
+    }
+
+    async fn check(&self, bucket: Uuid, gcra: Gcra) -> RateLimitStatus {
+        // The fallback SELECT reads the snapshot from before the UPSERT, so
+        // when the request is rejected it returns the unchanged TAT.
+        let result = sqlx::query_as::<_, (bool, f64)>(
+            r#"
+            WITH allowed AS (
+                INSERT INTO rate_limit_state AS s (bucket_id, tat)
+                VALUES ($1, NOW() + make_interval(secs => $2))
+                ON CONFLICT (bucket_id) DO UPDATE
+                SET tat = GREATEST(s.tat, NOW()) + make_interval(secs => $2)
+                WHERE GREATEST(s.tat, NOW()) - NOW() <= make_interval(secs => $3)
+                RETURNING tat
+            )
+            SELECT true, EXTRACT(EPOCH FROM tat - NOW())::float8 FROM allowed
+            UNION ALL
+            SELECT false, EXTRACT(EPOCH FROM tat - NOW())::float8 FROM rate_limit_state
+            WHERE bucket_id = $1 AND NOT EXISTS (SELECT 1 FROM allowed)
+            "#,
+        )
+        .bind(bucket)
+        .bind(gcra.emission_interval.as_secs_f64())
+        .bind(gcra.tolerance.as_secs_f64())
+        .fetch_optional(&*self.db)
+        .await;
+
+        match result {
+            Ok(Some((allowed, ahead))) => {
+                gcra.status(allowed, Duration::from_secs_f64(ahead.max(0.0)))
+            }
+            Ok(None) => gcra.status(true, gcra.emission_interval),
+            Err(e) if self.fail_open => {
+                tracing::error!("Rate limit check failed, allowing request: {:?}", e);
+                gcra.status(true, gcra.emission_interval)
+            }
+            Err(e) => {
+                tracing::error!("Rate limit check failed, rejecting request: {:?}", e);
+                RateLimitStatus {
+                    limit: gcra.limit_per_minute,
+                    remaining: 0,
+                    reset: gcra.emission_interval,
+                    retry_after: Some(gcra.emission_interval),
+                }
+            }
+        }
+    }
+
+    async fn refund(&self, bucket: Uuid, gcra: Gcra) {
+        let result = sqlx::query(
+            "UPDATE rate_limit_state SET tat = tat - make_interval(secs => $2) WHERE bucket_id = $1",
+        )
+        .bind(bucket)
+        .bind(gcra.emission_interval.as_secs_f64())
+        .execute(&*self.db)
+        .await;
+
+        if let Err(e) = result {
+            tracing::error!("Failed to refund rate limit token: {:?}", e);
+        }
+    }
+
+    async fn evict_idle(&self) -> usize {
+        let result = sqlx::query("DELETE FROM rate_limit_state WHERE tat <= NOW()")
+            .execute(&*self.db)
+            .await;
+
+        match result {
+            Ok(res) => res.rows_affected() as usize,
+            Err(e) => {
+                tracing::error!("Failed to evict idle rate limit state: {:?}", e);
+                0
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::PgPool;
+    use std::sync::Arc;
+
+    async fn allowed(limiter: &PostgresRateLimiter, bucket: Uuid, gcra: Gcra) -> bool {
+        limiter.check(bucket, gcra).await.retry_after.is_none()
+    }
+
+    #[sqlx::test]
+    async fn postgres_backend_enforces_burst_and_refunds(pool: PgPool) {
+        let limiter = PostgresRateLimiter::new(Arc::new(pool), false);
+        let gcra = Gcra::new(60, 2);
+        let bucket = Uuid::new_v4();
+
+        assert!(allowed(&limiter, bucket, gcra).await);
+        assert!(allowed(&limiter, bucket, gcra).await);
+        assert!(!allowed(&limiter, bucket, gcra).await);
+
+        limiter.refund(bucket, gcra).await;
+        assert!(allowed(&limiter, bucket, gcra).await);
+    }
+
+    #[sqlx::test]
+    async fn failed_checks_reject_unless_failing_open(pool: PgPool) {
+        let db = Arc::new(pool);
+        sqlx::query("DROP TABLE rate_limit_state")
+            .execute(&*db)
+            .await
+            .unwrap();
+        let gcra = Gcra::new(60, 10);
+
+        let closed = PostgresRateLimiter::new(db.clone(), false);
+        assert!(!allowed(&closed, Uuid::new_v4(), gcra).await);
+
+        let open = PostgresRateLimiter::new(db, true);
+        assert!(allowed(&open, Uuid::new_v4(), gcra).await);
+    }
+}
@@ -1,22 +1,17 @@
-use serde::Deserialize;
+use chrono::{DateTime, Duration, Utc};
 use warp::{Filter, Rejection, body, reject};
 
 #[derive(Debug)]
-#[allow(dead_code)]
 pub struct ValidationError(pub String);
 impl reject::Reject for ValidationError {}
 
-#[derive(Deserialize, serde::Serialize)]
-pub struct ErrorResponse {
-    pub error: String,
-    pub code: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub details: Option<String>,
-}
-
 const MAX_SENSOR_ID_LENGTH: usize = 100;
 const MAX_UNIT_LENGTH: usize = 50;
 const MAX_BODY_SIZE: u64 = 1024 * 1024;
+// How far a device clock may run ahead of ours, and how old a buffered
+// reading may be.
+const MAX_CLOCK_SKEW_SECONDS: i64 = 5 * 60;
+const MAX_READING_AGE_DAYS: i64 = 30;
 
 pub struct Validator;
 
@@ -83,6 +78,37 @@ impl Validator {
         Ok(())
     }
 
+    pub fn recorded_at(recorded_at: DateTime<Utc>) -> Result<(), String> {
+        let now = Utc::now();
+
+        if recorded_at > now + Duration::seconds(MAX_CLOCK_SKEW_SECONDS) {
+            return Err(format!(
+                "Timestamp is more than {} seconds in the future",
+                MAX_CLOCK_SKEW_SECONDS
+            ));
+        }
+
+        if recorded_at < now - Duration::days(MAX_READING_AGE_DAYS) {
+            return Err(format!(
+                "Timestamp is more than {} days in the past",
+                MAX_READING_AGE_DAYS
+            ));
+        }
+
+        Ok(())
+    }
+
+    pub fn reading(reading: &crate::models::ReadingRequest) -> Result<(), String> {
+        Validator::sensor_id(&reading.sensor_id).map_err(|e| format!("sensor_id: {}", e))?;
+        Validator::unit(&reading.unit).map_err(|e| format!("unit: {}", e))?;
+        Validator::value(reading.value).map_err(|e| format!("value: {}", e))?;
+        if let Some(recorded_at) = reading.recorded_at {
+            Validator::recorded_at(recorded_at).map_err(|e| format!("recorded_at: {}", e))?;
+        }
+
+        Ok(())
+    }
+
     pub fn body_limit() -> impl Filter<Extract = (), Error = Rejection> + Clone {
         body::content_length_limit(MAX_BODY_SIZE)
     }
@@ -91,15 +117,57 @@ impl Validator {
 pub fn validate_reading_request()
 -> impl Filter<Extract = (crate::models::ReadingRequest,), Error = warp::Rejection> + Clone {
     warp::body::json().and_then(|reading: crate::models::ReadingRequest| async move {
-        Validator::sensor_id(&reading.sensor_id)
-            .map_err(|e| warp::reject::custom(ValidationError(format!("sensor_id: {}", e))))?;
+        Validator::reading(&reading).map_err(|e| warp::reject::custom(ValidationError(e)))?;
 
-        Validator::unit(&reading.unit)
-            .map_err(|e| warp::reject::custom(ValidationError(format!("unit: {}", e))))?;
+        Ok::<_, warp::Rejection>(reading)
+    })
+}
 
-        Validator::value(reading.value)
-            .map_err(|e| warp::reject::custom(ValidationError(format!("value: {}", e))))?;
+// Only the batch as a whole is checked here. Items are kept as raw JSON so
+// that a malformed item is rejected on its own instead of failing the batch.
+pub fn validate_reading_batch(
+    max_items: usize,
+) -> impl Filter<Extract = (Vec<serde_json::Value>,), Error = warp::Rejection> + Clone {
+    warp::body::json().and_then(move |items: Vec<serde_json::Value>| async move {
+        if items.is_empty() {
+            return Err(warp::reject::custom(ValidationError(
+                "Batch must contain at least one reading".to_string(),
+            )));
+        }
 
-        Ok::<_, warp::Rejection>(reading)
+        if items.len() > max_items {
+            return Err(warp::reject::custom(ValidationError(format!(
+                "Batch exceeds maximum size of {} readings",
+                max_items
+            ))));
+        }
+
+        Ok(items)
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recorded_at_allows_small_clock_skew_and_buffered_readings() {
+        let now = Utc::now();
+
+        assert!(Validator::recorded_at(now).is_ok());
+        assert!(
+            Validator::recorded_at(now + Duration::seconds(MAX_CLOCK_SKEW_SECONDS - 5)).is_ok()
+        );
+        assert!(Validator::recorded_at(now - Duration::days(MAX_READING_AGE_DAYS - 1)).is_ok());
+    }
+
+    #[test]
+    fn recorded_at_rejects_far_future_and_stale_timestamps() {
+        let now = Utc::now();
+
+        let future = Validator::recorded_at(now + Duration::seconds(MAX_CLOCK_SKEW_SECONDS + 5));
+        assert!(future.unwrap_err().contains("in the future"));
+        let stale = Validator::recorded_at(now - Duration::days(MAX_READING_AGE_DAYS + 1));
+        assert!(stale.unwrap_err().contains("in the past"));
+    }
+}
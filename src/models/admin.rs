@@ -0,0 +1,52 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+// Roles are ordered by privilege so a route can require "at least" a role.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, sqlx::Type,
+)]
+#[sqlx(type_name = "admin_role", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum AdminRole {
+    Viewer,
+    Operator,
+    Owner,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct AdminToken {
+    pub id: Uuid,
+    pub token_prefix: String,
+    #[serde(skip_serializing)]
+    pub token_salt: String,
+    #[serde(skip_serializing)]
+    pub token_hash: String,
+    pub name: String,
+    pub role: AdminRole,
+    pub is_active: bool,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateAdminTokenRequest {
+    pub name: String,
+    pub role: AdminRole,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreateAdminTokenResponse {
+    pub id: Uuid,
+    pub token: String,
+    pub token_prefix: String,
+    pub name: String,
+    pub role: AdminRole,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AdminTokenListResponse {
+    pub tokens: Vec<AdminToken>,
+}
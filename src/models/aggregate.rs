@@ -0,0 +1,148 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub struct AggregateParams {
+    pub sensor_id: String,
+    pub bucket: String,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    #[serde(rename = "fn")]
+    pub functions: Option<String>,
+    pub convert_to: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AggregateFn {
+    Count,
+    Min,
+    Max,
+    Avg,
+    Sum,
+    P50,
+    P90,
+    P95,
+    P99,
+}
+
+impl AggregateFn {
+    pub const ALL: [AggregateFn; 9] = [
+        AggregateFn::Count,
+        AggregateFn::Min,
+        AggregateFn::Max,
+        AggregateFn::Avg,
+        AggregateFn::Sum,
+        AggregateFn::P50,
+        AggregateFn::P90,
+        AggregateFn::P95,
+        AggregateFn::P99,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AggregateFn::Count => "count",
+            AggregateFn::Min => "min",
+            AggregateFn::Max => "max",
+            AggregateFn::Avg => "avg",
+            AggregateFn::Sum => "sum",
+            AggregateFn::P50 => "p50",
+            AggregateFn::P90 => "p90",
+            AggregateFn::P95 => "p95",
+            AggregateFn::P99 => "p99",
+        }
+    }
+
+    // The aggregate of `value` over a bucket's readings, aliased to
+    // `as_str()`. `value` is a fixed SQL expression, never user input.
+    pub fn sql(self, value: &str) -> String {
+        match self {
+            AggregateFn::Count => format!("COUNT({value}) AS count"),
+            AggregateFn::Min => format!("MIN({value}) AS min"),
+            AggregateFn::Max => format!("MAX({value}) AS max"),
+            AggregateFn::Avg => format!("AVG({value}) AS avg"),
+            AggregateFn::Sum => format!("SUM({value}) AS sum"),
+            AggregateFn::P50 => {
+                format!("percentile_cont(0.5) WITHIN GROUP (ORDER BY {value}) AS p50")
+            }
+            AggregateFn::P90 => {
+                format!("percentile_cont(0.9) WITHIN GROUP (ORDER BY {value}) AS p90")
+            }
+            AggregateFn::P95 => {
+                format!("percentile_cont(0.95) WITHIN GROUP (ORDER BY {value}) AS p95")
+            }
+            AggregateFn::P99 => {
+                format!("percentile_cont(0.99) WITHIN GROUP (ORDER BY {value}) AS p99")
+            }
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<AggregateFn> {
+        AggregateFn::ALL.into_iter().find(|f| f.as_str() == s)
+    }
+
+    // A comma-separated list like "avg,max"; duplicates are dropped.
+    pub fn parse_list(s: &str) -> Result<Vec<AggregateFn>, String> {
+        let mut functions = Vec::new();
+
+        for name in s.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+            let f = AggregateFn::parse(name)
+                .ok_or_else(|| format!("Unknown aggregate function: {}", name))?;
+            if !functions.contains(&f) {
+                functions.push(f);
+            }
+        }
+
+        if functions.is_empty() {
+            return Err("fn must name at least one aggregate function".to_string());
+        }
+
+        Ok(functions)
+    }
+}
+
+// Bucket width such as "15m", "1h" or "1d".
+#[derive(Debug, Clone, Copy)]
+pub struct BucketWidth(pub Duration);
+
+impl BucketWidth {
+    pub fn parse(s: &str) -> Option<BucketWidth> {
+        let split = s.find(|c: char| !c.is_ascii_digit())?;
+        let (count, unit) = s.split_at(split);
+        let count: i64 = count.parse().ok().filter(|n| *n > 0)?;
+
+        let width = match unit {
+            "m" => Duration::try_minutes(count)?,
+            "h" => Duration::try_hours(count)?,
+            "d" => Duration::try_days(count)?,
+            _ => return None,
+        };
+
+        Some(BucketWidth(width))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bucket_widths() {
+        assert_eq!(BucketWidth::parse("15m").unwrap().0, Duration::minutes(15));
+        assert_eq!(BucketWidth::parse("1h").unwrap().0, Duration::hours(1));
+        assert_eq!(BucketWidth::parse("7d").unwrap().0, Duration::days(7));
+
+        for invalid in ["", "h", "0h", "-1h", "1w", "1.5h", "99999999999999d"] {
+            assert!(BucketWidth::parse(invalid).is_none(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn function_lists_drop_duplicates_and_blanks() {
+        assert_eq!(
+            AggregateFn::parse_list("avg, max,avg,").unwrap(),
+            vec![AggregateFn::Avg, AggregateFn::Max]
+        );
+        assert!(AggregateFn::parse_list(" , ").is_err());
+        assert!(AggregateFn::parse_list("avg,median").is_err());
+    }
+}
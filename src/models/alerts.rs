@@ -0,0 +1,130 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+use super::double_option;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "alert_comparison", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum Comparison {
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+}
+
+impl Comparison {
+    pub fn matches(self, value: f64, threshold: f64) -> bool {
+        match self {
+            Comparison::Gt => value > threshold,
+            Comparison::Gte => value >= threshold,
+            Comparison::Lt => value < threshold,
+            Comparison::Lte => value <= threshold,
+        }
+    }
+
+    // A firing alert only resolves once the value is back within the
+    // threshold moved `hysteresis` towards the safe side, so a value hovering
+    // around the threshold does not flap.
+    pub fn recovered(self, value: f64, threshold: f64, hysteresis: f64) -> bool {
+        match self {
+            Comparison::Gt | Comparison::Gte => !self.matches(value, threshold - hysteresis),
+            Comparison::Lt | Comparison::Lte => !self.matches(value, threshold + hysteresis),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "alert_status", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum AlertStatus {
+    Pending,
+    Firing,
+    Resolved,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct AlertRule {
+    pub id: Uuid,
+    pub api_key_id: Uuid,
+    pub name: String,
+    pub sensor_id: Option<String>,
+    pub sensor_tag: Option<String>,
+    pub comparison: Comparison,
+    pub threshold: f64,
+    pub unit: Option<String>,
+    pub duration_seconds: i32,
+    pub hysteresis: f64,
+    pub enabled: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateAlertRuleRequest {
+    pub name: String,
+    pub sensor_id: Option<String>,
+    pub sensor_tag: Option<String>,
+    pub comparison: Comparison,
+    pub threshold: f64,
+    pub unit: Option<String>,
+    pub duration_seconds: Option<i32>,
+    pub hysteresis: Option<f64>,
+    pub enabled: Option<bool>,
+}
+
+// Same rules as UpdateApiKeyRequest: `null` clears a field, omitting it
+// leaves it alone.
+#[derive(Debug, Deserialize)]
+pub struct UpdateAlertRuleRequest {
+    pub name: Option<String>,
+    #[serde(default, deserialize_with = "double_option")]
+    pub sensor_id: Option<Option<String>>,
+    #[serde(default, deserialize_with = "double_option")]
+    pub sensor_tag: Option<Option<String>>,
+    pub comparison: Option<Comparison>,
+    pub threshold: Option<f64>,
+    #[serde(default, deserialize_with = "double_option")]
+    pub unit: Option<Option<String>>,
+    pub duration_seconds: Option<i32>,
+    pub hysteresis: Option<f64>,
+    pub enabled: Option<bool>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AlertRuleListResponse {
+    pub rules: Vec<AlertRule>,
+}
+
+// Values are in the rule's unit when it has one.
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Alert {
+    pub id: Uuid,
+    pub rule_id: Uuid,
+    pub api_key_id: Uuid,
+    pub sensor_id: String,
+    pub status: AlertStatus,
+    pub started_at: DateTime<Utc>,
+    pub fired_at: Option<DateTime<Utc>>,
+    pub resolved_at: Option<DateTime<Utc>>,
+    pub trigger_value: f64,
+    pub last_value: f64,
+    pub last_reading_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AlertsParams {
+    pub status: Option<AlertStatus>,
+    pub sensor_id: Option<String>,
+    pub rule_id: Option<Uuid>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AlertListResponse {
+    pub alerts: Vec<Alert>,
+}
@@ -1,5 +1,5 @@
 use chrono::{DateTime, Utc};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use sqlx::FromRow;
 use uuid::Uuid;
 
@@ -8,6 +8,93 @@ pub struct ReadingRequest {
     pub sensor_id: String,
     pub value: f64,
     pub unit: String,
+    #[serde(default, deserialize_with = "timestamp")]
+    pub recorded_at: Option<DateTime<Utc>>,
+}
+
+// Devices send either an RFC 3339 string or milliseconds since the epoch.
+fn timestamp<'de, D>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Timestamp {
+        Rfc3339(String),
+        EpochMillis(i64),
+    }
+
+    match Option::<Timestamp>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(Timestamp::Rfc3339(s)) => DateTime::parse_from_rfc3339(&s)
+            .map(|t| Some(t.with_timezone(&Utc)))
+            .map_err(|_| serde::de::Error::custom("recorded_at must be an RFC 3339 timestamp")),
+        Some(Timestamp::EpochMillis(ms)) => DateTime::from_timestamp_millis(ms)
+            .map(Some)
+            .ok_or_else(|| serde::de::Error::custom("recorded_at is out of range")),
+    }
+}
+
+// Filters for GET /readings. Time and value bounds are inclusive.
+#[derive(Debug, Deserialize)]
+pub struct ReadingsParams {
+    pub sensor_id: Option<String>,
+    pub unit: Option<String>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub min_value: Option<f64>,
+    pub max_value: Option<f64>,
+    // Converts values to this unit where the stored unit allows it.
+    pub convert_to: Option<String>,
+    #[serde(default)]
+    pub order: SortOrder,
+    pub limit: Option<i64>,
+    pub cursor: Option<String>,
+}
+
+// Filters for GET /readings/stream and /readings/ws.
+#[derive(Debug, Deserialize)]
+pub struct ReadingStreamParams {
+    pub sensor_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+// Position of the last reading on a page, handed to clients as an opaque hex
+// string. Pages are ordered by (recorded_at, id), so the next page starts
+// strictly after this pair.
+#[derive(Debug, Clone, Copy)]
+pub struct ReadingCursor {
+    pub recorded_at: DateTime<Utc>,
+    pub id: Uuid,
+}
+
+impl ReadingCursor {
+    pub fn encode(&self) -> String {
+        let mut bytes = self.recorded_at.timestamp_micros().to_be_bytes().to_vec();
+        bytes.extend_from_slice(self.id.as_bytes());
+        hex::encode(bytes)
+    }
+
+    pub fn decode(s: &str) -> Option<Self> {
+        let bytes = hex::decode(s).ok()?;
+        if bytes.len() != 24 {
+            return None;
+        }
+
+        let micros = i64::from_be_bytes(bytes[..8].try_into().ok()?);
+
+        Some(Self {
+            recorded_at: DateTime::from_timestamp_micros(micros)?,
+            id: Uuid::from_slice(&bytes[8..]).ok()?,
+        })
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -23,9 +110,11 @@ pub struct ReadingData {
     pub sensor_id: String,
     pub value: f64,
     pub unit: String,
+    pub raw_unit: String,
+    pub recorded_at: DateTime<Utc>,
 }
 
-#[derive(Debug, Serialize, FromRow)]
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct Reading {
     pub id: Uuid,
     pub api_key_id: Uuid,
@@ -33,4 +122,84 @@ pub struct Reading {
     pub value: f64,
     pub unit: String,
     pub created_at: DateTime<Utc>,
+    pub recorded_at: DateTime<Utc>,
+    pub raw_unit: String,
+}
+
+// Outcome for one item of a batch, in request order.
+#[derive(Debug, Serialize)]
+pub struct BatchItemResult {
+    pub index: usize,
+    pub status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<Uuid>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReadingBatchResponse {
+    pub status: String,
+    pub accepted: usize,
+    pub rejected: usize,
+    pub timestamp: DateTime<Utc>,
+    pub results: Vec<BatchItemResult>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn recorded_at(body: serde_json::Value) -> Result<Option<DateTime<Utc>>, serde_json::Error> {
+        serde_json::from_value::<ReadingRequest>(body).map(|r| r.recorded_at)
+    }
+
+    #[test]
+    fn recorded_at_accepts_rfc3339_and_epoch_millis() {
+        let expected = Utc.with_ymd_and_hms(2025, 8, 25, 10, 0, 0).unwrap();
+        let reading = |recorded_at| serde_json::json!({"sensor_id": "s1", "value": 1.0, "unit": "C", "recorded_at": recorded_at});
+
+        assert_eq!(
+            recorded_at(reading(serde_json::json!("2025-08-25T12:00:00+02:00"))).unwrap(),
+            Some(expected)
+        );
+        assert_eq!(
+            recorded_at(reading(serde_json::json!(expected.timestamp_millis()))).unwrap(),
+            Some(expected)
+        );
+        assert_eq!(recorded_at(reading(serde_json::Value::Null)).unwrap(), None);
+        assert_eq!(
+            recorded_at(serde_json::json!({"sensor_id": "s1", "value": 1.0, "unit": "C"})).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn recorded_at_rejects_other_formats() {
+        for value in [
+            serde_json::json!("2025-08-25 12:00"),
+            serde_json::json!(i64::MAX),
+            serde_json::json!(true),
+        ] {
+            let body = serde_json::json!({"sensor_id": "s1", "value": 1.0, "unit": "C", "recorded_at": value});
+            assert!(recorded_at(body).is_err(), "{value}");
+        }
+    }
+
+    #[test]
+    fn cursor_round_trips_and_rejects_garbage() {
+        let cursor = ReadingCursor {
+            recorded_at: Utc.with_ymd_and_hms(2025, 8, 25, 10, 0, 0).unwrap()
+                + chrono::Duration::microseconds(123),
+            id: Uuid::new_v4(),
+        };
+
+        let decoded = ReadingCursor::decode(&cursor.encode()).unwrap();
+        assert_eq!(decoded.recorded_at, cursor.recorded_at);
+        assert_eq!(decoded.id, cursor.id);
+
+        assert!(ReadingCursor::decode("not hex").is_none());
+        assert!(ReadingCursor::decode(&cursor.encode()[..46]).is_none());
+    }
 }
@@ -0,0 +1,191 @@
+use crate::models::{ApiKey, QuotaStatus, Scope};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+// The metered routes, i.e. the ones authenticated with an API key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Endpoint {
+    SubmitReading,
+    SubmitReadingBatch,
+    ListReadings,
+    AggregateReadings,
+    StreamReadings,
+    ReadingsSocket,
+    OwnUsage,
+    ListSensors,
+    CreateSensor,
+    GetSensor,
+    UpdateSensor,
+    DeleteSensor,
+    ListAlerts,
+    ListAlertRules,
+    CreateAlertRule,
+    GetAlertRule,
+    UpdateAlertRule,
+    DeleteAlertRule,
+}
+
+impl Endpoint {
+    pub const ALL: [Endpoint; 18] = [
+        Endpoint::SubmitReading,
+        Endpoint::SubmitReadingBatch,
+        Endpoint::ListReadings,
+        Endpoint::AggregateReadings,
+        Endpoint::StreamReadings,
+        Endpoint::ReadingsSocket,
+        Endpoint::OwnUsage,
+        Endpoint::ListSensors,
+        Endpoint::CreateSensor,
+        Endpoint::GetSensor,
+        Endpoint::UpdateSensor,
+        Endpoint::DeleteSensor,
+        Endpoint::ListAlerts,
+        Endpoint::ListAlertRules,
+        Endpoint::CreateAlertRule,
+        Endpoint::GetAlertRule,
+        Endpoint::UpdateAlertRule,
+        Endpoint::DeleteAlertRule,
+    ];
+
+    pub fn method(self) -> &'static str {
+        match self {
+            Endpoint::SubmitReading
+            | Endpoint::SubmitReadingBatch
+            | Endpoint::CreateSensor
+            | Endpoint::CreateAlertRule => "POST",
+            Endpoint::ListReadings
+            | Endpoint::AggregateReadings
+            | Endpoint::StreamReadings
+            | Endpoint::ReadingsSocket
+            | Endpoint::OwnUsage
+            | Endpoint::ListSensors
+            | Endpoint::GetSensor
+            | Endpoint::ListAlerts
+            | Endpoint::ListAlertRules
+            | Endpoint::GetAlertRule => "GET",
+            Endpoint::UpdateSensor | Endpoint::UpdateAlertRule => "PATCH",
+            Endpoint::DeleteSensor | Endpoint::DeleteAlertRule => "DELETE",
+        }
+    }
+
+    pub fn route(self) -> &'static str {
+        match self {
+            Endpoint::SubmitReading | Endpoint::ListReadings => "/readings",
+            Endpoint::SubmitReadingBatch => "/readings/batch",
+            Endpoint::AggregateReadings => "/readings/aggregate",
+            Endpoint::StreamReadings => "/readings/stream",
+            Endpoint::ReadingsSocket => "/readings/ws",
+            Endpoint::OwnUsage => "/usage",
+            Endpoint::ListSensors | Endpoint::CreateSensor => "/sensors",
+            Endpoint::GetSensor | Endpoint::UpdateSensor | Endpoint::DeleteSensor => {
+                "/sensors/{sensor_id}"
+            }
+            Endpoint::ListAlerts => "/alerts",
+            Endpoint::ListAlertRules | Endpoint::CreateAlertRule => "/alerts/rules",
+            Endpoint::GetAlertRule | Endpoint::UpdateAlertRule | Endpoint::DeleteAlertRule => {
+                "/alerts/rules/{rule_id}"
+            }
+        }
+    }
+
+    pub fn scope(self) -> Scope {
+        match self {
+            Endpoint::SubmitReading
+            | Endpoint::SubmitReadingBatch
+            | Endpoint::CreateSensor
+            | Endpoint::UpdateSensor
+            | Endpoint::DeleteSensor
+            | Endpoint::CreateAlertRule
+            | Endpoint::UpdateAlertRule
+            | Endpoint::DeleteAlertRule => Scope::ReadingsWrite,
+            Endpoint::ListReadings
+            | Endpoint::AggregateReadings
+            | Endpoint::StreamReadings
+            | Endpoint::ReadingsSocket
+            | Endpoint::ListSensors
+            | Endpoint::GetSensor
+            | Endpoint::ListAlerts
+            | Endpoint::ListAlertRules
+            | Endpoint::GetAlertRule => Scope::ReadingsRead,
+            Endpoint::OwnUsage => Scope::UsageRead,
+        }
+    }
+
+    pub fn parse(method: &str, route: &str) -> Option<Endpoint> {
+        Endpoint::ALL
+            .into_iter()
+            .find(|e| e.method().eq_ignore_ascii_case(method) && e.route() == route)
+    }
+}
+
+// Quota units charged per request: `base_cost` when the request is
+// authenticated, plus `per_item_cost` for each reading it returns or stores.
+#[derive(Debug, Serialize, FromRow)]
+pub struct EndpointCost {
+    pub method: String,
+    pub route: String,
+    pub base_cost: i32,
+    pub per_item_cost: i32,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateEndpointCostRequest {
+    pub method: String,
+    pub route: String,
+    pub base_cost: i32,
+    pub per_item_cost: i32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EndpointCostListResponse {
+    pub costs: Vec<EndpointCost>,
+}
+
+// Per-endpoint limits for one key, applied on top of the key-wide ones.
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct KeyLimit {
+    pub id: Uuid,
+    pub api_key_id: Uuid,
+    pub method: String,
+    pub route: String,
+    pub rate_limit_per_minute: Option<i32>,
+    pub rate_limit_burst: Option<i32>,
+    pub quota_limit: Option<i32>,
+    pub usage_count: i32,
+    pub period_started_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl KeyLimit {
+    // Endpoint quotas follow the key's quota period.
+    pub fn quota_status(&self, api_key: &ApiKey, now: DateTime<Utc>) -> QuotaStatus {
+        QuotaStatus::new(
+            api_key.quota_period,
+            api_key.quota_anchor,
+            self.period_started_at,
+            self.usage_count,
+            self.quota_limit,
+            now,
+        )
+    }
+}
+
+// Omitted or null limits are not enforced for the endpoint.
+#[derive(Debug, Deserialize)]
+pub struct SetKeyLimitRequest {
+    pub method: String,
+    pub route: String,
+    pub rate_limit_per_minute: Option<i32>,
+    pub rate_limit_burst: Option<i32>,
+    pub quota_limit: Option<i32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct KeyLimitListResponse {
+    pub limits: Vec<KeyLimit>,
+}
@@ -1,5 +1,5 @@
 use chrono::{DateTime, Utc};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use sqlx::FromRow;
 use uuid::Uuid;
 
@@ -12,10 +12,35 @@ pub use usage::*;
 pub mod metrics;
 pub use metrics::*;
 
+pub mod admin;
+pub use admin::*;
+
+pub mod quota;
+pub use quota::*;
+
+pub mod metering;
+pub use metering::*;
+
+pub mod aggregate;
+pub use aggregate::*;
+
+pub mod sensors;
+pub use sensors::*;
+
+pub mod alerts;
+pub use alerts::*;
+
+pub mod webhooks;
+pub use webhooks::*;
+
 #[derive(Debug, Serialize, Deserialize, FromRow)]
 pub struct ApiKey {
     pub id: Uuid,
-    pub key: String,
+    pub key_prefix: String,
+    #[serde(skip_serializing)]
+    pub key_salt: String,
+    #[serde(skip_serializing)]
+    pub key_hash: String,
     pub name: String,
     pub usage_count: i32,
     pub is_active: bool,
@@ -23,18 +48,128 @@ pub struct ApiKey {
     pub updated_at: DateTime<Utc>,
     pub quota_limit: Option<i32>,
     pub rate_limit_per_minute: i32,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub rotated_at: Option<DateTime<Utc>>,
+    pub previous_key_prefix: Option<String>,
+    #[serde(skip_serializing)]
+    pub previous_key_salt: Option<String>,
+    #[serde(skip_serializing)]
+    pub previous_key_hash: Option<String>,
+    pub previous_key_expires_at: Option<DateTime<Utc>>,
+    pub previous_key_last_used_at: Option<DateTime<Utc>>,
+    pub not_before: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub scopes: Vec<String>,
+    pub quota_period: QuotaPeriod,
+    pub quota_anchor: DateTime<Utc>,
+    pub period_started_at: DateTime<Utc>,
+    pub rate_limit_burst: Option<i32>,
+    pub max_concurrent_requests: Option<i32>,
+}
+
+impl ApiKey {
+    pub fn burst(&self) -> i32 {
+        self.rate_limit_burst.unwrap_or(self.rate_limit_per_minute)
+    }
+
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        self.scopes.iter().any(|s| s == scope.as_str())
+    }
+
+    pub fn quota_status(&self, now: DateTime<Utc>) -> QuotaStatus {
+        QuotaStatus::new(
+            self.quota_period,
+            self.quota_anchor,
+            self.period_started_at,
+            self.usage_count,
+            self.quota_limit,
+            now,
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    ReadingsWrite,
+    ReadingsRead,
+    UsageRead,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 3] = [Scope::ReadingsWrite, Scope::ReadingsRead, Scope::UsageRead];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Scope::ReadingsWrite => "readings:write",
+            Scope::ReadingsRead => "readings:read",
+            Scope::UsageRead => "usage:read",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Scope> {
+        Scope::ALL.into_iter().find(|scope| scope.as_str() == s)
+    }
 }
 
 #[derive(Debug, Deserialize)]
 pub struct CreateApiKeyRequest {
     pub name: String,
+    pub not_before: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub scopes: Option<Vec<String>>,
+    pub quota_period: Option<QuotaPeriod>,
+    pub quota_anchor: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Serialize)]
 pub struct CreateApiKeyResponse {
     pub id: Uuid,
     pub key: String,
+    pub key_prefix: String,
+    pub name: String,
+    pub not_before: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub scopes: Vec<String>,
+}
+
+// Every field is optional; only the ones present in the body are changed.
+// `quota_limit: null` removes the quota, while omitting it leaves it alone.
+#[derive(Debug, Deserialize)]
+pub struct UpdateApiKeyRequest {
+    pub name: Option<String>,
+    #[serde(default, deserialize_with = "double_option")]
+    pub quota_limit: Option<Option<i32>>,
+    pub rate_limit_per_minute: Option<i32>,
+    #[serde(default, deserialize_with = "double_option")]
+    pub rate_limit_burst: Option<Option<i32>>,
+    #[serde(default, deserialize_with = "double_option")]
+    pub max_concurrent_requests: Option<Option<i32>>,
+    pub is_active: Option<bool>,
+    pub quota_period: Option<QuotaPeriod>,
+    pub quota_anchor: Option<DateTime<Utc>>,
+}
+
+fn double_option<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RotateApiKeyParams {
+    pub grace_period_seconds: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RotateApiKeyResponse {
+    pub id: Uuid,
+    pub key: String,
+    pub key_prefix: String,
     pub name: String,
+    pub previous_key_prefix: Option<String>,
+    pub previous_key_expires_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Serialize)]
@@ -45,8 +180,54 @@ pub struct ApiKeyListResponse {
 #[derive(Debug, Serialize)]
 pub struct ApiKeyInfo {
     pub id: Uuid,
+    pub key_prefix: String,
     pub name: String,
     pub usage_count: i32,
+    pub quota_limit: Option<i32>,
+    pub rate_limit_per_minute: i32,
+    pub rate_limit_burst: i32,
+    // `null` means the server's default cap applies.
+    pub max_concurrent_requests: Option<i32>,
     pub is_active: bool,
     pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub rotated_at: Option<DateTime<Utc>>,
+    pub previous_key_prefix: Option<String>,
+    pub previous_key_expires_at: Option<DateTime<Utc>>,
+    pub previous_key_last_used_at: Option<DateTime<Utc>>,
+    pub not_before: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub scopes: Vec<String>,
+    pub quota: QuotaStatus,
+}
+
+impl From<ApiKey> for ApiKeyInfo {
+    fn from(k: ApiKey) -> Self {
+        let quota = k.quota_status(Utc::now());
+        let rate_limit_burst = k.burst();
+
+        ApiKeyInfo {
+            id: k.id,
+            key_prefix: k.key_prefix,
+            name: k.name,
+            usage_count: k.usage_count,
+            quota_limit: k.quota_limit,
+            rate_limit_per_minute: k.rate_limit_per_minute,
+            rate_limit_burst,
+            max_concurrent_requests: k.max_concurrent_requests,
+            is_active: k.is_active,
+            created_at: k.created_at,
+            updated_at: k.updated_at,
+            last_used_at: k.last_used_at,
+            rotated_at: k.rotated_at,
+            previous_key_prefix: k.previous_key_prefix,
+            previous_key_expires_at: k.previous_key_expires_at,
+            previous_key_last_used_at: k.previous_key_last_used_at,
+            not_before: k.not_before,
+            expires_at: k.expires_at,
+            scopes: k.scopes,
+            quota,
+        }
+    }
 }
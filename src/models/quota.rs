@@ -0,0 +1,197 @@
+use chrono::{DateTime, Datelike, Duration, Months, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "quota_period", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum QuotaPeriod {
+    Lifetime,
+    Daily,
+    Monthly,
+}
+
+impl QuotaPeriod {
+    // Start and end of the period containing `now`, counted in whole periods
+    // from `anchor`. Lifetime quotas never reset, so they have no bounds.
+    pub fn bounds(
+        self,
+        anchor: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+        match self {
+            QuotaPeriod::Lifetime => None,
+            QuotaPeriod::Daily => {
+                let days = (now - anchor).num_seconds().div_euclid(86_400);
+                let start = anchor + Duration::days(days);
+                Some((start, start + Duration::days(1)))
+            }
+            QuotaPeriod::Monthly => {
+                let mut months =
+                    (now.year() - anchor.year()) * 12 + now.month() as i32 - anchor.month() as i32;
+                if shift_months(anchor, months) > now {
+                    months -= 1;
+                }
+                Some((
+                    shift_months(anchor, months),
+                    shift_months(anchor, months + 1),
+                ))
+            }
+        }
+    }
+}
+
+// Always shifts from the anchor itself so a period anchored on the 31st
+// lands on the last day of shorter months without drifting afterwards.
+fn shift_months(anchor: DateTime<Utc>, months: i32) -> DateTime<Utc> {
+    let shifted = if months >= 0 {
+        anchor.checked_add_months(Months::new(months as u32))
+    } else {
+        anchor.checked_sub_months(Months::new(months.unsigned_abs()))
+    };
+
+    shifted.unwrap_or(anchor)
+}
+
+#[derive(Debug, Serialize)]
+pub struct QuotaStatus {
+    pub period: QuotaPeriod,
+    pub period_start: DateTime<Utc>,
+    pub period_end: Option<DateTime<Utc>>,
+    pub limit: Option<i32>,
+    pub used: i32,
+    pub remaining: Option<i32>,
+}
+
+impl QuotaStatus {
+    pub fn new(
+        period: QuotaPeriod,
+        anchor: DateTime<Utc>,
+        period_started_at: DateTime<Utc>,
+        usage_count: i32,
+        limit: Option<i32>,
+        now: DateTime<Utc>,
+    ) -> Self {
+        // A key that has been idle since its period ended has not been reset
+        // yet, but nothing has been used in the current period.
+        let (period_start, period_end, used) = match period.bounds(anchor, now) {
+            Some((start, end)) if period_started_at < start => (start, Some(end), 0),
+            Some((start, end)) => (start, Some(end), usage_count),
+            None => (period_started_at, None, usage_count),
+        };
+
+        QuotaStatus {
+            period,
+            period_start,
+            period_end,
+            limit,
+            used,
+            remaining: limit.map(|l| (l - used).max(0)),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct QuotaPeriodUsage {
+    pub id: Uuid,
+    pub api_key_id: Uuid,
+    pub period_start: DateTime<Utc>,
+    pub period_end: DateTime<Utc>,
+    pub usage_count: i32,
+    pub quota_limit: Option<i32>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct QuotaHistoryResponse {
+    pub current: QuotaStatus,
+    pub periods: Vec<QuotaPeriodUsage>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(y: i32, m: u32, d: u32, h: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(y, m, d, h, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn lifetime_has_no_bounds() {
+        assert_eq!(
+            QuotaPeriod::Lifetime.bounds(at(2025, 1, 1, 0), at(2030, 1, 1, 0)),
+            None
+        );
+    }
+
+    #[test]
+    fn daily_periods_start_at_the_anchor_time() {
+        let anchor = at(2025, 3, 10, 6);
+
+        assert_eq!(
+            QuotaPeriod::Daily.bounds(anchor, at(2025, 3, 12, 5)),
+            Some((at(2025, 3, 11, 6), at(2025, 3, 12, 6)))
+        );
+        assert_eq!(
+            QuotaPeriod::Daily.bounds(anchor, at(2025, 3, 12, 6)),
+            Some((at(2025, 3, 12, 6), at(2025, 3, 13, 6)))
+        );
+    }
+
+    #[test]
+    fn monthly_period_before_the_anchor_day_belongs_to_previous_month() {
+        let anchor = at(2025, 1, 15, 0);
+
+        assert_eq!(
+            QuotaPeriod::Monthly.bounds(anchor, at(2025, 4, 14, 23)),
+            Some((at(2025, 3, 15, 0), at(2025, 4, 15, 0)))
+        );
+        assert_eq!(
+            QuotaPeriod::Monthly.bounds(anchor, at(2025, 4, 15, 0)),
+            Some((at(2025, 4, 15, 0), at(2025, 5, 15, 0)))
+        );
+    }
+
+    #[test]
+    fn monthly_anchor_on_the_31st_clamps_without_drifting() {
+        let anchor = at(2025, 1, 31, 0);
+
+        assert_eq!(
+            QuotaPeriod::Monthly.bounds(anchor, at(2025, 2, 28, 12)),
+            Some((at(2025, 2, 28, 0), at(2025, 3, 31, 0)))
+        );
+        assert_eq!(
+            QuotaPeriod::Monthly.bounds(anchor, at(2025, 4, 30, 12)),
+            Some((at(2025, 4, 30, 0), at(2025, 5, 31, 0)))
+        );
+    }
+
+    #[test]
+    fn shift_months_goes_both_ways_from_the_anchor() {
+        let anchor = at(2024, 3, 31, 0);
+
+        assert_eq!(shift_months(anchor, 0), anchor);
+        assert_eq!(shift_months(anchor, 1), at(2024, 4, 30, 0));
+        assert_eq!(shift_months(anchor, -1), at(2024, 2, 29, 0));
+        assert_eq!(shift_months(anchor, 11), at(2025, 2, 28, 0));
+    }
+
+    #[test]
+    fn idle_key_reports_an_empty_current_period() {
+        let anchor = at(2025, 1, 1, 0);
+        let status = QuotaStatus::new(
+            QuotaPeriod::Daily,
+            anchor,
+            anchor,
+            40,
+            Some(100),
+            at(2025, 1, 3, 12),
+        );
+
+        assert_eq!(status.used, 0);
+        assert_eq!(status.remaining, Some(100));
+        assert_eq!(status.period_start, at(2025, 1, 3, 0));
+    }
+}
@@ -0,0 +1,55 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+use super::double_option;
+
+// A registered sensor. `unit`, `min_value` and `max_value` describe what its
+// readings are expected to look like.
+#[derive(Debug, Serialize, FromRow)]
+pub struct Sensor {
+    pub id: Uuid,
+    pub api_key_id: Uuid,
+    pub sensor_id: String,
+    pub name: String,
+    pub location: Option<String>,
+    pub tags: Vec<String>,
+    pub unit: Option<String>,
+    pub min_value: Option<f64>,
+    pub max_value: Option<f64>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateSensorRequest {
+    pub sensor_id: String,
+    pub name: Option<String>,
+    pub location: Option<String>,
+    pub tags: Option<Vec<String>>,
+    pub unit: Option<String>,
+    pub min_value: Option<f64>,
+    pub max_value: Option<f64>,
+}
+
+// Same rules as UpdateApiKeyRequest: `null` clears a field, omitting it
+// leaves it alone.
+#[derive(Debug, Deserialize)]
+pub struct UpdateSensorRequest {
+    pub name: Option<String>,
+    #[serde(default, deserialize_with = "double_option")]
+    pub location: Option<Option<String>>,
+    pub tags: Option<Vec<String>>,
+    #[serde(default, deserialize_with = "double_option")]
+    pub unit: Option<Option<String>>,
+    #[serde(default, deserialize_with = "double_option")]
+    pub min_value: Option<Option<f64>>,
+    #[serde(default, deserialize_with = "double_option")]
+    pub max_value: Option<Option<f64>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SensorListResponse {
+    pub sensors: Vec<Sensor>,
+}
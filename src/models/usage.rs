@@ -3,6 +3,8 @@ use serde::Serialize;
 use sqlx::FromRow;
 use uuid::Uuid;
 
+use crate::models::QuotaStatus;
+
 #[allow(dead_code)]
 #[derive(Debug, Serialize, FromRow)]
 pub struct Request {
@@ -22,6 +24,7 @@ pub struct UsageStats {
     pub requests_today: i64,
     pub requests_this_month: i64,
     pub last_used: Option<DateTime<Utc>>,
+    pub quota: QuotaStatus,
 }
 
 #[derive(Debug, Serialize)]
@@ -0,0 +1,133 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+use super::double_option;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WebhookEvent {
+    QuotaWarning,
+    QuotaExhausted,
+    KeyCreated,
+    KeyDeleted,
+    KeyRotated,
+    RateLimitExceeded,
+    AlertFired,
+    AlertResolved,
+}
+
+impl WebhookEvent {
+    pub const ALL: [WebhookEvent; 8] = [
+        WebhookEvent::QuotaWarning,
+        WebhookEvent::QuotaExhausted,
+        WebhookEvent::KeyCreated,
+        WebhookEvent::KeyDeleted,
+        WebhookEvent::KeyRotated,
+        WebhookEvent::RateLimitExceeded,
+        WebhookEvent::AlertFired,
+        WebhookEvent::AlertResolved,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            WebhookEvent::QuotaWarning => "quota.warning",
+            WebhookEvent::QuotaExhausted => "quota.exhausted",
+            WebhookEvent::KeyCreated => "key.created",
+            WebhookEvent::KeyDeleted => "key.deleted",
+            WebhookEvent::KeyRotated => "key.rotated",
+            WebhookEvent::RateLimitExceeded => "rate_limit.exceeded",
+            WebhookEvent::AlertFired => "alert.fired",
+            WebhookEvent::AlertResolved => "alert.resolved",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<WebhookEvent> {
+        WebhookEvent::ALL.into_iter().find(|e| e.as_str() == s)
+    }
+}
+
+// A subscription. Without an api_key_id it receives events for every key.
+// The signing secret is deliberately not loaded.
+#[derive(Debug, Serialize, FromRow)]
+pub struct Webhook {
+    pub id: Uuid,
+    pub url: String,
+    pub events: Vec<String>,
+    pub api_key_id: Option<Uuid>,
+    pub description: Option<String>,
+    pub is_active: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateWebhookRequest {
+    pub url: String,
+    pub events: Vec<String>,
+    pub api_key_id: Option<Uuid>,
+    pub description: Option<String>,
+}
+
+// The signing secret is only ever returned here.
+#[derive(Debug, Serialize)]
+pub struct CreateWebhookResponse {
+    #[serde(flatten)]
+    pub webhook: Webhook,
+    pub secret: String,
+}
+
+// Same rules as UpdateApiKeyRequest: `null` clears a field, omitting it
+// leaves it alone.
+#[derive(Debug, Deserialize)]
+pub struct UpdateWebhookRequest {
+    pub url: Option<String>,
+    pub events: Option<Vec<String>>,
+    #[serde(default, deserialize_with = "double_option")]
+    pub description: Option<Option<String>>,
+    pub is_active: Option<bool>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WebhookListResponse {
+    pub webhooks: Vec<Webhook>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "webhook_delivery_status", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryStatus {
+    Pending,
+    Delivered,
+    Failed,
+}
+
+// One attempt series at getting an event to a webhook. Replaying an event
+// adds a new delivery, so earlier ones stay in the log.
+#[derive(Debug, Serialize, FromRow)]
+pub struct WebhookDelivery {
+    pub id: Uuid,
+    pub webhook_id: Uuid,
+    pub event_id: Uuid,
+    pub event_type: String,
+    pub status: DeliveryStatus,
+    pub attempts: i32,
+    pub next_attempt_at: DateTime<Utc>,
+    pub last_attempt_at: Option<DateTime<Utc>>,
+    pub response_status: Option<i16>,
+    pub last_error: Option<String>,
+    pub delivered_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WebhookDeliveriesParams {
+    pub status: Option<DeliveryStatus>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WebhookDeliveryListResponse {
+    pub deliveries: Vec<WebhookDelivery>,
+}
@@ -0,0 +1,330 @@
+use crate::db::DbPool;
+use crate::feed::ReadingFeed;
+use crate::handlers;
+use crate::middleware::auth::{self, InsufficientScope, KeyExpired, KeyNotYetActive, Unauthorized};
+use crate::middleware::metering::{MeteredRequest, Metering};
+use crate::middleware::validation::Validator;
+use crate::models::{Endpoint, ReadingRequest};
+use crate::sensors::SensorPolicy;
+use anyhow::{Result, anyhow, bail};
+use bytes::BytesMut;
+use chrono::Utc;
+use rumqttc::mqttbytes::{self, Protocol, QoS};
+use rumqttc::{
+    ConnAck, ConnectReturnCode, Packet, PubAck, PubComp, PubRec, Publish, SubAck,
+    SubscribeReasonCode, UnsubAck,
+};
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use uuid::Uuid;
+use warp::Reply;
+use warp::http::StatusCode;
+
+// Readings are published to sensors/{sensor_id}.
+const TOPIC_PREFIX: &str = "sensors/";
+const MAX_PACKET_SIZE: usize = 64 * 1024;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+// An ingest-only MQTT 3.1.1 endpoint. Devices connect with their API key as
+// the password (or as the username, if they send no password) and publish
+// readings; nothing is ever delivered to subscribers. Each message goes
+// through the same auth, metering, validation and storage as POST /readings.
+#[derive(Clone)]
+pub struct MqttIngest {
+    pub db: DbPool,
+    pub metering: Metering,
+    pub feed: ReadingFeed,
+    pub sensor_policy: SensorPolicy,
+}
+
+pub fn spawn_mqtt_listener(listener: TcpListener, ingest: MqttIngest) {
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, peer)) => {
+                    let ingest = ingest.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = ingest.serve(stream).await {
+                            tracing::debug!("Closed MQTT connection from {}: {}", peer, e);
+                        }
+                    });
+                }
+                Err(e) => {
+                    tracing::error!("Failed to accept MQTT connection: {:?}", e);
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                }
+            }
+        }
+    });
+}
+
+struct Connection {
+    stream: TcpStream,
+    buffer: BytesMut,
+}
+
+impl Connection {
+    // None once the client has closed the connection.
+    async fn read(&mut self) -> Result<Option<Packet>> {
+        loop {
+            match Packet::read(&mut self.buffer, MAX_PACKET_SIZE) {
+                Ok(packet) => return Ok(Some(packet)),
+                Err(mqttbytes::Error::InsufficientBytes(_)) => {}
+                Err(e) => return Err(e.into()),
+            }
+
+            if self.stream.read_buf(&mut self.buffer).await? == 0 {
+                return Ok(None);
+            }
+        }
+    }
+
+    async fn write(&mut self, packet: Packet) -> Result<()> {
+        let mut bytes = BytesMut::new();
+        packet.write(&mut bytes, MAX_PACKET_SIZE)?;
+        self.stream.write_all(&bytes).await?;
+        Ok(())
+    }
+}
+
+impl MqttIngest {
+    async fn serve(self, stream: TcpStream) -> Result<()> {
+        let mut conn = Connection {
+            stream,
+            buffer: BytesMut::new(),
+        };
+
+        let connect = match tokio::time::timeout(CONNECT_TIMEOUT, conn.read()).await?? {
+            Some(Packet::Connect(connect)) => connect,
+            Some(packet) => bail!("expected CONNECT, got {:?}", packet),
+            None => return Ok(()),
+        };
+
+        if !matches!(connect.protocol, Protocol::V4) {
+            let refused = ConnAck::new(ConnectReturnCode::RefusedProtocolVersion, false);
+            return conn.write(Packet::ConnAck(refused)).await;
+        }
+
+        let key = match connect.login {
+            Some(login) if !login.password.is_empty() => login.password,
+            Some(login) => login.username,
+            None => String::new(),
+        };
+
+        let api_key_id = match self.authenticate(&key).await {
+            Ok(id) => id,
+            Err(code) => return conn.write(Packet::ConnAck(ConnAck::new(code, false))).await,
+        };
+
+        conn.write(Packet::ConnAck(ConnAck::new(
+            ConnectReturnCode::Success,
+            false,
+        )))
+        .await?;
+
+        tracing::info!(
+            "MQTT client {} connected with API key ID: {}",
+            connect.client_id,
+            api_key_id
+        );
+
+        // The spec allows one and a half keep-alive periods of silence.
+        let idle_timeout = (connect.keep_alive > 0)
+            .then(|| Duration::from_millis(u64::from(connect.keep_alive) * 1500));
+        // QoS 2 messages that were stored but not yet released by the client,
+        // so a resend is not stored twice.
+        let mut unreleased = HashSet::new();
+
+        loop {
+            let packet = match idle_timeout {
+                Some(timeout) => tokio::time::timeout(timeout, conn.read())
+                    .await
+                    .map_err(|_| anyhow!("keep-alive expired"))??,
+                None => conn.read().await?,
+            };
+
+            let Some(packet) = packet else {
+                return Ok(());
+            };
+
+            match packet {
+                Packet::Publish(publish) => {
+                    if !(publish.qos == QoS::ExactlyOnce && unreleased.contains(&publish.pkid)) {
+                        let status = self.ingest(&key, api_key_id, &publish).await?;
+
+                        // MQTT 3.1.1 cannot refuse a message, but a client
+                        // resends QoS 1 and 2 messages it never saw acked.
+                        // Failures that may pass on a retry end the
+                        // connection instead of acking; the rest are acked
+                        // and dropped.
+                        let retryable =
+                            status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error();
+                        if retryable && publish.qos != QoS::AtMostOnce {
+                            bail!(
+                                "reading on {} not stored ({}), closing so it is resent",
+                                publish.topic,
+                                status
+                            );
+                        }
+                    }
+
+                    match publish.qos {
+                        QoS::AtMostOnce => {}
+                        QoS::AtLeastOnce => {
+                            conn.write(Packet::PubAck(PubAck::new(publish.pkid)))
+                                .await?
+                        }
+                        QoS::ExactlyOnce => {
+                            unreleased.insert(publish.pkid);
+                            conn.write(Packet::PubRec(PubRec::new(publish.pkid)))
+                                .await?
+                        }
+                    }
+                }
+                Packet::PubRel(release) => {
+                    unreleased.remove(&release.pkid);
+                    conn.write(Packet::PubComp(PubComp::new(release.pkid)))
+                        .await?;
+                }
+                Packet::Subscribe(subscribe) => {
+                    let codes = vec![SubscribeReasonCode::Failure; subscribe.filters.len()];
+                    conn.write(Packet::SubAck(SubAck::new(subscribe.pkid, codes)))
+                        .await?;
+                }
+                Packet::Unsubscribe(unsubscribe) => {
+                    conn.write(Packet::UnsubAck(UnsubAck::new(unsubscribe.pkid)))
+                        .await?;
+                }
+                Packet::PingReq => conn.write(Packet::PingResp).await?,
+                Packet::Disconnect => return Ok(()),
+                packet => bail!("unexpected {:?}", packet),
+            }
+        }
+    }
+
+    // Checks the key without charging it; each message is metered on its own.
+    async fn authenticate(&self, key: &str) -> Result<Uuid, ConnectReturnCode> {
+        if key.is_empty() {
+            return Err(ConnectReturnCode::BadUserNamePassword);
+        }
+
+        let api_key = match auth::find_api_key(&self.db, key).await {
+            Ok(Some((api_key, _))) => api_key,
+            Ok(None) => return Err(ConnectReturnCode::BadUserNamePassword),
+            Err(e) => {
+                tracing::error!("Database error during MQTT authentication: {:?}", e);
+                return Err(ConnectReturnCode::ServiceUnavailable);
+            }
+        };
+
+        let now = Utc::now();
+        let usable = api_key.is_active
+            && api_key.expires_at.is_none_or(|t| t > now)
+            && api_key.not_before.is_none_or(|t| t <= now)
+            && api_key.has_scope(Endpoint::SubmitReading.scope());
+
+        if !usable {
+            return Err(ConnectReturnCode::NotAuthorized);
+        }
+
+        Ok(api_key.id)
+    }
+
+    // Stores one published reading, logs it in `requests` under its topic
+    // and returns the status a POST /readings would have got. A key that
+    // stops being valid ends the connection.
+    async fn ingest(&self, key: &str, api_key_id: Uuid, publish: &Publish) -> Result<StatusCode> {
+        let started = Instant::now();
+
+        let (status, cost) = match publish.topic.strip_prefix(TOPIC_PREFIX) {
+            None => (StatusCode::NOT_FOUND, 0),
+            Some(sensor_id) => {
+                let result = auth::validate_api_key(
+                    Some(key.to_string()),
+                    self.db.clone(),
+                    self.metering.clone(),
+                    Endpoint::SubmitReading,
+                )
+                .await;
+
+                match result {
+                    Err(rejection) => {
+                        let revoked = rejection.find::<Unauthorized>().is_some()
+                            || rejection.find::<KeyExpired>().is_some()
+                            || rejection.find::<KeyNotYetActive>().is_some()
+                            || rejection.find::<InsufficientScope>().is_some();
+                        if revoked {
+                            bail!("API key is no longer valid");
+                        }
+
+                        let Ok(reply) = auth::handle_rejection(rejection).await;
+                        (reply.into_response().status(), 0)
+                    }
+                    // Dropping the AuthContext refunds the reserved cost, as
+                    // for an HTTP request that fails validation.
+                    Ok(auth) => match parse_reading(sensor_id, &publish.payload) {
+                        Err(e) => {
+                            tracing::warn!("Rejected MQTT reading on {}: {}", publish.topic, e);
+                            (StatusCode::BAD_REQUEST, 0)
+                        }
+                        Ok(reading) => {
+                            let Ok(reply) = handlers::business::submit_reading(
+                                auth,
+                                self.db.clone(),
+                                self.feed.clone(),
+                                self.sensor_policy,
+                                reading,
+                            )
+                            .await;
+                            let response = reply.into_response();
+                            let cost = response
+                                .extensions()
+                                .get::<MeteredRequest>()
+                                .map_or(0, |m| m.cost);
+
+                            (response.status(), cost)
+                        }
+                    },
+                }
+            }
+        };
+
+        auth::log_request(
+            &self.db,
+            api_key_id,
+            &publish.topic,
+            "PUBLISH",
+            status,
+            started.elapsed(),
+            cost,
+        )
+        .await;
+
+        Ok(status)
+    }
+}
+
+// The payload is a POST /readings body. sensor_id comes from the topic and
+// may be left out of the payload.
+fn parse_reading(sensor_id: &str, payload: &[u8]) -> Result<ReadingRequest, String> {
+    let mut body: serde_json::Value =
+        serde_json::from_slice(payload).map_err(|_| "Payload is not valid JSON".to_string())?;
+    let fields = body
+        .as_object_mut()
+        .ok_or_else(|| "Payload must be a JSON object".to_string())?;
+
+    match fields.get("sensor_id") {
+        None => {
+            fields.insert("sensor_id".to_string(), sensor_id.into());
+        }
+        Some(id) if id == sensor_id => {}
+        Some(_) => return Err("sensor_id does not match the topic".to_string()),
+    }
+
+    let reading: ReadingRequest = serde_json::from_value(body).map_err(|e| e.to_string())?;
+    Validator::reading(&reading)?;
+
+    Ok(reading)
+}
@@ -0,0 +1,60 @@
+use rand::Rng;
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+// Length of the visible identifier kept in plaintext, e.g. `sk_AbCd1234`.
+pub const PREFIX_LEN: usize = 11;
+
+pub struct HashedSecret {
+    pub prefix: String,
+    pub salt: String,
+    pub hash: String,
+}
+
+pub fn generate_secret(prefix: &str) -> String {
+    const CHARSET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+    const KEY_LEN: usize = 32;
+    let mut rng = rand::rng();
+
+    let key: String = (0..KEY_LEN)
+        .map(|_| {
+            let idx = rng.random_range(0..CHARSET.len());
+            CHARSET[idx] as char
+        })
+        .collect();
+
+    format!("{}_{}", prefix, key)
+}
+
+pub fn prefix_of(secret: &str) -> &str {
+    match secret.char_indices().nth(PREFIX_LEN) {
+        Some((idx, _)) => &secret[..idx],
+        None => secret,
+    }
+}
+
+// Hashes as hex(sha256(salt || secret)); the migrations use the same scheme
+// through pgcrypto, so keep the two in sync.
+pub fn hash_secret(secret: &str) -> HashedSecret {
+    let salt: [u8; 16] = rand::rng().random();
+    let salt = hex::encode(salt);
+    let hash = digest(&salt, secret);
+
+    HashedSecret {
+        prefix: prefix_of(secret).to_string(),
+        salt,
+        hash,
+    }
+}
+
+pub fn verify_secret(secret: &str, salt: &str, expected_hash: &str) -> bool {
+    let hash = digest(salt, secret);
+    hash.as_bytes().ct_eq(expected_hash.as_bytes()).into()
+}
+
+fn digest(salt: &str, secret: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(salt.as_bytes());
+    hasher.update(secret.as_bytes());
+    hex::encode(hasher.finalize())
+}
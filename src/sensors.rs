@@ -0,0 +1,273 @@
+use crate::db::DbPool;
+use crate::models::{ReadingRequest, Sensor};
+use crate::units;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+// What to do with readings from sensors that are not in the registry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnknownSensors {
+    Allow,
+    Register,
+    Reject,
+}
+
+impl UnknownSensors {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "allow" => Some(UnknownSensors::Allow),
+            "register" => Some(UnknownSensors::Register),
+            "reject" => Some(UnknownSensors::Reject),
+            _ => None,
+        }
+    }
+}
+
+// How incoming readings are checked against the sensor registry.
+#[derive(Debug, Clone, Copy)]
+pub struct SensorPolicy {
+    // Readings from registered sensors must use the sensor's unit and stay
+    // within its value range.
+    pub enforce: bool,
+    pub unknown: UnknownSensors,
+}
+
+impl SensorPolicy {
+    fn is_noop(&self) -> bool {
+        !self.enforce && self.unknown == UnknownSensors::Allow
+    }
+}
+
+// Checks each reading against the key's registered sensors, registering
+// unknown ones first if the policy says so. Returns one result per reading,
+// in order, with the same "field: reason" errors as `Validator::reading`.
+pub async fn check_readings(
+    db: &DbPool,
+    api_key_id: Uuid,
+    policy: SensorPolicy,
+    readings: &[&ReadingRequest],
+) -> Result<Vec<Result<(), String>>, sqlx::Error> {
+    if policy.is_noop() || readings.is_empty() {
+        return Ok(vec![Ok(()); readings.len()]);
+    }
+
+    let mut sensor_ids: Vec<&str> = Vec::new();
+    let mut sensor_units: Vec<String> = Vec::new();
+    for reading in readings {
+        if !sensor_ids.contains(&reading.sensor_id.as_str()) {
+            sensor_ids.push(&reading.sensor_id);
+            sensor_units.push(units::normalize(&reading.unit));
+        }
+    }
+
+    // Auto-registered sensors take the unit of their first reading, so
+    // enforcement keeps them consistent from then on.
+    if policy.unknown == UnknownSensors::Register {
+        sqlx::query(
+            r#"
+            INSERT INTO sensors (api_key_id, sensor_id, name, unit)
+            SELECT $1, s.sensor_id, s.sensor_id, s.unit
+            FROM UNNEST($2::text[], $3::text[]) AS s(sensor_id, unit)
+            ON CONFLICT (api_key_id, sensor_id) DO NOTHING
+            "#,
+        )
+        .bind(api_key_id)
+        .bind(&sensor_ids)
+        .bind(&sensor_units)
+        .execute(&**db)
+        .await?;
+    }
+
+    let registered: HashMap<String, Sensor> = sqlx::query_as::<_, Sensor>(
+        "SELECT * FROM sensors WHERE api_key_id = $1 AND sensor_id = ANY($2)",
+    )
+    .bind(api_key_id)
+    .bind(&sensor_ids)
+    .fetch_all(&**db)
+    .await?
+    .into_iter()
+    .map(|s| (s.sensor_id.clone(), s))
+    .collect();
+
+    Ok(readings
+        .iter()
+        .map(|reading| match registered.get(&reading.sensor_id) {
+            None if policy.unknown == UnknownSensors::Reject => Err(format!(
+                "sensor_id: Sensor {} is not registered",
+                reading.sensor_id
+            )),
+            None => Ok(()),
+            Some(_) if !policy.enforce => Ok(()),
+            Some(sensor) => check_reading(sensor, reading),
+        })
+        .collect())
+}
+
+fn check_reading(sensor: &Sensor, reading: &ReadingRequest) -> Result<(), String> {
+    // Compared by canonical symbol, so "C" matches a sensor registered in °C.
+    if let Some(unit) = &sensor.unit
+        && units::normalize(unit) != units::normalize(&reading.unit)
+    {
+        return Err(format!(
+            "unit: Sensor {} reports in {}, not {}",
+            sensor.sensor_id, unit, reading.unit
+        ));
+    }
+
+    if sensor.min_value.is_some_and(|min| reading.value < min)
+        || sensor.max_value.is_some_and(|max| reading.value > max)
+    {
+        return Err(format!(
+            "value: {} is outside the valid range of sensor {}",
+            reading.value, sensor.sensor_id
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::create_api_key;
+    use sqlx::PgPool;
+    use std::sync::Arc;
+
+    fn reading(sensor_id: &str, value: f64, unit: &str) -> ReadingRequest {
+        ReadingRequest {
+            sensor_id: sensor_id.to_string(),
+            value,
+            unit: unit.to_string(),
+            recorded_at: None,
+        }
+    }
+
+    async fn register(db: &DbPool, api_key_id: Uuid, sensor_id: &str, unit: &str) {
+        sqlx::query(
+            r#"
+            INSERT INTO sensors (api_key_id, sensor_id, name, unit, min_value, max_value)
+            VALUES ($1, $2, $2, $3, -40, 60)
+            "#,
+        )
+        .bind(api_key_id)
+        .bind(sensor_id)
+        .bind(unit)
+        .execute(&**db)
+        .await
+        .unwrap();
+    }
+
+    async fn check(
+        db: &DbPool,
+        api_key_id: Uuid,
+        policy: SensorPolicy,
+        readings: &[ReadingRequest],
+    ) -> Vec<Result<(), String>> {
+        let readings: Vec<&ReadingRequest> = readings.iter().collect();
+        check_readings(db, api_key_id, policy, &readings)
+            .await
+            .unwrap()
+    }
+
+    #[sqlx::test]
+    async fn enforced_sensors_check_unit_and_range(pool: PgPool) {
+        let db = Arc::new(pool);
+        let (id, _) = create_api_key(&db).await;
+        register(&db, id, "t1", "°C").await;
+        let policy = SensorPolicy {
+            enforce: true,
+            unknown: UnknownSensors::Allow,
+        };
+
+        let results = check(
+            &db,
+            id,
+            policy,
+            &[
+                reading("t1", 20.0, "C"),
+                reading("t1", 20.0, "%"),
+                reading("t1", 61.0, "°C"),
+                reading("other", 1000.0, "%"),
+            ],
+        )
+        .await;
+
+        assert_eq!(results[0], Ok(()));
+        assert!(results[1].as_ref().unwrap_err().starts_with("unit:"));
+        assert!(results[2].as_ref().unwrap_err().starts_with("value:"));
+        assert_eq!(results[3], Ok(()));
+    }
+
+    #[sqlx::test]
+    async fn registered_sensors_are_not_checked_unless_enforced(pool: PgPool) {
+        let db = Arc::new(pool);
+        let (id, _) = create_api_key(&db).await;
+        register(&db, id, "t1", "°C").await;
+        let policy = SensorPolicy {
+            enforce: false,
+            unknown: UnknownSensors::Reject,
+        };
+
+        let results = check(&db, id, policy, &[reading("t1", 500.0, "%")]).await;
+
+        assert_eq!(results, vec![Ok(())]);
+    }
+
+    #[sqlx::test]
+    async fn unknown_sensors_can_be_rejected(pool: PgPool) {
+        let db = Arc::new(pool);
+        let (id, _) = create_api_key(&db).await;
+        let (other_key, _) = create_api_key(&db).await;
+        // Sensors belong to a key; another key's registration does not count.
+        register(&db, other_key, "t1", "°C").await;
+        let policy = SensorPolicy {
+            enforce: false,
+            unknown: UnknownSensors::Reject,
+        };
+
+        let results = check(&db, id, policy, &[reading("t1", 20.0, "C")]).await;
+
+        assert_eq!(
+            results,
+            vec![Err("sensor_id: Sensor t1 is not registered".to_string())]
+        );
+    }
+
+    #[sqlx::test]
+    async fn registering_unknown_sensors_takes_the_first_reading_unit(pool: PgPool) {
+        let db = Arc::new(pool);
+        let (id, _) = create_api_key(&db).await;
+        let policy = SensorPolicy {
+            enforce: true,
+            unknown: UnknownSensors::Register,
+        };
+
+        let results = check(
+            &db,
+            id,
+            policy,
+            &[reading("new", 20.0, "C"), reading("new", 20.0, "%")],
+        )
+        .await;
+
+        assert_eq!(results[0], Ok(()));
+        assert!(results[1].as_ref().unwrap_err().starts_with("unit:"));
+        let unit: Option<String> = sqlx::query_scalar(
+            "SELECT unit FROM sensors WHERE api_key_id = $1 AND sensor_id = 'new'",
+        )
+        .bind(id)
+        .fetch_one(&*db)
+        .await
+        .unwrap();
+        assert_eq!(unit.as_deref(), Some("°C"));
+    }
+
+    #[test]
+    fn policy_names_are_case_insensitive() {
+        assert_eq!(
+            UnknownSensors::parse("Register"),
+            Some(UnknownSensors::Register)
+        );
+        assert_eq!(UnknownSensors::parse("ignore"), None);
+    }
+}
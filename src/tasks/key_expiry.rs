@@ -0,0 +1,81 @@
+use crate::db::DbPool;
+use std::time::Duration;
+
+// Periodically deactivates keys whose expires_at has passed. Validation
+// already rejects expired keys on its own; this keeps is_active and the
+// admin listings truthful for keys that are never used again.
+pub fn spawn_key_expiry_sweeper(db: DbPool, every: Duration) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(every);
+
+        loop {
+            interval.tick().await;
+
+            let result = deactivate_expired_keys(&db).await;
+
+            match result {
+                Ok(count) if count > 0 => {
+                    tracing::info!("Deactivated {} expired API key(s)", count);
+                }
+                Ok(_) => {}
+                Err(e) => tracing::error!("Failed to sweep expired API keys: {:?}", e),
+            }
+        }
+    });
+}
+
+pub async fn deactivate_expired_keys(db: &DbPool) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+        UPDATE api_keys
+        SET is_active = false
+        WHERE is_active = true
+            AND expires_at IS NOT NULL
+            AND expires_at <= NOW()
+        "#,
+    )
+    .execute(&**db)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::create_api_key;
+    use sqlx::PgPool;
+    use std::sync::Arc;
+
+    #[sqlx::test]
+    async fn deactivates_only_expired_keys(pool: PgPool) {
+        let db = Arc::new(pool);
+        let (expired, _) = create_api_key(&db).await;
+        let (current, _) = create_api_key(&db).await;
+        let (open_ended, _) = create_api_key(&db).await;
+
+        sqlx::query("UPDATE api_keys SET expires_at = NOW() - INTERVAL '1 minute' WHERE id = $1")
+            .bind(expired)
+            .execute(&*db)
+            .await
+            .unwrap();
+        sqlx::query("UPDATE api_keys SET expires_at = NOW() + INTERVAL '1 hour' WHERE id = $1")
+            .bind(current)
+            .execute(&*db)
+            .await
+            .unwrap();
+
+        assert_eq!(deactivate_expired_keys(&db).await.unwrap(), 1);
+        assert_eq!(deactivate_expired_keys(&db).await.unwrap(), 0);
+
+        for (id, active) in [(expired, false), (current, true), (open_ended, true)] {
+            let is_active: bool =
+                sqlx::query_scalar("SELECT is_active FROM api_keys WHERE id = $1")
+                    .bind(id)
+                    .fetch_one(&*db)
+                    .await
+                    .unwrap();
+            assert_eq!(is_active, active);
+        }
+    }
+}
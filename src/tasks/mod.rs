@@ -0,0 +1,4 @@
+pub mod key_expiry;
+pub mod rate_limit_eviction;
+pub mod readings_listener;
+pub mod webhook_delivery;
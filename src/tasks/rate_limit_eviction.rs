@@ -0,0 +1,20 @@
+use crate::middleware::rate_limiter::RateLimiter;
+use std::time::Duration;
+
+// Periodically drops rate limit state for keys that have gone idle, so the
+// in-memory map (or the rate_limit_state table) only holds recently active
+// keys instead of every key ever seen.
+pub fn spawn_rate_limit_evictor(limiter: RateLimiter, every: Duration) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(every);
+
+        loop {
+            interval.tick().await;
+
+            let evicted = limiter.evict_idle().await;
+            if evicted > 0 {
+                tracing::debug!("Evicted rate limit state for {} idle key(s)", evicted);
+            }
+        }
+    });
+}
@@ -0,0 +1,50 @@
+use crate::feed::{self, ReadingFeed};
+use crate::models::Reading;
+use sqlx::postgres::PgListener;
+use std::sync::Arc;
+use std::time::Duration;
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+// With the postgres feed backend, passes readings announced by any instance
+// on to this instance's streams. Readings announced while the listener is
+// reconnecting are missed. The listener holds its connection for as long as
+// it runs, so it opens its own rather than taking one from the shared pool.
+pub fn spawn_readings_listener(database_url: String, feed: ReadingFeed) {
+    tokio::spawn(async move {
+        loop {
+            let mut listener = match PgListener::connect(&database_url).await {
+                Ok(listener) => listener,
+                Err(e) => {
+                    tracing::error!("Failed to connect readings listener: {:?}", e);
+                    tokio::time::sleep(RECONNECT_DELAY).await;
+                    continue;
+                }
+            };
+
+            if let Err(e) = listener.listen(feed::NOTIFY_CHANNEL).await {
+                tracing::error!("Failed to listen for readings: {:?}", e);
+                tokio::time::sleep(RECONNECT_DELAY).await;
+                continue;
+            }
+
+            loop {
+                match listener.recv().await {
+                    Ok(notification) => {
+                        match serde_json::from_str::<Reading>(notification.payload()) {
+                            Ok(reading) => feed.broadcast(Arc::new(reading)),
+                            Err(e) => {
+                                tracing::warn!("Ignoring malformed reading notification: {}", e)
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        tracing::error!("Readings listener lost its connection: {:?}", e);
+                        tokio::time::sleep(RECONNECT_DELAY).await;
+                        break;
+                    }
+                }
+            }
+        }
+    });
+}
@@ -0,0 +1,184 @@
+use crate::db::DbPool;
+use crate::webhooks::{self, Webhooks};
+use chrono::Utc;
+use sqlx::FromRow;
+use std::time::Duration;
+use tokio::task::JoinSet;
+use uuid::Uuid;
+
+// Deliveries claimed per round.
+const BATCH_SIZE: i64 = 50;
+// Retry delays double from the first to the last, e.g. 30s, 1m, 2m, ...
+const FIRST_RETRY_DELAY_SECONDS: i64 = 30;
+const MAX_RETRY_DELAY_SECONDS: i64 = 60 * 60;
+const MAX_ERROR_LENGTH: usize = 500;
+
+#[derive(Debug, Clone, Copy)]
+pub struct DeliveryConfig {
+    pub max_attempts: i32,
+    pub timeout: Duration,
+    pub poll_interval: Duration,
+}
+
+#[derive(Debug, FromRow)]
+struct DueDelivery {
+    id: Uuid,
+    attempts: i32,
+    url: String,
+    secret: String,
+    event_id: Uuid,
+    event_type: String,
+    payload: String,
+}
+
+// Sends pending webhook deliveries as soon as they are queued, and retries
+// failed ones with exponential backoff until they succeed or run out of
+// attempts. Replicas can run this side by side: each delivery is leased to
+// one of them while it is being sent.
+pub fn spawn_webhook_deliverer(db: DbPool, webhooks: Webhooks, config: DeliveryConfig) {
+    let client = reqwest::Client::builder()
+        .timeout(config.timeout)
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .expect("webhook HTTP client should build");
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(config.poll_interval);
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = webhooks.wait_for_deliveries() => {}
+            }
+
+            loop {
+                match deliver_due(&db, &client, config).await {
+                    Ok(sent) if sent == BATCH_SIZE as usize => continue,
+                    Ok(_) => break,
+                    Err(e) => {
+                        tracing::error!("Failed to load due webhook deliveries: {:?}", e);
+                        break;
+                    }
+                }
+            }
+        }
+    });
+}
+
+async fn deliver_due(
+    db: &DbPool,
+    client: &reqwest::Client,
+    config: DeliveryConfig,
+) -> Result<usize, sqlx::Error> {
+    // Pushing next_attempt_at past the request timeout leases the delivery;
+    // if this process dies mid-send, it becomes due again afterwards.
+    let lease_seconds = config.timeout.as_secs_f64() + 30.0;
+
+    let due = sqlx::query_as::<_, DueDelivery>(
+        r#"
+        WITH due AS (
+            SELECT d.id FROM webhook_deliveries d
+            JOIN webhooks w ON w.id = d.webhook_id
+            WHERE d.status = 'pending' AND d.next_attempt_at <= NOW() AND w.is_active
+            ORDER BY d.next_attempt_at
+            LIMIT $1
+            FOR UPDATE OF d SKIP LOCKED
+        )
+        UPDATE webhook_deliveries d
+        SET attempts = d.attempts + 1,
+            last_attempt_at = NOW(),
+            next_attempt_at = NOW() + make_interval(secs => $2)
+        FROM due, webhooks w, webhook_events e
+        WHERE d.id = due.id AND w.id = d.webhook_id AND e.id = d.event_id
+        RETURNING d.id, d.attempts, w.url, w.secret, e.id AS event_id, e.event_type, e.payload
+        "#,
+    )
+    .bind(BATCH_SIZE)
+    .bind(lease_seconds)
+    .fetch_all(&**db)
+    .await?;
+
+    let count = due.len();
+    let mut sends = JoinSet::new();
+    for delivery in due {
+        let db = db.clone();
+        let client = client.clone();
+        sends.spawn(async move { deliver(&db, &client, delivery, config.max_attempts).await });
+    }
+    sends.join_all().await;
+
+    Ok(count)
+}
+
+async fn deliver(db: &DbPool, client: &reqwest::Client, delivery: DueDelivery, max_attempts: i32) {
+    let timestamp = Utc::now().timestamp();
+    let signature = webhooks::sign(&delivery.secret, timestamp, delivery.payload.as_bytes());
+
+    let result = client
+        .post(&delivery.url)
+        .header("content-type", "application/json")
+        .header("x-webhook-id", delivery.event_id.to_string())
+        .header("x-webhook-event", &delivery.event_type)
+        .header("x-webhook-delivery", delivery.id.to_string())
+        .header(
+            "x-webhook-signature",
+            format!("t={},v1={}", timestamp, signature),
+        )
+        .body(delivery.payload)
+        .send()
+        .await;
+
+    let (response_status, error) = match result {
+        Ok(res) if res.status().is_success() => (Some(res.status().as_u16() as i16), None),
+        Ok(res) => (
+            Some(res.status().as_u16() as i16),
+            Some(format!("Receiver responded with {}", res.status())),
+        ),
+        Err(e) => (None, Some(e.to_string())),
+    };
+
+    let update = match error {
+        None => sqlx::query(
+            r#"
+            UPDATE webhook_deliveries
+            SET status = 'delivered', delivered_at = NOW(), response_status = $2, last_error = NULL
+            WHERE id = $1
+            "#,
+        )
+        .bind(delivery.id)
+        .bind(response_status),
+        Some(error) => {
+            tracing::warn!(
+                "Webhook delivery {} failed (attempt {}): {}",
+                delivery.id,
+                delivery.attempts,
+                error
+            );
+
+            sqlx::query(
+                r#"
+                UPDATE webhook_deliveries
+                SET status = CASE WHEN attempts >= $4 THEN 'failed' ELSE status END,
+                    next_attempt_at = NOW() + make_interval(secs => $5),
+                    response_status = $2,
+                    last_error = $3
+                WHERE id = $1
+                "#,
+            )
+            .bind(delivery.id)
+            .bind(response_status)
+            .bind(error.chars().take(MAX_ERROR_LENGTH).collect::<String>())
+            .bind(max_attempts)
+            .bind(retry_delay_seconds(delivery.attempts) as f64)
+        }
+    };
+
+    if let Err(e) = update.execute(&**db).await {
+        tracing::error!("Failed to record webhook delivery {}: {:?}", delivery.id, e);
+    }
+}
+
+fn retry_delay_seconds(attempts: i32) -> i64 {
+    let doublings = attempts.clamp(1, 20) - 1;
+    (FIRST_RETRY_DELAY_SECONDS << doublings).min(MAX_RETRY_DELAY_SECONDS)
+}
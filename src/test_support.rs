@@ -0,0 +1,107 @@
+use crate::db::DbPool;
+use crate::middleware::auth::{AuthContext, validate_api_key};
+use crate::middleware::metering::{BillableStatuses, Metering};
+use crate::middleware::rate_limiter::{RateLimitBackend, RateLimiter};
+use crate::models::Endpoint;
+use crate::secrets;
+use crate::webhooks::Webhooks;
+use serde_json::Value;
+use sqlx::PgPool;
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+use warp::http::StatusCode;
+use warp::{Filter, Reply};
+
+// Helpers for the database-backed tests. Each #[sqlx::test] gets its own
+// freshly migrated database.
+
+// Inserts an active key with the column defaults and returns its id and
+// secret.
+pub async fn create_api_key(db: &DbPool) -> (Uuid, String) {
+    let key = secrets::generate_secret("sk");
+    let hashed = secrets::hash_secret(&key);
+
+    let id = sqlx::query_scalar(
+        r#"
+        INSERT INTO api_keys (key_prefix, key_salt, key_hash, name)
+        VALUES ($1, $2, $3, 'test key')
+        RETURNING id
+        "#,
+    )
+    .bind(&hashed.prefix)
+    .bind(&hashed.salt)
+    .bind(&hashed.hash)
+    .fetch_one(&**db)
+    .await
+    .unwrap();
+
+    (id, key)
+}
+
+// Metering as configured by default in main, with the memory rate limiter.
+pub fn metering(db: &DbPool) -> Metering {
+    Metering::new(
+        RateLimiter::new(RateLimitBackend::Memory, db.clone(), 100, false),
+        4,
+        BillableStatuses::parse("2xx").unwrap(),
+        Webhooks::new(db.clone()),
+    )
+}
+
+// Runs `key` through the auth filter for `endpoint`, for calling handlers
+// directly.
+pub async fn authenticate(db: &DbPool, key: &str, endpoint: Endpoint) -> AuthContext {
+    validate_api_key(Some(key.to_string()), db.clone(), metering(db), endpoint)
+        .await
+        .unwrap_or_else(|_| panic!("{} is not authorized for {:?}", key, endpoint))
+}
+
+// The status and JSON body of a handler's reply.
+pub async fn into_json(reply: impl Reply) -> (StatusCode, Value) {
+    let response = Arc::new(Mutex::new(Some(reply.into_response())));
+    let filter = warp::any().map(move || response.lock().unwrap().take().unwrap());
+    let response = warp::test::request().reply(&filter).await;
+
+    let body = serde_json::from_slice(response.body()).unwrap_or(Value::Null);
+
+    (response.status(), body)
+}
+
+// Applies the migrations whose version satisfies `include`, in order. Lets a
+// test with `migrations = false` seed data between two migrations.
+pub async fn apply_migrations(pool: &PgPool, include: impl Fn(i64) -> bool) {
+    for migration in sqlx::migrate!("./migrations").iter() {
+        if include(migration.version) {
+            sqlx::raw_sql(&migration.sql).execute(pool).await.unwrap();
+        }
+    }
+}
+
+// Subscribes a global webhook to `events`, so emitted events get stored.
+pub async fn subscribe_webhook(db: &DbPool, events: &[&str]) {
+    sqlx::query("INSERT INTO webhooks (url, secret, events) VALUES ('http://127.0.0.1:9/hook', 'whsec_test', $1)")
+        .bind(events)
+        .execute(&**db)
+        .await
+        .unwrap();
+}
+
+// Events are stored in the background; waits briefly for `expected` of them.
+pub async fn wait_for_events(db: &DbPool, event_type: &str, expected: i64) -> i64 {
+    let count = || async {
+        sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM webhook_events WHERE event_type = $1")
+            .bind(event_type)
+            .fetch_one(&**db)
+            .await
+            .unwrap()
+    };
+
+    for _ in 0..50 {
+        if count().await == expected {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+
+    count().await
+}
@@ -0,0 +1,286 @@
+// Units the server understands, with the spellings devices commonly send.
+// Readings are stored under the canonical symbol; anything not listed here
+// is stored as sent and never converted.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dimension {
+    Temperature,
+    Pressure,
+    Length,
+    Ratio,
+}
+
+#[derive(Debug)]
+pub struct Unit {
+    pub symbol: &'static str,
+    pub dimension: Dimension,
+    // value_in_base_unit = value * scale + offset. The base units are °C,
+    // Pa, m and %.
+    scale: f64,
+    offset: f64,
+    // Lowercase; matched case-insensitively.
+    aliases: &'static [&'static str],
+}
+
+const CATALOG: &[Unit] = &[
+    Unit {
+        symbol: "°C",
+        dimension: Dimension::Temperature,
+        scale: 1.0,
+        offset: 0.0,
+        aliases: &["c", "degc", "deg c", "celsius"],
+    },
+    Unit {
+        symbol: "°F",
+        dimension: Dimension::Temperature,
+        scale: 5.0 / 9.0,
+        offset: -32.0 * 5.0 / 9.0,
+        aliases: &["f", "degf", "deg f", "fahrenheit"],
+    },
+    Unit {
+        symbol: "K",
+        dimension: Dimension::Temperature,
+        scale: 1.0,
+        offset: -273.15,
+        aliases: &["kelvin"],
+    },
+    Unit {
+        symbol: "Pa",
+        dimension: Dimension::Pressure,
+        scale: 1.0,
+        offset: 0.0,
+        aliases: &["pascal", "pascals"],
+    },
+    Unit {
+        symbol: "hPa",
+        dimension: Dimension::Pressure,
+        scale: 100.0,
+        offset: 0.0,
+        aliases: &[
+            "hectopascal",
+            "hectopascals",
+            "mbar",
+            "millibar",
+            "millibars",
+        ],
+    },
+    Unit {
+        symbol: "kPa",
+        dimension: Dimension::Pressure,
+        scale: 1_000.0,
+        offset: 0.0,
+        aliases: &["kilopascal", "kilopascals"],
+    },
+    Unit {
+        symbol: "bar",
+        dimension: Dimension::Pressure,
+        scale: 100_000.0,
+        offset: 0.0,
+        aliases: &["bars"],
+    },
+    Unit {
+        symbol: "psi",
+        dimension: Dimension::Pressure,
+        scale: 6_894.757_293_168,
+        offset: 0.0,
+        aliases: &[],
+    },
+    Unit {
+        symbol: "atm",
+        dimension: Dimension::Pressure,
+        scale: 101_325.0,
+        offset: 0.0,
+        aliases: &["atmosphere", "atmospheres"],
+    },
+    Unit {
+        symbol: "mmHg",
+        dimension: Dimension::Pressure,
+        scale: 133.322_387_415,
+        offset: 0.0,
+        aliases: &[],
+    },
+    Unit {
+        symbol: "mm",
+        dimension: Dimension::Length,
+        scale: 0.001,
+        offset: 0.0,
+        aliases: &["millimeter", "millimeters", "millimetre", "millimetres"],
+    },
+    Unit {
+        symbol: "cm",
+        dimension: Dimension::Length,
+        scale: 0.01,
+        offset: 0.0,
+        aliases: &["centimeter", "centimeters", "centimetre", "centimetres"],
+    },
+    Unit {
+        symbol: "m",
+        dimension: Dimension::Length,
+        scale: 1.0,
+        offset: 0.0,
+        aliases: &["meter", "meters", "metre", "metres"],
+    },
+    Unit {
+        symbol: "km",
+        dimension: Dimension::Length,
+        scale: 1_000.0,
+        offset: 0.0,
+        aliases: &["kilometer", "kilometers", "kilometre", "kilometres"],
+    },
+    Unit {
+        symbol: "in",
+        dimension: Dimension::Length,
+        scale: 0.0254,
+        offset: 0.0,
+        aliases: &["inch", "inches"],
+    },
+    Unit {
+        symbol: "ft",
+        dimension: Dimension::Length,
+        scale: 0.3048,
+        offset: 0.0,
+        aliases: &["foot", "feet"],
+    },
+    Unit {
+        symbol: "%",
+        dimension: Dimension::Ratio,
+        scale: 1.0,
+        offset: 0.0,
+        aliases: &["percent", "pct"],
+    },
+    Unit {
+        symbol: "ppm",
+        dimension: Dimension::Ratio,
+        scale: 0.0001,
+        offset: 0.0,
+        aliases: &[],
+    },
+];
+
+impl Unit {
+    // Only spaces are trimmed: they are the one blank Validator::unit allows,
+    // and all that the raw_unit migration strips when it backfills symbols.
+    pub fn lookup(s: &str) -> Option<&'static Unit> {
+        let s = s.trim_matches(' ');
+        if let Some(unit) = CATALOG.iter().find(|u| u.symbol == s) {
+            return Some(unit);
+        }
+
+        let lower = s.to_lowercase();
+        CATALOG
+            .iter()
+            .find(|u| u.symbol.to_lowercase() == lower || u.aliases.contains(&lower.as_str()))
+    }
+
+    // The (scale, offset) that takes a value in this unit to `target`, if
+    // both measure the same thing.
+    pub fn conversion_to(&self, target: &Unit) -> Option<(f64, f64)> {
+        if self.dimension != target.dimension {
+            return None;
+        }
+
+        Some((
+            self.scale / target.scale,
+            (self.offset - target.offset) / target.scale,
+        ))
+    }
+
+    // Rounded to 9 decimal places, so 100 °C comes out as 212 °F rather
+    // than 211.99999999999997.
+    pub fn convert(&self, value: f64, target: &Unit) -> Option<f64> {
+        self.conversion_to(target)
+            .map(|(scale, offset)| ((value * scale + offset) * 1e9).round() / 1e9)
+    }
+
+    // Every unit that converts to `self`, by canonical symbol, along with its
+    // (scale, offset). Stored readings hold canonical symbols for known
+    // units, so this is all SQL needs to convert them.
+    pub fn conversions_from_all(&self) -> Vec<(&'static str, f64, f64)> {
+        CATALOG
+            .iter()
+            .filter_map(|u| {
+                u.conversion_to(self)
+                    .map(|(scale, offset)| (u.symbol, scale, offset))
+            })
+            .collect()
+    }
+}
+
+// The canonical symbol for a known unit, otherwise the unit as sent.
+pub fn normalize(unit: &str) -> String {
+    match Unit::lookup(unit) {
+        Some(u) => u.symbol.to_string(),
+        None => unit.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::apply_migrations;
+    use sqlx::PgPool;
+
+    const RAW_UNIT_MIGRATION: i64 = 20250826100000;
+
+    #[test]
+    fn lookup_ignores_case_and_surrounding_spaces() {
+        assert_eq!(normalize("  Celsius "), "°C");
+        assert_eq!(normalize("HPA"), "hPa");
+        assert_eq!(normalize("\tC"), "\tC");
+        assert_eq!(normalize("furlong"), "furlong");
+    }
+
+    // The raw_unit migration backfills canonical symbols from its own copy of
+    // the catalog; run it over every spelling and compare with `normalize`.
+    #[sqlx::test(migrations = false)]
+    async fn migration_backfill_matches_normalize(pool: PgPool) {
+        apply_migrations(&pool, |v| v < RAW_UNIT_MIGRATION).await;
+        let api_key_id: uuid::Uuid = sqlx::query_scalar(
+            r#"
+            INSERT INTO api_keys (key_prefix, key_salt, key_hash, name)
+            VALUES ('sk_units', 'salt', 'hash', 'units')
+            RETURNING id
+            "#,
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+
+        let mut raw_units: Vec<String> = vec![
+            "furlong".into(),
+            "\tC".into(),
+            "C\n".into(),
+            "".into(),
+            "  ".into(),
+        ];
+        for unit in CATALOG {
+            for name in std::iter::once(unit.symbol).chain(unit.aliases.iter().copied()) {
+                raw_units.push(name.to_string());
+                raw_units.push(name.to_uppercase());
+                raw_units.push(format!("  {} ", name));
+            }
+        }
+
+        sqlx::query(
+            r#"
+            INSERT INTO readings (id, api_key_id, sensor_id, value, unit)
+            SELECT gen_random_uuid(), $1, 's1', 0, u FROM UNNEST($2::text[]) AS u
+            "#,
+        )
+        .bind(api_key_id)
+        .bind(&raw_units)
+        .execute(&pool)
+        .await
+        .unwrap();
+        apply_migrations(&pool, |v| v >= RAW_UNIT_MIGRATION).await;
+
+        let migrated: Vec<(String, String)> = sqlx::query_as("SELECT raw_unit, unit FROM readings")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(migrated.len(), raw_units.len());
+        for (raw_unit, unit) in migrated {
+            assert_eq!(unit, normalize(&raw_unit), "raw unit {:?}", raw_unit);
+        }
+    }
+}
@@ -0,0 +1,210 @@
+use crate::db::DbPool;
+use crate::models::{Alert, ApiKey, Endpoint, WebhookEvent};
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+use uuid::Uuid;
+
+// A key that keeps hitting its rate limit is reported at most this often.
+const RATE_LIMIT_REPORT_INTERVAL: Duration = Duration::from_secs(60);
+const MAX_TRACKED_RATE_LIMITED_KEYS: usize = 10_000;
+
+// Queues events for the webhook delivery task. Events are written to the
+// database in the background, so emitting one never slows down or fails the
+// request that caused it.
+#[derive(Clone)]
+pub struct Webhooks {
+    db: DbPool,
+    queued: Arc<Notify>,
+    rate_limit_reported: Arc<Mutex<HashMap<Uuid, Instant>>>,
+}
+
+impl Webhooks {
+    pub fn new(db: DbPool) -> Self {
+        Self {
+            db,
+            queued: Arc::new(Notify::new()),
+            rate_limit_reported: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    // Resolves once new deliveries may be waiting.
+    pub async fn wait_for_deliveries(&self) {
+        self.queued.notified().await;
+    }
+
+    pub fn wake_deliverer(&self) {
+        self.queued.notify_one();
+    }
+
+    // Creates one pending delivery per active webhook subscribed to the
+    // event. With a `dedupe_key`, an event that was already stored under the
+    // same key is dropped.
+    pub fn emit(
+        &self,
+        event: WebhookEvent,
+        api_key_id: Option<Uuid>,
+        data: serde_json::Value,
+        dedupe_key: Option<String>,
+    ) {
+        let this = self.clone();
+
+        tokio::spawn(async move {
+            let id = Uuid::new_v4();
+            let payload = serde_json::json!({
+                "id": id,
+                "type": event.as_str(),
+                "created_at": Utc::now(),
+                "api_key_id": api_key_id,
+                "data": data,
+            });
+
+            let result = sqlx::query(
+                r#"
+                WITH targets AS (
+                    SELECT id FROM webhooks
+                    WHERE is_active
+                        AND $2 = ANY(events)
+                        AND (api_key_id IS NULL OR api_key_id = $3)
+                ),
+                event AS (
+                    INSERT INTO webhook_events (id, event_type, api_key_id, payload, dedupe_key)
+                    SELECT $1, $2, $3, $4, $5
+                    WHERE EXISTS (SELECT 1 FROM targets)
+                    ON CONFLICT (dedupe_key) DO NOTHING
+                    RETURNING id
+                )
+                INSERT INTO webhook_deliveries (webhook_id, event_id)
+                SELECT targets.id, event.id FROM targets, event
+                "#,
+            )
+            .bind(id)
+            .bind(event.as_str())
+            .bind(api_key_id)
+            .bind(payload.to_string())
+            .bind(dedupe_key)
+            .execute(&*this.db)
+            .await;
+
+            match result {
+                Ok(res) if res.rows_affected() > 0 => this.wake_deliverer(),
+                Ok(_) => {}
+                Err(e) => tracing::error!("Failed to queue {} webhook: {:?}", event.as_str(), e),
+            }
+        });
+    }
+
+    pub fn key_event(&self, event: WebhookEvent, api_key: &ApiKey) {
+        self.emit(
+            event,
+            Some(api_key.id),
+            serde_json::json!({
+                "id": api_key.id,
+                "key_prefix": api_key.key_prefix,
+                "name": api_key.name,
+                "previous_key_prefix": api_key.previous_key_prefix,
+                "previous_key_expires_at": api_key.previous_key_expires_at,
+            }),
+            None,
+        );
+    }
+
+    // Reports the key's usage crossing 80% and 100% of its quota, given its
+    // usage before and after a committed charge. Only billed requests get
+    // here, so refunds cannot trigger a crossing; the dedupe key still keeps
+    // concurrent commits from announcing the same one twice in a period.
+    pub fn quota_charged(&self, api_key: &ApiKey, usage_before: i32) {
+        let Some(limit) = api_key.quota_limit else {
+            return;
+        };
+
+        let warning = (i64::from(limit) * 80 + 99) / 100;
+        let thresholds = [
+            (WebhookEvent::QuotaWarning, warning),
+            (WebhookEvent::QuotaExhausted, i64::from(limit)),
+        ];
+
+        for (event, threshold) in thresholds {
+            if i64::from(usage_before) < threshold && i64::from(api_key.usage_count) >= threshold {
+                self.emit(
+                    event,
+                    Some(api_key.id),
+                    serde_json::json!({
+                        "key_prefix": api_key.key_prefix,
+                        "name": api_key.name,
+                        "usage_count": api_key.usage_count,
+                        "quota_limit": limit,
+                        "quota_period": api_key.quota_period,
+                        "period_started_at": api_key.period_started_at,
+                    }),
+                    Some(format!(
+                        "{}:{}:{}",
+                        event.as_str(),
+                        api_key.id,
+                        api_key.period_started_at.timestamp_micros()
+                    )),
+                );
+            }
+        }
+    }
+
+    pub fn rate_limited(&self, api_key: &ApiKey, endpoint: Endpoint) {
+        {
+            let now = Instant::now();
+            let mut reported = self
+                .rate_limit_reported
+                .lock()
+                .unwrap_or_else(|e| e.into_inner());
+
+            if reported
+                .get(&api_key.id)
+                .is_some_and(|at| now.duration_since(*at) < RATE_LIMIT_REPORT_INTERVAL)
+            {
+                return;
+            }
+
+            if reported.len() >= MAX_TRACKED_RATE_LIMITED_KEYS {
+                reported.retain(|_, at| now.duration_since(*at) < RATE_LIMIT_REPORT_INTERVAL);
+            }
+            reported.insert(api_key.id, now);
+        }
+
+        self.emit(
+            WebhookEvent::RateLimitExceeded,
+            Some(api_key.id),
+            serde_json::json!({
+                "key_prefix": api_key.key_prefix,
+                "name": api_key.name,
+                "method": endpoint.method(),
+                "route": endpoint.route(),
+                "rate_limit_per_minute": api_key.rate_limit_per_minute,
+                "rate_limit_burst": api_key.burst(),
+            }),
+            None,
+        );
+    }
+
+    pub fn alert_event(&self, event: WebhookEvent, alert: &Alert) {
+        match serde_json::to_value(alert) {
+            Ok(data) => self.emit(event, Some(alert.api_key_id), data, None),
+            Err(e) => tracing::error!("Failed to serialize alert {}: {:?}", alert.id, e),
+        }
+    }
+}
+
+// Hex HMAC-SHA256 of "{timestamp}.{body}", sent as
+// `X-Webhook-Signature: t={timestamp},v1={signature}`. Including the
+// timestamp lets receivers reject replayed requests.
+pub fn sign(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+
+    hex::encode(mac.finalize().into_bytes())
+}